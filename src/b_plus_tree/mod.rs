@@ -0,0 +1,607 @@
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+use core::fmt::Debug;
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+
+mod validate;
+
+struct Node<T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    keys: Vec<T>,
+    // Only leaf nodes hold values, internal nodes only hold separator keys.
+    values: Vec<V>,
+    children: Vec<Rc<RefCell<Node<T, V>>>>,
+    // Leaves are linked left-to-right, internal nodes never set this.
+    next: Option<Rc<RefCell<Node<T, V>>>>,
+}
+
+// Separator key and new right node handed to the parent after a split
+type Split<T, V> = Option<(T, Rc<RefCell<Node<T, V>>>)>;
+
+/// BPlusTree
+///
+/// A B+ tree variant of `BTree`. Internal nodes only hold separator keys and
+/// links to their children, all key-value pairs are stored in the leaves. The
+/// leaves are linked from left to right, so ordered and range scans walk the
+/// leaf chain and never revisit internal nodes.
+#[derive(Debug)]
+pub struct BPlusTree<T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    max_keys_per_node: usize,
+    len: usize,
+    root: Rc<RefCell<Node<T, V>>>,
+}
+
+/// Iterator over the entries of a `BPlusTree` in key order
+///
+/// Created by `BPlusTree::iter` and `BPlusTree::range`. Yields clones of the
+/// keys and values, following the `next` links between the leaves. Borrows
+/// the tree, so it can not change while it is iterated over.
+pub struct Range<'a, T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    leaf: Option<Rc<RefCell<Node<T, V>>>>,
+    index: usize,
+    end: Bound<T>,
+    tree: PhantomData<&'a BPlusTree<T, V>>,
+}
+
+impl<T, V> BPlusTree<T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    /// Create a tree without any keys
+    ///
+    /// Returns an Err if `max_keys_per_node` is below 3.
+    pub fn new(max_keys_per_node: usize) -> Result<Self, &'static str> {
+        if max_keys_per_node < 3 {
+            return Err("max_keys_per_node must be at least 3");
+        }
+        Ok(BPlusTree {
+            max_keys_per_node,
+            len: 0,
+            root: Rc::new(RefCell::new(Node::new_leaf())),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Returns an Err when the key already exists
+    pub fn insert(&mut self, key: T, value: V) -> Result<(), &'static str> {
+        let split =
+            BPlusTree::traverse_insert(Rc::clone(&self.root), key, value, self.max_keys_per_node)?;
+        if let Some((separator, right_node)) = split {
+            // The root was split, so the tree grows one level taller
+            let new_root = Node {
+                keys: vec![separator],
+                values: Vec::new(),
+                children: vec![Rc::clone(&self.root), right_node],
+                next: None,
+            };
+            self.root = Rc::new(RefCell::new(new_root));
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    // Returns an Err when the key does not exist
+    pub fn remove(&mut self, key: T) -> Result<V, &'static str> {
+        let min_keys = self.min_keys_per_node();
+        let value = BPlusTree::traverse_remove(Rc::clone(&self.root), &key, min_keys)
+            .ok_or("Key not found")?;
+        let new_root = {
+            let borrowed_root = self.root.borrow();
+            if borrowed_root.keys.is_empty() && !borrowed_root.children.is_empty() {
+                Some(Rc::clone(&borrowed_root.children[0]))
+            } else {
+                None
+            }
+        };
+        if let Some(node) = new_root {
+            self.root = node;
+        }
+        self.len -= 1;
+        Ok(value)
+    }
+
+    /// Minimum number of keys in any node but the root, below which removal
+    /// borrows from or merges with a sibling
+    fn min_keys_per_node(&self) -> usize {
+        self.max_keys_per_node / 2
+    }
+
+    pub fn exists(&self, key: T) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: T) -> Option<V> {
+        let leaf = BPlusTree::find_leaf(Rc::clone(&self.root), &key);
+        let borrowed_leaf = leaf.borrow();
+        borrowed_leaf
+            .keys
+            .iter()
+            .position(|current_key| *current_key == key)
+            .map(|i| borrowed_leaf.values[i].clone())
+    }
+
+    /// Iterate over all entries in key order
+    pub fn iter(&self) -> Range<'_, T, V> {
+        self.range(..)
+    }
+
+    /// Iterate over the entries with keys inside `range`, in key order
+    ///
+    /// Descends the tree once to find the leaf holding the start of the range,
+    /// after which the scan only follows the leaf chain.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Range<'_, T, V> {
+        let end = range.end_bound().cloned();
+        let (leaf, index) = match range.start_bound() {
+            Bound::Unbounded => (BPlusTree::first_leaf(Rc::clone(&self.root)), 0),
+            Bound::Included(start) => {
+                let leaf = BPlusTree::find_leaf(Rc::clone(&self.root), start);
+                let index = leaf.borrow().keys.partition_point(|key| key < start);
+                (leaf, index)
+            }
+            Bound::Excluded(start) => {
+                let leaf = BPlusTree::find_leaf(Rc::clone(&self.root), start);
+                let index = leaf.borrow().keys.partition_point(|key| key <= start);
+                (leaf, index)
+            }
+        };
+        Range {
+            leaf: Some(leaf),
+            index,
+            end,
+            tree: PhantomData,
+        }
+    }
+
+    /// Find the child of an internal node that covers `key`
+    ///
+    /// Child `i` holds the keys that are at least `keys[i - 1]` and smaller than
+    /// `keys[i]`, so the child index is the number of separators <= `key`.
+    fn child_index(node: &Node<T, V>, key: &T) -> usize {
+        node.keys.partition_point(|current_key| current_key <= key)
+    }
+
+    fn find_leaf(current_node: Rc<RefCell<Node<T, V>>>, key: &T) -> Rc<RefCell<Node<T, V>>> {
+        let mut node = current_node;
+        loop {
+            let child = {
+                let borrowed_node = node.borrow();
+                if borrowed_node.children.is_empty() {
+                    break;
+                }
+                let i = BPlusTree::child_index(&borrowed_node, key);
+                Rc::clone(&borrowed_node.children[i])
+            };
+            node = child;
+        }
+        node
+    }
+
+    fn first_leaf(current_node: Rc<RefCell<Node<T, V>>>) -> Rc<RefCell<Node<T, V>>> {
+        let mut node = current_node;
+        loop {
+            let child = match node.borrow().children.first() {
+                Some(child) => Rc::clone(child),
+                None => break,
+            };
+            node = child;
+        }
+        node
+    }
+
+    /// Insert the key-value pair into the leaf covering `key`
+    ///
+    /// Recursively descends to the leaf that should hold the key. When a node
+    /// goes over the maximum number of keys it is split, and the separator key
+    /// and new right node are returned to the parent, which inserts them and
+    /// might have to split in turn.
+    fn traverse_insert(
+        current_node: Rc<RefCell<Node<T, V>>>,
+        key: T,
+        value: V,
+        max_keys_per_node: usize,
+    ) -> Result<Split<T, V>, &'static str> {
+        let child_to_traverse = {
+            let mut borrowed_node = current_node.borrow_mut();
+            if borrowed_node.children.is_empty() {
+                let i = borrowed_node
+                    .keys
+                    .partition_point(|current_key| *current_key < key);
+                if i < borrowed_node.keys.len() && borrowed_node.keys[i] == key {
+                    return Err("Key already exists");
+                }
+                borrowed_node.keys.insert(i, key);
+                borrowed_node.values.insert(i, value);
+                drop(borrowed_node);
+                return Ok(BPlusTree::split_leaf(&current_node, max_keys_per_node));
+            }
+            let i = BPlusTree::child_index(&borrowed_node, &key);
+            (i, Rc::clone(&borrowed_node.children[i]))
+        };
+
+        let (i, child) = child_to_traverse;
+        let split = BPlusTree::traverse_insert(child, key, value, max_keys_per_node)?;
+        match split {
+            Some((separator, right_node)) => {
+                {
+                    let mut borrowed_node = current_node.borrow_mut();
+                    borrowed_node.keys.insert(i, separator);
+                    borrowed_node.children.insert(i + 1, right_node);
+                }
+                Ok(BPlusTree::split_internal(&current_node, max_keys_per_node))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Split a leaf if it has too many keys
+    ///
+    /// The upper half of the entries moves to a new right leaf, which is linked
+    /// into the leaf chain directly after the split leaf. The first key of the
+    /// right leaf is copied up as the separator.
+    fn split_leaf(leaf: &Rc<RefCell<Node<T, V>>>, max_keys_per_node: usize) -> Split<T, V> {
+        let mut borrowed_leaf = leaf.borrow_mut();
+        if borrowed_leaf.keys.len() <= max_keys_per_node {
+            return None;
+        }
+        let mid = borrowed_leaf.keys.len() / 2;
+        let right_node = Node {
+            keys: borrowed_leaf.keys.split_off(mid),
+            values: borrowed_leaf.values.split_off(mid),
+            children: Vec::new(),
+            next: borrowed_leaf.next.take(),
+        };
+        let separator = right_node.keys[0].clone();
+        let right_node = Rc::new(RefCell::new(right_node));
+        borrowed_leaf.next = Some(Rc::clone(&right_node));
+        Some((separator, right_node))
+    }
+
+    /// Split an internal node if it has too many keys
+    ///
+    /// Unlike a leaf split, the middle key is moved up to the parent instead of
+    /// copied, since internal nodes only hold separators.
+    fn split_internal(node: &Rc<RefCell<Node<T, V>>>, max_keys_per_node: usize) -> Split<T, V> {
+        let mut borrowed_node = node.borrow_mut();
+        if borrowed_node.keys.len() <= max_keys_per_node {
+            return None;
+        }
+        let mid = borrowed_node.keys.len() / 2;
+        let right_keys = borrowed_node.keys.split_off(mid + 1);
+        let separator = borrowed_node.keys.pop().unwrap();
+        let right_children = borrowed_node.children.split_off(mid + 1);
+        let right_node = Node {
+            keys: right_keys,
+            values: Vec::new(),
+            children: right_children,
+            next: None,
+        };
+        Some((separator, Rc::new(RefCell::new(right_node))))
+    }
+
+    /// Remove the key from the leaf covering it
+    ///
+    /// After removing from a child, the child is rebalanced with one of its
+    /// siblings if it went under the minimum number of keys. Separator keys are
+    /// left as they are, they only have to route searches to the right leaf.
+    fn traverse_remove(
+        current_node: Rc<RefCell<Node<T, V>>>,
+        key: &T,
+        min_keys: usize,
+    ) -> Option<V> {
+        let (i, child) = {
+            let mut borrowed_node = current_node.borrow_mut();
+            if borrowed_node.children.is_empty() {
+                let i = borrowed_node
+                    .keys
+                    .iter()
+                    .position(|current_key| current_key == key)?;
+                borrowed_node.keys.remove(i);
+                return Some(borrowed_node.values.remove(i));
+            }
+            let i = BPlusTree::child_index(&borrowed_node, key);
+            (i, Rc::clone(&borrowed_node.children[i]))
+        };
+        let value = BPlusTree::traverse_remove(Rc::clone(&child), key, min_keys)?;
+        if child.borrow().keys.len() < min_keys {
+            BPlusTree::rebalance_child(&current_node, i, min_keys);
+        }
+        Some(value)
+    }
+
+    /// Fix a child that went under the minimum number of keys
+    ///
+    /// Borrows an entry from the left or right sibling if one of them has keys
+    /// to spare, otherwise merges the child with a sibling and removes the
+    /// separator between them from the parent.
+    fn rebalance_child(parent: &Rc<RefCell<Node<T, V>>>, i: usize, min_keys: usize) {
+        let mut borrowed_parent = parent.borrow_mut();
+        let child = Rc::clone(&borrowed_parent.children[i]);
+        let is_leaf = child.borrow().children.is_empty();
+
+        if i > 0 {
+            let left = Rc::clone(&borrowed_parent.children[i - 1]);
+            if left.borrow().keys.len() > min_keys {
+                let mut left = left.borrow_mut();
+                let mut child = child.borrow_mut();
+                if is_leaf {
+                    let key = left.keys.pop().unwrap();
+                    let value = left.values.pop().unwrap();
+                    borrowed_parent.keys[i - 1] = key.clone();
+                    child.keys.insert(0, key);
+                    child.values.insert(0, value);
+                } else {
+                    let key = left.keys.pop().unwrap();
+                    let separator = core::mem::replace(&mut borrowed_parent.keys[i - 1], key);
+                    child.keys.insert(0, separator);
+                    child.children.insert(0, left.children.pop().unwrap());
+                }
+                return;
+            }
+        }
+        if i + 1 < borrowed_parent.children.len() {
+            let right = Rc::clone(&borrowed_parent.children[i + 1]);
+            if right.borrow().keys.len() > min_keys {
+                let mut right = right.borrow_mut();
+                let mut child = child.borrow_mut();
+                if is_leaf {
+                    child.keys.push(right.keys.remove(0));
+                    child.values.push(right.values.remove(0));
+                    borrowed_parent.keys[i] = right.keys[0].clone();
+                } else {
+                    let key = right.keys.remove(0);
+                    let separator = core::mem::replace(&mut borrowed_parent.keys[i], key);
+                    child.keys.push(separator);
+                    child.children.push(right.children.remove(0));
+                }
+                return;
+            }
+        }
+
+        // Neither sibling can spare a key, merge the right node of the pair into the left one
+        let left_index = if i > 0 { i - 1 } else { i };
+        let left = Rc::clone(&borrowed_parent.children[left_index]);
+        let right = borrowed_parent.children.remove(left_index + 1);
+        let separator = borrowed_parent.keys.remove(left_index);
+        let mut left = left.borrow_mut();
+        let mut right = right.borrow_mut();
+        if is_leaf {
+            left.next = right.next.take();
+        } else {
+            left.keys.push(separator);
+        }
+        left.keys.append(&mut right.keys);
+        left.values.append(&mut right.values);
+        left.children.append(&mut right.children);
+    }
+}
+
+impl<T, V> Node<T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    fn new_leaf() -> Self {
+        Node {
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
+            next: None,
+        }
+    }
+}
+
+impl<T, V> Iterator for Range<'_, T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    type Item = (T, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let leaf = self.leaf.clone()?;
+            let borrowed_leaf = leaf.borrow();
+            if self.index < borrowed_leaf.keys.len() {
+                let key = &borrowed_leaf.keys[self.index];
+                let in_range = match &self.end {
+                    Bound::Included(end) => key <= end,
+                    Bound::Excluded(end) => key < end,
+                    Bound::Unbounded => true,
+                };
+                if !in_range {
+                    self.leaf = None;
+                    return None;
+                }
+                self.index += 1;
+                return Some((key.clone(), borrowed_leaf.values[self.index - 1].clone()));
+            }
+            // Reached the end of this leaf, continue with the next one in the chain
+            self.leaf = borrowed_leaf.next.clone();
+            self.index = 0;
+        }
+    }
+}
+
+impl<T, V> fmt::Debug for Node<T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let next = self.next.as_ref().map(|_| ());
+        write!(
+            f,
+            "Node {{\nkeys: {0:#?},\nvalues: {1:#?},\nchildren: {2:#?},\nnext: {3:?}\n}}",
+            self.keys, self.values, self.children, next
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
+
+    use super::BPlusTree;
+    use crate::test_util::Rng;
+
+    fn assert_matches(tree: &BPlusTree<u32, u32>, map: &BTreeMap<u32, u32>) {
+        tree.validate()
+            .unwrap_or_else(|e| panic!("{e} in {tree:?}"));
+        assert_eq!(tree.len(), map.len());
+        let entries: Vec<_> = tree.iter().collect();
+        let expected: Vec<_> = map.iter().map(|(key, value)| (*key, *value)).collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn random_operations_match_btreemap() {
+        let mut rng = Rng::new(9);
+        for max_keys_per_node in 3..=9 {
+            let mut tree = BPlusTree::new(max_keys_per_node).unwrap();
+            let mut map = BTreeMap::new();
+            for step in 0..3000 {
+                let key = rng.below(300);
+                // Removals win over time, so leaves are merged and the tree shrinks
+                let inserting = if step < 1500 {
+                    rng.below(3) != 0
+                } else {
+                    rng.below(3) == 0
+                };
+                if inserting {
+                    let fresh = !map.contains_key(&key);
+                    assert_eq!(tree.insert(key, step).is_ok(), fresh);
+                    map.entry(key).or_insert(step);
+                } else {
+                    assert_eq!(tree.remove(key).ok(), map.remove(&key));
+                }
+                assert_eq!(tree.get(key), map.get(&key).copied());
+                if step % 50 == 0 {
+                    assert_matches(&tree, &map);
+                }
+            }
+            assert_matches(&tree, &map);
+
+            for _ in 0..20 {
+                let start = rng.below(300);
+                let end = start + rng.below(100);
+                let entries: Vec<_> = tree.range(start..end).collect();
+                let expected: Vec<_> = map
+                    .range(start..end)
+                    .map(|(key, value)| (*key, *value))
+                    .collect();
+                assert_eq!(entries, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn removing_everything_merges_the_leaves_back_into_the_root() {
+        for max_keys_per_node in 3..=9 {
+            let mut tree = BPlusTree::new(max_keys_per_node).unwrap();
+            let mut map = BTreeMap::new();
+            for key in 0..300 {
+                tree.insert(key, key).unwrap();
+                map.insert(key, key);
+            }
+            // From both ends and from the middle, so leaves merge with either sibling
+            let order = (0..100).chain((200..300).rev()).chain(100..200);
+            for (i, key) in order.enumerate() {
+                assert_eq!(tree.remove(key).ok(), map.remove(&key));
+                if i % 10 == 0 {
+                    assert_matches(&tree, &map);
+                }
+            }
+            assert!(tree.is_empty());
+            assert_matches(&tree, &map);
+        }
+    }
+
+    #[test]
+    fn new_rejects_nodes_that_cannot_be_split() {
+        assert!(BPlusTree::<u32, u32>::new(2).is_err());
+    }
+
+    #[test]
+    fn range_bounds_at_the_separators_match_btreemap() {
+        // Separators are copies of the first key of the leaf on their right,
+        // so ranges that start or end exactly on one cross the leaf chain
+        use core::ops::Bound::{self, Excluded, Included, Unbounded};
+
+        let mut tree = BPlusTree::new(3).unwrap();
+        let mut map = BTreeMap::new();
+        for key in 0..40 {
+            tree.insert(key * 2, key).unwrap();
+            map.insert(key * 2, key);
+        }
+        let bounds = |key| [Included(key), Excluded(key), Unbounded];
+        for start in 0..82 {
+            for end in start..82 {
+                for start_bound in bounds(start) {
+                    for end_bound in bounds(end) {
+                        let range: (Bound<u32>, Bound<u32>) = (start_bound, end_bound);
+                        if start == end && range.0 == Excluded(start) && range.1 == Excluded(end) {
+                            // BTreeMap panics on this empty range
+                            continue;
+                        }
+                        let entries: Vec<_> = tree.range(range).collect();
+                        let expected: Vec<_> = map
+                            .range(range)
+                            .map(|(key, value)| (*key, *value))
+                            .collect();
+                        assert_eq!(entries, expected, "{range:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_tree_has_no_entries() {
+        let mut tree = BPlusTree::<u32, u32>::new(4).unwrap();
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.range(3..).next(), None);
+        assert_eq!(tree.get(3), None);
+        assert!(tree.remove(3).is_err());
+        tree.validate().unwrap();
+
+        tree.insert(3, 30).unwrap();
+        assert!(tree.insert(3, 31).is_err());
+        assert_eq!(tree.remove(3), Ok(30));
+        assert!(tree.is_empty());
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn errors_can_be_kept_while_the_tree_changes() {
+        let mut tree = BPlusTree::new(4).unwrap();
+        tree.insert(1, 10).unwrap();
+        let errors = [tree.insert(1, 11).unwrap_err(), tree.remove(2).unwrap_err()];
+        tree.insert(2, 20).unwrap();
+        assert_eq!(errors, ["Key already exists", "Key not found"]);
+    }
+}
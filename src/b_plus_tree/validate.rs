@@ -0,0 +1,106 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::Debug;
+
+use super::{BPlusTree, Node};
+
+impl<T, V> BPlusTree<T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    /// Check the invariants of the tree, returns an Err describing the first
+    /// one that does not hold
+    ///
+    /// Checks that the keys are sorted and routed to the right child, that
+    /// every node but the root has an allowed number of keys, that only leaves
+    /// hold values, that all leaves are at the same depth, and that the leaf
+    /// chain links all leaves from left to right. Walks the whole tree, so
+    /// this is meant for testing and debugging.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let mut leaves = Vec::new();
+        let mut leaf_depth = None;
+        let len = self.validate_node(&self.root, 0, &mut leaf_depth, &mut leaves, None, None)?;
+        if len != self.len {
+            return Err("Tree has the wrong entry count");
+        }
+        for (i, leaf) in leaves.iter().enumerate() {
+            let linked = match (&leaf.borrow().next, leaves.get(i + 1)) {
+                (Some(next), Some(expected)) => Rc::ptr_eq(next, expected),
+                (None, None) => true,
+                _ => false,
+            };
+            if !linked {
+                return Err("Leaf chain skips or repeats a leaf");
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the subtree below `node`, whose keys must be at least `lower`
+    /// and smaller than `upper`
+    ///
+    /// Adds the leaves of the subtree to `leaves` from left to right, and
+    /// returns the number of entries in the subtree.
+    fn validate_node(
+        &self,
+        node: &Rc<RefCell<Node<T, V>>>,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+        leaves: &mut Vec<Rc<RefCell<Node<T, V>>>>,
+        lower: Option<&T>,
+        upper: Option<&T>,
+    ) -> Result<usize, &'static str> {
+        let borrowed_node = node.borrow();
+        if borrowed_node.keys.len() > self.max_keys_per_node {
+            return Err("Node has too many keys");
+        }
+        if depth > 0 && borrowed_node.keys.len() < self.min_keys_per_node() {
+            return Err("Node has too few keys");
+        }
+        if borrowed_node.keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("Keys are out of order");
+        }
+        let out_of_bounds = borrowed_node.keys.iter().any(|key| {
+            lower.is_some_and(|lower| key < lower) || upper.is_some_and(|upper| key >= upper)
+        });
+        if out_of_bounds {
+            return Err("Key is on the wrong side of a separator");
+        }
+
+        if borrowed_node.children.is_empty() {
+            if borrowed_node.keys.len() != borrowed_node.values.len() {
+                return Err("Leaf has a different number of keys and values");
+            }
+            match leaf_depth {
+                Some(leaf_depth) if *leaf_depth != depth => {
+                    return Err("Leaves are at different depths");
+                }
+                _ => *leaf_depth = Some(depth),
+            }
+            leaves.push(Rc::clone(node));
+            return Ok(borrowed_node.keys.len());
+        }
+        if !borrowed_node.values.is_empty() {
+            return Err("Internal node holds values");
+        }
+        if borrowed_node.next.is_some() {
+            return Err("Internal node is linked into the leaf chain");
+        }
+        if borrowed_node.children.len() != borrowed_node.keys.len() + 1 {
+            return Err("Node has the wrong number of children");
+        }
+        let mut size = 0;
+        for (i, child) in borrowed_node.children.iter().enumerate() {
+            let lower = if i == 0 {
+                lower
+            } else {
+                borrowed_node.keys.get(i - 1)
+            };
+            let upper = borrowed_node.keys.get(i).or(upper);
+            size += self.validate_node(child, depth + 1, leaf_depth, leaves, lower, upper)?;
+        }
+        Ok(size)
+    }
+}
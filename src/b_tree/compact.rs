@@ -0,0 +1,210 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::Debug;
+
+use super::{BTree, Node, Summary, TreeStats};
+
+/// What `BTree::compact` or `BTree::shrink_to_fit` changed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactStats {
+    pub nodes_before: usize,
+    pub nodes_after: usize,
+    /// See `TreeStats::fill_factor`
+    pub fill_factor_before: f64,
+    pub fill_factor_after: f64,
+    /// See `TreeStats::estimated_heap_bytes`
+    pub heap_bytes_before: usize,
+    pub heap_bytes_after: usize,
+}
+
+impl CompactStats {
+    fn new(before: &TreeStats, after: &TreeStats) -> Self {
+        CompactStats {
+            nodes_before: before.internal_nodes + before.leaf_nodes,
+            nodes_after: after.internal_nodes + after.leaf_nodes,
+            fill_factor_before: before.fill_factor,
+            fill_factor_after: after.fill_factor,
+            heap_bytes_before: before.estimated_heap_bytes,
+            heap_bytes_after: after.estimated_heap_bytes,
+        }
+    }
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Rebuild the tree into nodes that are `target_fill` full, and release
+    /// the spare capacity of the nodes
+    ///
+    /// After many removals a tree can be left with lots of nodes that are only
+    /// just above the minimum. The entries are moved out of the nodes in key
+    /// order and built into new nodes bottom-up, which takes O(n). The fill is
+    /// a fraction of `max_keys_per_node`, and is kept above the minimum number
+    /// of keys. A fill of 1 packs the nodes as densely as possible, but then
+    /// the next insertions split them right away.
+    ///
+    /// Returns an Err if `target_fill` is not above 0 and at most 1.
+    pub fn compact(&mut self, target_fill: f64) -> Result<CompactStats, &'static str> {
+        if !(target_fill > 0.0 && target_fill <= 1.0) {
+            return Err("Target fill must be above 0 and at most 1");
+        }
+        let before = self.stats();
+        // Rounded by hand, `f64::round` needs `std`
+        let keys_per_node = (target_fill * self.max_keys_per_node as f64 + 0.5) as usize;
+        // No node is split or merged, so only the new root and height are reported
+        self.observe_bulk(|tree, _| {
+            let root = core::mem::replace(&mut tree.root, Rc::new(RefCell::new(Node::new_empty())));
+            let mut entries = Vec::with_capacity(before.entries);
+            BTree::move_entries(root, &mut entries);
+            tree.root = tree.build_from_sorted(entries, keys_per_node);
+        });
+        BTree::shrink_node(&self.root);
+        Ok(CompactStats::new(&before, &self.stats()))
+    }
+
+    /// Release the spare capacity of the vectors in every node, without
+    /// moving any entries between nodes
+    pub fn shrink_to_fit(&mut self) -> CompactStats {
+        let before = self.stats();
+        BTree::shrink_node(&self.root);
+        CompactStats::new(&before, &self.stats())
+    }
+
+    fn shrink_node(node: &Rc<RefCell<Node<T, V, S>>>) {
+        let mut borrowed_node = node.borrow_mut();
+        borrowed_node.keys.shrink_to_fit();
+        borrowed_node.values.shrink_to_fit();
+        borrowed_node.children.shrink_to_fit();
+        for child in borrowed_node.children.iter() {
+            BTree::shrink_node(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::test_util::{assert_matches, configs, random_map, tree_from, Rng};
+    use crate::{BTree, BTreeConfig};
+
+    /// A tree of order 7 that held 0..1000, with all keys but every tenth removed
+    fn sparse_tree() -> BTree<u32, u32> {
+        let mut tree = BTreeConfig::new().order(7).build().unwrap();
+        for key in 0..1000 {
+            tree.insert(key, key).unwrap();
+        }
+        for key in (0..1000).filter(|key| key % 10 != 0) {
+            tree.remove(key).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn compact_keeps_the_entries_and_a_valid_tree() {
+        let mut rng = Rng::new(8);
+        for config in configs() {
+            let mut map = random_map(&mut rng, 500, 2000);
+            let mut tree = tree_from(&config, &map);
+            for key in random_map(&mut rng, 300, 2000).into_keys() {
+                assert_eq!(tree.remove(key).ok(), map.remove(&key));
+            }
+            for target_fill in [0.1, 0.5, 0.75, 1.0] {
+                tree.compact(target_fill).unwrap();
+                assert_matches(&tree, &map);
+
+                // The compacted nodes still take insertions and removals
+                for key in random_map(&mut rng, 50, 2000).into_keys() {
+                    if rng.below(2) == 0 {
+                        assert_eq!(tree.remove(key).ok(), map.remove(&key));
+                    } else if tree.insert(key, key).is_ok() {
+                        map.insert(key, key);
+                    }
+                }
+                assert_matches(&tree, &map);
+            }
+        }
+    }
+
+    #[test]
+    fn compact_packs_a_sparse_tree_into_fewer_nodes() {
+        let mut tree = sparse_tree();
+        let before = tree.stats();
+        let compacted = tree.compact(1.0).unwrap();
+        let after = tree.stats();
+        assert_eq!(
+            compacted.nodes_before,
+            before.internal_nodes + before.leaf_nodes
+        );
+        assert_eq!(
+            compacted.nodes_after,
+            after.internal_nodes + after.leaf_nodes
+        );
+        assert_eq!(compacted.fill_factor_before, before.fill_factor);
+        assert_eq!(compacted.fill_factor_after, after.fill_factor);
+        assert_eq!(compacted.heap_bytes_after, after.estimated_heap_bytes);
+        // The removals left the nodes about half full
+        assert!(compacted.fill_factor_before < 0.6);
+        assert!(compacted.fill_factor_after > 0.85);
+        assert!(compacted.nodes_after < compacted.nodes_before);
+        assert!(compacted.heap_bytes_after < compacted.heap_bytes_before);
+        let keys: Vec<_> = tree.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, (0..100).map(|key| key * 10).collect::<Vec<_>>());
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn a_low_target_fill_stays_above_the_minimum() {
+        let mut tree = sparse_tree();
+        tree.compact(0.01).unwrap();
+        // Order 7 nodes need 3 keys, the root excepted
+        tree.validate().unwrap();
+        let sparse = tree.stats();
+        assert!(sparse.levels[1..]
+            .iter()
+            .all(|level| level.occupancy[..3] == [0, 0, 0]));
+
+        let dense = tree.compact(1.0).unwrap();
+        assert!(dense.nodes_after < sparse.internal_nodes + sparse.leaf_nodes);
+    }
+
+    #[test]
+    fn compact_rejects_fills_outside_0_to_1_and_leaves_the_tree_alone() {
+        let mut tree = sparse_tree();
+        let levels = tree.levels();
+        for target_fill in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(tree.compact(target_fill).is_err());
+        }
+        assert_eq!(tree.levels(), levels);
+    }
+
+    #[test]
+    fn compact_an_empty_tree() {
+        let mut tree: BTree<u32, u32> = BTree::empty(4);
+        let compacted = tree.compact(1.0).unwrap();
+        assert_eq!((compacted.nodes_before, compacted.nodes_after), (1, 1));
+        assert!(tree.is_empty());
+        tree.insert(1, 1).unwrap();
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn shrink_to_fit_releases_capacity_without_moving_entries() {
+        let mut tree = sparse_tree();
+        let levels = tree.levels();
+        let shrunk = tree.shrink_to_fit();
+        assert_eq!(shrunk.nodes_after, shrunk.nodes_before);
+        assert_eq!(shrunk.fill_factor_after, shrunk.fill_factor_before);
+        assert!(shrunk.heap_bytes_after < shrunk.heap_bytes_before);
+        assert_eq!(tree.levels(), levels);
+
+        // Nothing is left to release the second time
+        let again = tree.shrink_to_fit();
+        assert_eq!(again.heap_bytes_after, again.heap_bytes_before);
+        tree.validate().unwrap();
+    }
+}
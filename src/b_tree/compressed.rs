@@ -0,0 +1,731 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+use core::mem::size_of;
+
+use super::{BTree, Node, Prefixed, Summary};
+
+/// CompressedBTree
+///
+/// A B-tree with byte-string keys, in a node layout that stores each key only
+/// partly. The keys of a node often start with the same bytes, so every node
+/// stores the prefix its keys have in common once, and for every key only the
+/// bytes after it. All suffixes of a node are stored back to back in a single
+/// buffer, instead of each key in its own allocation.
+///
+/// Lookups compare against the prefix first and then only against the
+/// suffixes, so keys are never decompressed during a search. Insertion and
+/// removal decompress the keys of the nodes they change and compress them
+/// again, which like shifting the keys of a `BTree` node takes time in
+/// proportion to the size of the node. Created by `CompressedBTree::new`, or
+/// from a `BTree` by `BTree::to_compressed`.
+#[derive(Debug)]
+pub struct CompressedBTree<V>
+where
+    V: Clone + Debug,
+{
+    max_keys_per_node: usize,
+    min_keys_per_node: usize,
+    root: CompressedNode<V>,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct CompressedNode<V>
+where
+    V: Clone + Debug,
+{
+    // Bytes all keys of this node start with
+    prefix: Vec<u8>,
+    // The bytes of all keys after the prefix, one after the other
+    suffixes: Vec<u8>,
+    // Where the suffix of each key ends in `suffixes`
+    suffix_ends: Vec<usize>,
+    values: Vec<V>,
+    children: Vec<CompressedNode<V>>,
+}
+
+// Separator and new right node handed to the parent after a split
+type Split<V> = Option<(Vec<u8>, V, CompressedNode<V>)>;
+
+/// Iterator over the entries of a `CompressedBTree` in key order
+///
+/// Created by `CompressedBTree::iter`. Yields each key decompressed into a
+/// new vector, together with a reference to its value.
+pub struct CompressedIter<'a, V>
+where
+    V: Clone + Debug,
+{
+    stack: Vec<(&'a CompressedNode<V>, usize)>,
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug + Prefixed,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Copy the tree into the prefix-compressed node layout
+    ///
+    /// The copy has exactly the same shape and number of keys per node as
+    /// this tree, and can be changed independently of it.
+    pub fn to_compressed(&self) -> CompressedBTree<V> {
+        CompressedBTree {
+            max_keys_per_node: self.max_keys_per_node,
+            min_keys_per_node: self.min_keys_allowed(),
+            root: CompressedNode::from_node(&self.root.borrow()),
+            len: self.len(),
+        }
+    }
+
+    /// Number of bytes used to store the keys
+    ///
+    /// Counts the key itself and the bytes it points to, which can be
+    /// compared to `compressed_key_memory`.
+    pub fn key_memory(&self) -> usize {
+        self.iter()
+            .map(|(key, _)| size_of::<T>() + key.key_bytes().len())
+            .sum()
+    }
+
+    /// Number of bytes the keys would use in the prefix-compressed node layout
+    ///
+    /// The same as `CompressedBTree::key_memory` after `to_compressed`, but
+    /// counted from the nodes of this tree without copying them.
+    pub fn compressed_key_memory(&self) -> usize {
+        BTree::node_compressed_key_memory(&self.root.borrow())
+    }
+
+    fn node_compressed_key_memory(node: &Node<T, V, S>) -> usize {
+        let prefix_len = common_prefix_len(node.keys.iter().map(|key| key.key_bytes()));
+        let key_bytes: usize = node.keys.iter().map(|key| key.key_bytes().len()).sum();
+        let suffix_bytes = key_bytes - prefix_len * node.keys.len();
+        let own = layout_bytes(prefix_len, suffix_bytes, node.keys.len());
+        own + node
+            .children
+            .iter()
+            .map(|child| BTree::node_compressed_key_memory(&child.borrow()))
+            .sum::<usize>()
+    }
+}
+
+impl<V> CompressedBTree<V>
+where
+    V: Clone + Debug,
+{
+    /// Create a tree without any keys
+    ///
+    /// Returns an Err if `max_keys_per_node` is below 2. Removal keeps at
+    /// least half of `max_keys_per_node` keys in every node but the root.
+    pub fn new(max_keys_per_node: usize) -> Result<Self, &'static str> {
+        if max_keys_per_node < 2 {
+            return Err("max_keys_per_node must be at least 2");
+        }
+        Ok(CompressedBTree {
+            max_keys_per_node,
+            min_keys_per_node: max_keys_per_node / 2,
+            root: CompressedNode::new(Vec::new(), Vec::new(), Vec::new()),
+            len: 0,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The value stored under `key`
+    pub fn get<P: Prefixed + ?Sized>(&self, key: &P) -> Option<&V> {
+        let key = key.key_bytes();
+        let mut node = &self.root;
+        loop {
+            let (i, found) = node.search(key);
+            if found {
+                return Some(&node.values[i]);
+            }
+            node = node.children.get(i)?;
+        }
+    }
+
+    pub fn contains_key<P: Prefixed + ?Sized>(&self, key: &P) -> bool {
+        self.get(key).is_some()
+    }
+
+    // Returns an Err when the key already exists
+    pub fn insert<P: Prefixed + ?Sized>(&mut self, key: &P, value: V) -> Result<(), &'static str> {
+        let split = self
+            .root
+            .insert(key.key_bytes(), value, self.max_keys_per_node)?;
+        if let Some((separator, value, right)) = split {
+            // The root was split, so the tree grows one level taller
+            let left = core::mem::replace(
+                &mut self.root,
+                CompressedNode::new(Vec::new(), Vec::new(), Vec::new()),
+            );
+            self.root = CompressedNode::new(vec![separator], vec![value], vec![left, right]);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    // Returns an Err when the key does not exist
+    pub fn remove<P: Prefixed + ?Sized>(&mut self, key: &P) -> Result<V, &'static str> {
+        let value = self
+            .root
+            .remove(key.key_bytes(), self.min_keys_per_node)
+            .ok_or("Key not found")?;
+        if self.root.values.is_empty() && !self.root.children.is_empty() {
+            // The root lost its last key to a merge, so the tree gets one level shorter
+            self.root = self.root.children.pop().unwrap();
+        }
+        self.len -= 1;
+        Ok(value)
+    }
+
+    /// Iterate over all entries in key order
+    pub fn iter(&self) -> CompressedIter<'_, V> {
+        let mut iter = CompressedIter { stack: Vec::new() };
+        iter.descend_leftmost(&self.root);
+        iter
+    }
+
+    /// Number of bytes used to store the keys
+    ///
+    /// Counts the prefix and suffix buffers of every node with their
+    /// bookkeeping, to compare with `BTree::key_memory`.
+    pub fn key_memory(&self) -> usize {
+        self.root.key_memory()
+    }
+
+    /// Check the invariants of the tree, returns an Err describing the first
+    /// one that does not hold
+    ///
+    /// Checks that every node stores the longest prefix its keys have in
+    /// common, that the keys are sorted across all nodes, that every node but
+    /// the root has an allowed number of keys, and that all leaves are at the
+    /// same depth. Walks the whole tree, so this is meant for testing and
+    /// debugging.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let mut leaf_depth = None;
+        let len = self.validate_node(&self.root, 0, &mut leaf_depth, None, None)?;
+        if len != self.len {
+            return Err("Tree has the wrong entry count");
+        }
+        Ok(())
+    }
+
+    /// Check the subtree below `node`, whose keys must lie between `lower` and `upper`
+    ///
+    /// Returns the number of entries in the subtree.
+    fn validate_node(
+        &self,
+        node: &CompressedNode<V>,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+        lower: Option<&[u8]>,
+        upper: Option<&[u8]>,
+    ) -> Result<usize, &'static str> {
+        if node.suffix_ends.len() != node.values.len() {
+            return Err("Node has a different number of keys and values");
+        }
+        if node.values.len() > self.max_keys_per_node {
+            return Err("Node has too many keys");
+        }
+        if depth > 0 && node.values.len() < self.min_keys_per_node {
+            return Err("Node has too few keys");
+        }
+        let keys = node.keys();
+        if common_prefix_len(keys.iter().map(Vec::as_slice)) != node.prefix.len() {
+            return Err("Node does not store the common prefix of its keys");
+        }
+        if keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("Keys are out of order");
+        }
+        let out_of_bounds = keys.iter().any(|key| {
+            lower.is_some_and(|lower| key.as_slice() <= lower)
+                || upper.is_some_and(|upper| key.as_slice() >= upper)
+        });
+        if out_of_bounds {
+            return Err("Key is on the wrong side of a key in a parent node");
+        }
+
+        let mut size = keys.len();
+        if node.children.is_empty() {
+            match leaf_depth {
+                Some(leaf_depth) if *leaf_depth != depth => {
+                    return Err("Leaves are at different depths");
+                }
+                _ => *leaf_depth = Some(depth),
+            }
+            return Ok(size);
+        }
+        if node.children.len() != keys.len() + 1 {
+            return Err("Node has the wrong number of children");
+        }
+        for (i, child) in node.children.iter().enumerate() {
+            let lower = if i == 0 {
+                lower
+            } else {
+                Some(keys[i - 1].as_slice())
+            };
+            let upper = keys.get(i).map(Vec::as_slice).or(upper);
+            size += self.validate_node(child, depth + 1, leaf_depth, lower, upper)?;
+        }
+        Ok(size)
+    }
+}
+
+impl<V> CompressedNode<V>
+where
+    V: Clone + Debug,
+{
+    fn new(keys: Vec<Vec<u8>>, values: Vec<V>, children: Vec<CompressedNode<V>>) -> Self {
+        let mut node = CompressedNode {
+            prefix: Vec::new(),
+            suffixes: Vec::new(),
+            suffix_ends: Vec::new(),
+            values,
+            children,
+        };
+        node.set_keys(keys.iter().map(Vec::as_slice));
+        node
+    }
+
+    fn from_node<T, S>(node: &Node<T, V, S>) -> Self
+    where
+        T: PartialOrd + Clone + Debug + Prefixed,
+        S: Summary<T, V>,
+    {
+        let mut compressed = CompressedNode {
+            prefix: Vec::new(),
+            suffixes: Vec::new(),
+            suffix_ends: Vec::new(),
+            values: node.values.clone(),
+            children: node
+                .children
+                .iter()
+                .map(|child| CompressedNode::from_node(&child.borrow()))
+                .collect(),
+        };
+        compressed.set_keys(node.keys.iter().map(|key| key.key_bytes()));
+        compressed
+    }
+
+    /// Store `keys`, which must be sorted, as their common prefix and their suffixes
+    fn set_keys<'k, I>(&mut self, keys: I)
+    where
+        I: Iterator<Item = &'k [u8]> + Clone,
+    {
+        let prefix_len = common_prefix_len(keys.clone());
+        self.prefix.clear();
+        self.suffixes.clear();
+        self.suffix_ends.clear();
+        for (i, key) in keys.enumerate() {
+            if i == 0 {
+                self.prefix.extend_from_slice(&key[..prefix_len]);
+            }
+            self.suffixes.extend_from_slice(&key[prefix_len..]);
+            self.suffix_ends.push(self.suffixes.len());
+        }
+        self.prefix.shrink_to_fit();
+        self.suffixes.shrink_to_fit();
+    }
+
+    /// All keys with the prefix put back in front, to change the keys of the node
+    fn keys(&self) -> Vec<Vec<u8>> {
+        (0..self.suffix_ends.len()).map(|i| self.key(i)).collect()
+    }
+
+    /// The bytes of key `i` after the prefix
+    fn suffix(&self, i: usize) -> &[u8] {
+        let start = if i == 0 { 0 } else { self.suffix_ends[i - 1] };
+        &self.suffixes[start..self.suffix_ends[i]]
+    }
+
+    /// Key `i` with the prefix put back in front
+    fn key(&self, i: usize) -> Vec<u8> {
+        let mut key = self.prefix.clone();
+        key.extend_from_slice(self.suffix(i));
+        key
+    }
+
+    /// Position of the first key that is not smaller than `key`, and whether
+    /// that key is equal to it
+    fn search(&self, key: &[u8]) -> (usize, bool) {
+        let len = self.suffix_ends.len();
+        if !key.starts_with(&self.prefix) {
+            // All keys of the node start with the prefix, so they are either
+            // all larger or all smaller than `key`
+            return match key.cmp(&self.prefix[..]) {
+                Ordering::Less => (0, false),
+                _ => (len, false),
+            };
+        }
+        let rest = &key[self.prefix.len()..];
+        let mut low = 0;
+        let mut high = len;
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.suffix(mid).cmp(rest) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Equal => return (mid, true),
+                Ordering::Greater => high = mid,
+            }
+        }
+        (low, false)
+    }
+
+    /// Insert an entry at position `i`, without touching the children
+    fn insert_entry(&mut self, i: usize, key: Vec<u8>, value: V) {
+        let mut keys = self.keys();
+        keys.insert(i, key);
+        self.set_keys(keys.iter().map(Vec::as_slice));
+        self.values.insert(i, value);
+    }
+
+    /// Remove the entry at position `i`, without touching the children
+    fn remove_entry(&mut self, i: usize) -> (Vec<u8>, V) {
+        let mut keys = self.keys();
+        let key = keys.remove(i);
+        self.set_keys(keys.iter().map(Vec::as_slice));
+        (key, self.values.remove(i))
+    }
+
+    /// Put another entry at position `i`, and return the entry that was there
+    fn replace_entry(&mut self, i: usize, key: Vec<u8>, value: V) -> (Vec<u8>, V) {
+        let mut keys = self.keys();
+        let old_key = core::mem::replace(&mut keys[i], key);
+        self.set_keys(keys.iter().map(Vec::as_slice));
+        (old_key, core::mem::replace(&mut self.values[i], value))
+    }
+
+    /// Insert the key-value pair into the subtree below this node
+    ///
+    /// Like `BTree` with the default settings, the key goes into a leaf, and
+    /// a node that goes over the maximum number of keys is split in two on
+    /// the way back up. The separator and the new right node are returned to
+    /// the parent.
+    fn insert(
+        &mut self,
+        key: &[u8],
+        value: V,
+        max_keys_per_node: usize,
+    ) -> Result<Split<V>, &'static str> {
+        let (i, found) = self.search(key);
+        if found {
+            return Err("Key already exists");
+        }
+        if self.children.is_empty() {
+            self.insert_entry(i, key.to_vec(), value);
+        } else {
+            match self.children[i].insert(key, value, max_keys_per_node)? {
+                Some((separator, value, right)) => {
+                    self.insert_entry(i, separator, value);
+                    self.children.insert(i + 1, right);
+                }
+                None => return Ok(None),
+            }
+        }
+        if self.values.len() <= max_keys_per_node {
+            return Ok(None);
+        }
+        let mut keys = self.keys();
+        let mid = keys.len() / 2;
+        let right_keys = keys.split_off(mid + 1);
+        let separator = keys.pop().unwrap();
+        self.set_keys(keys.iter().map(Vec::as_slice));
+        let right_values = self.values.split_off(mid + 1);
+        let value = self.values.pop().unwrap();
+        let right_children = if self.children.is_empty() {
+            Vec::new()
+        } else {
+            self.children.split_off(mid + 1)
+        };
+        let right = CompressedNode::new(right_keys, right_values, right_children);
+        Ok(Some((separator, value, right)))
+    }
+
+    /// Remove the key from the subtree below this node
+    ///
+    /// A key in an internal node is replaced by its predecessor. After
+    /// removing from a child, the child is rebalanced if it went below the
+    /// minimum number of keys.
+    fn remove(&mut self, key: &[u8], min_keys_per_node: usize) -> Option<V> {
+        let (i, found) = self.search(key);
+        if self.children.is_empty() {
+            return found.then(|| self.remove_entry(i).1);
+        }
+        let value = if found {
+            let (key, value) = self.children[i].remove_last(min_keys_per_node);
+            self.replace_entry(i, key, value).1
+        } else {
+            self.children[i].remove(key, min_keys_per_node)?
+        };
+        if self.children[i].values.len() < min_keys_per_node {
+            self.rebalance_child(i, min_keys_per_node);
+        }
+        Some(value)
+    }
+
+    /// Remove the entry with the largest key from the subtree below this node
+    fn remove_last(&mut self, min_keys_per_node: usize) -> (Vec<u8>, V) {
+        if self.children.is_empty() {
+            return self.remove_entry(self.values.len() - 1);
+        }
+        let i = self.children.len() - 1;
+        let entry = self.children[i].remove_last(min_keys_per_node);
+        if self.children[i].values.len() < min_keys_per_node {
+            self.rebalance_child(i, min_keys_per_node);
+        }
+        entry
+    }
+
+    /// Fix child `i`, which went below the minimum number of keys
+    ///
+    /// Borrows an entry through this node from a sibling that has keys to
+    /// spare, and otherwise merges the child with a sibling.
+    fn rebalance_child(&mut self, i: usize, min_keys_per_node: usize) {
+        if i > 0 && self.children[i - 1].values.len() > min_keys_per_node {
+            let left = &mut self.children[i - 1];
+            let (key, value) = left.remove_entry(left.values.len() - 1);
+            let child = left.children.pop();
+            let (key, value) = self.replace_entry(i - 1, key, value);
+            self.children[i].insert_entry(0, key, value);
+            if let Some(child) = child {
+                self.children[i].children.insert(0, child);
+            }
+            return;
+        }
+        if i + 1 < self.children.len() && self.children[i + 1].values.len() > min_keys_per_node {
+            let right = &mut self.children[i + 1];
+            let (key, value) = right.remove_entry(0);
+            let child = (!right.children.is_empty()).then(|| right.children.remove(0));
+            let (key, value) = self.replace_entry(i, key, value);
+            let len = self.children[i].values.len();
+            self.children[i].insert_entry(len, key, value);
+            if let Some(child) = child {
+                self.children[i].children.push(child);
+            }
+            return;
+        }
+        // Neither sibling can spare a key, merge the right node of the pair into the left one
+        let left_index = if i > 0 { i - 1 } else { i };
+        let right = self.children.remove(left_index + 1);
+        let (key, value) = self.remove_entry(left_index);
+        let left = &mut self.children[left_index];
+        let mut keys = left.keys();
+        keys.push(key);
+        keys.extend(right.keys());
+        left.set_keys(keys.iter().map(Vec::as_slice));
+        left.values.push(value);
+        left.values.extend(right.values);
+        left.children.extend(right.children);
+    }
+
+    fn key_memory(&self) -> usize {
+        let own = layout_bytes(
+            self.prefix.len(),
+            self.suffixes.len(),
+            self.suffix_ends.len(),
+        );
+        own + self
+            .children
+            .iter()
+            .map(|child| child.key_memory())
+            .sum::<usize>()
+    }
+}
+
+/// Number of bytes that sorted `keys` all start with
+///
+/// The first and last key share the least bytes, so only those are compared.
+fn common_prefix_len<'k, I: Iterator<Item = &'k [u8]>>(keys: I) -> usize {
+    let mut keys = keys;
+    let Some(first) = keys.next() else {
+        return 0;
+    };
+    let last = keys.last().unwrap_or(first);
+    first.iter().zip(last).take_while(|(a, b)| a == b).count()
+}
+
+/// Memory used by the keys of a node in the prefix-compressed layout
+fn layout_bytes(prefix_len: usize, suffix_bytes: usize, keys: usize) -> usize {
+    3 * size_of::<Vec<u8>>() + prefix_len + suffix_bytes + keys * size_of::<usize>()
+}
+
+impl<'a, V> CompressedIter<'a, V>
+where
+    V: Clone + Debug,
+{
+    /// Push the path to the smallest key of the subtree below `node`
+    fn descend_leftmost(&mut self, node: &'a CompressedNode<V>) {
+        let mut current_node = node;
+        loop {
+            self.stack.push((current_node, 0));
+            match current_node.children.first() {
+                Some(child) => current_node = child,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<'a, V> Iterator for CompressedIter<'a, V>
+where
+    V: Clone + Debug,
+{
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, i) = self.stack.last_mut()?;
+            let (node, i) = (*node, *i);
+            if i >= node.values.len() {
+                // All keys of this node are visited, continue with the parent
+                self.stack.pop();
+                continue;
+            }
+            self.stack.last_mut()?.1 += 1;
+            // The keys in the child after this key come next
+            if let Some(child) = node.children.get(i + 1) {
+                self.descend_leftmost(child);
+            }
+            return Some((node.key(i), &node.values[i]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::format;
+    use alloc::vec::Vec;
+
+    use super::{common_prefix_len, CompressedBTree};
+    use crate::test_util::Rng;
+    use crate::BTree;
+
+    #[test]
+    fn random_operations_match_btreemap() {
+        let mut rng = Rng::new(14);
+        for max_keys_per_node in 2..=8 {
+            let mut tree = CompressedBTree::new(max_keys_per_node).unwrap();
+            let mut map = BTreeMap::new();
+            for step in 0..1500 {
+                // Keys with long shared prefixes, and a few that are prefixes of others
+                let key = format!("key/{:03}/{}", rng.below(80), rng.below(4)).into_bytes();
+                let key = if rng.below(10) == 0 {
+                    key[..5].to_vec()
+                } else {
+                    key
+                };
+                if rng.below(3) != 0 {
+                    let fresh = !map.contains_key(&key);
+                    assert_eq!(tree.insert(&key, step).is_ok(), fresh);
+                    map.entry(key).or_insert(step);
+                } else {
+                    assert_eq!(tree.remove(&key).ok(), map.remove(&key));
+                }
+                if step % 25 == 0 {
+                    tree.validate().unwrap();
+                }
+            }
+            tree.validate().unwrap();
+            assert_eq!(tree.len(), map.len());
+            let entries: Vec<_> = tree.iter().map(|(key, value)| (key, *value)).collect();
+            let expected: Vec<_> = map
+                .iter()
+                .map(|(key, value)| (key.clone(), *value))
+                .collect();
+            assert_eq!(entries, expected);
+            for (key, value) in &map {
+                assert_eq!(tree.get(key), Some(value));
+            }
+            assert!(!tree.contains_key("other"));
+        }
+    }
+
+    #[test]
+    fn to_compressed_copies_the_tree() {
+        for max_keys_per_node in 2..=8 {
+            let mut tree: BTree<Vec<u8>, usize> = BTree::empty(max_keys_per_node);
+            for i in 0..300 {
+                tree.insert(format!("prefix/{}", i * 7 % 300).into_bytes(), i)
+                    .unwrap();
+            }
+            let mut compressed = tree.to_compressed();
+            compressed.validate().unwrap();
+            assert_eq!(compressed.key_memory(), tree.compressed_key_memory());
+
+            for (key, value) in tree.iter().take(100) {
+                assert_eq!(compressed.remove(&key), Ok(value));
+            }
+            compressed.insert("prefix/new", 0).unwrap();
+            compressed.validate().unwrap();
+            assert_eq!(compressed.len(), 201);
+            assert_eq!(tree.len(), 300);
+        }
+    }
+
+    #[test]
+    fn common_prefix_of_sorted_keys() {
+        let keys: [&[u8]; 3] = [b"abc", b"abd", b"abz"];
+        assert_eq!(common_prefix_len(keys.into_iter()), 2);
+        assert_eq!(common_prefix_len([&b"abc"[..]].into_iter()), 3);
+        assert_eq!(common_prefix_len([&b"ab"[..], b"abc"].into_iter()), 2);
+        assert_eq!(common_prefix_len([&b""[..], b"a"].into_iter()), 0);
+        assert_eq!(common_prefix_len([&b"a"[..], b"b"].into_iter()), 0);
+        assert_eq!(common_prefix_len(core::iter::empty()), 0);
+    }
+
+    #[test]
+    fn keys_that_are_prefixes_of_each_other() {
+        let mut tree = CompressedBTree::new(2).unwrap();
+        // The empty key, and every key a prefix of the next
+        for (i, key) in ["", "a", "aa", "aaa", "aaaa", "aaaab", "ab", "b"]
+            .iter()
+            .enumerate()
+        {
+            tree.insert(*key, i).unwrap();
+            tree.validate().unwrap();
+        }
+        assert_eq!(tree.get(""), Some(&0));
+        assert_eq!(tree.get("aaa"), Some(&3));
+        assert_eq!(tree.get("aaab"), None);
+        assert_eq!(tree.insert("aa", 9), Err("Key already exists"));
+        let keys: Vec<_> = tree.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys[..3], [&b""[..], b"a", b"aa"]);
+        for key in ["aa", "", "aaaab", "b"] {
+            assert!(tree.remove(key).is_ok());
+            tree.validate().unwrap();
+        }
+        assert_eq!(tree.len(), 4);
+    }
+
+    #[test]
+    fn empty_trees_and_missing_keys() {
+        assert_eq!(
+            CompressedBTree::<u32>::new(1).err(),
+            Some("max_keys_per_node must be at least 2")
+        );
+        let mut tree: CompressedBTree<u32> = CompressedBTree::new(3).unwrap();
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.get("a"), None);
+        assert_eq!(tree.remove("a"), Err("Key not found"));
+        tree.insert("a", 1).unwrap();
+        assert_eq!(tree.remove("b"), Err("Key not found"));
+        assert_eq!(tree.remove("a"), Ok(1));
+        assert!(tree.is_empty());
+        tree.validate().unwrap();
+
+        let empty: BTree<Vec<u8>, u32> = BTree::empty(3);
+        let compressed = empty.to_compressed();
+        assert!(compressed.is_empty());
+        compressed.validate().unwrap();
+    }
+}
@@ -0,0 +1,342 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+use core::fmt::Debug;
+
+use super::{BTree, InsertionMode, Node, OverflowPolicy, Summary};
+
+/// How the maximum number of keys in a node is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeSize {
+    /// Maximum number of children, one more than the maximum number of keys
+    Order(usize),
+    /// Largest number of keys that fits in a node of this many bytes
+    Bytes(usize),
+}
+
+/// Settings for a new `BTree`, checked when the tree is built
+///
+/// The order is the maximum number of children of a node, so a node holds
+/// at most one key less. Any order of at least 3 can be used, odd or even.
+/// By default the order is 5, and removal keeps every node but the root at
+/// least half full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BTreeConfig {
+    node_size: NodeSize,
+    min_keys_per_node: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    insertion_mode: InsertionMode,
+}
+
+/// Why `BTreeConfig::build` could not build a tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The order is below 3, so a node could not be split in two
+    OrderTooSmall { order: usize },
+    /// A node of `node_bytes` bytes has no room for two keys, which needs `needed` bytes
+    NodeBytesTooSmall { node_bytes: usize, needed: usize },
+    /// The minimum is 0, or more than half of the maximum, in which case two
+    /// nodes that are merged would not fit in one node
+    MinKeysOutOfRange {
+        min_keys_per_node: usize,
+        max_keys_per_node: usize,
+    },
+    /// Top-down insertion splits full nodes before they overflow, which needs
+    /// an order of at least 4
+    TopDownOrderTooSmall { order: usize },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::OrderTooSmall { order } => {
+                write!(f, "order must be at least 3, for 2 keys per node, got {order}")
+            }
+            ConfigError::NodeBytesTooSmall { node_bytes, needed } => write!(
+                f,
+                "a node of {node_bytes} bytes cannot hold 2 keys, that needs at least {needed} bytes"
+            ),
+            ConfigError::MinKeysOutOfRange {
+                min_keys_per_node,
+                max_keys_per_node,
+            } => write!(
+                f,
+                "minimum number of keys per node must be between 1 and {}, got {min_keys_per_node}",
+                max_keys_per_node / 2
+            ),
+            ConfigError::TopDownOrderTooSmall { order } => {
+                write!(f, "top-down insertion needs an order of at least 4, got {order}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConfigError {}
+
+impl Default for BTreeConfig {
+    fn default() -> Self {
+        BTreeConfig {
+            node_size: NodeSize::Order(5),
+            min_keys_per_node: None,
+            overflow_policy: OverflowPolicy::default(),
+            insertion_mode: InsertionMode::default(),
+        }
+    }
+}
+
+impl BTreeConfig {
+    pub fn new() -> Self {
+        BTreeConfig::default()
+    }
+
+    /// Allow nodes to have at most `order` children, and `order - 1` keys
+    pub fn order(mut self, order: usize) -> Self {
+        self.node_size = NodeSize::Order(order);
+        self
+    }
+
+    /// Allow nodes to have at most `max_keys_per_node` keys, like `order(max_keys_per_node + 1)`
+    pub fn max_keys_per_node(self, max_keys_per_node: usize) -> Self {
+        self.order(max_keys_per_node.saturating_add(1))
+    }
+
+    /// Derive the order from the memory a node may take up
+    ///
+    /// Uses as many keys as fit in `node_bytes`, counting the node itself and
+    /// `size_of` a key, a value and a child for every key, as in
+    /// `TreeStats::estimated_heap_bytes`. Memory that the keys or values point
+    /// to is not counted. Replaces the order set before.
+    pub fn node_bytes(mut self, node_bytes: usize) -> Self {
+        self.node_size = NodeSize::Bytes(node_bytes);
+        self
+    }
+
+    /// Keep at least `min_keys_per_node` keys in every node but the root when removing
+    ///
+    /// Below it, a node borrows a key from or is merged with a sibling. A lower
+    /// minimum means less rebalancing on removal, but sparser nodes. Must be
+    /// between 1 and half of the maximum number of keys, which is the default.
+    pub fn min_keys_per_node(mut self, min_keys_per_node: usize) -> Self {
+        self.min_keys_per_node = Some(min_keys_per_node);
+        self
+    }
+
+    /// See `BTree::with_overflow_policy`
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// See `BTree::with_insertion_mode`
+    pub fn insertion_mode(mut self, insertion_mode: InsertionMode) -> Self {
+        self.insertion_mode = insertion_mode;
+        self
+    }
+
+    /// Build an empty tree with these settings
+    pub fn build<T, V>(&self) -> Result<BTree<T, V>, ConfigError>
+    where
+        T: PartialOrd + Clone + Debug,
+        V: Clone + Debug,
+    {
+        self.build_with_summary()
+    }
+
+    /// Build an empty tree with these settings, that caches a summary `S` in every node
+    ///
+    /// See `BTree::empty_with_summary`.
+    pub fn build_with_summary<T, V, S>(&self) -> Result<BTree<T, V, S>, ConfigError>
+    where
+        T: PartialOrd + Clone + Debug,
+        V: Clone + Debug,
+        S: Summary<T, V>,
+    {
+        let order = match self.node_size {
+            NodeSize::Order(order) => order,
+            NodeSize::Bytes(node_bytes) => {
+                // A node with room for a single key could not be split
+                let bytes_for = |keys| BTree::<T, V, S>::node_bytes(keys, keys, keys + 1);
+                let needed = bytes_for(2);
+                if node_bytes < needed {
+                    return Err(ConfigError::NodeBytesTooSmall { node_bytes, needed });
+                }
+                let per_key = bytes_for(1) - bytes_for(0);
+                (node_bytes - bytes_for(0)) / per_key + 1
+            }
+        };
+        if order < 3 {
+            return Err(ConfigError::OrderTooSmall { order });
+        }
+        if self.insertion_mode == InsertionMode::TopDown && order < 4 {
+            return Err(ConfigError::TopDownOrderTooSmall { order });
+        }
+        let max_keys_per_node = order - 1;
+        let min_keys_per_node = self.min_keys_per_node.unwrap_or(max_keys_per_node / 2);
+        if min_keys_per_node < 1 || min_keys_per_node > max_keys_per_node / 2 {
+            return Err(ConfigError::MinKeysOutOfRange {
+                min_keys_per_node,
+                max_keys_per_node,
+            });
+        }
+        Ok(BTree {
+            max_keys_per_node,
+            min_keys_per_node,
+            overflow_policy: self.overflow_policy,
+            insertion_mode: self.insertion_mode,
+            root: Rc::new(RefCell::new(Node::new_empty())),
+            observer: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::string::ToString;
+
+    use super::{BTreeConfig, ConfigError};
+    use crate::test_util::assert_matches;
+    use crate::{BTree, InsertionMode};
+
+    #[test]
+    fn build_checks_the_settings() {
+        let tree: BTree<u32, u32> = BTreeConfig::new().order(7).build().unwrap();
+        assert_eq!(tree.max_keys_per_node(), 6);
+        assert_eq!(tree.min_keys_per_node(), 3);
+        assert_eq!(
+            BTreeConfig::new().order(2).build::<u32, u32>().unwrap_err(),
+            ConfigError::OrderTooSmall { order: 2 }
+        );
+        assert_eq!(
+            BTreeConfig::new()
+                .order(3)
+                .insertion_mode(InsertionMode::TopDown)
+                .build::<u32, u32>()
+                .unwrap_err(),
+            ConfigError::TopDownOrderTooSmall { order: 3 }
+        );
+        assert_eq!(
+            BTreeConfig::new()
+                .order(7)
+                .min_keys_per_node(4)
+                .build::<u32, u32>()
+                .unwrap_err(),
+            ConfigError::MinKeysOutOfRange {
+                min_keys_per_node: 4,
+                max_keys_per_node: 6
+            }
+        );
+        assert!(matches!(
+            BTreeConfig::new().node_bytes(8).build::<u32, u32>(),
+            Err(ConfigError::NodeBytesTooSmall { node_bytes: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn odd_orders_and_low_minimums_keep_a_valid_tree() {
+        for (order, min_keys_per_node) in [(3, 1), (5, 1), (5, 2), (6, 2), (9, 1)] {
+            let mut tree = BTreeConfig::new()
+                .order(order)
+                .min_keys_per_node(min_keys_per_node)
+                .build()
+                .unwrap();
+            let mut map = BTreeMap::new();
+            for key in 0..300 {
+                let key = key * 7 % 300;
+                tree.insert(key, key).unwrap();
+                map.insert(key, key);
+            }
+            assert_matches(&tree, &map);
+            for key in (0..300).filter(|key| key % 3 != 0) {
+                assert_eq!(tree.remove(key).ok(), map.remove(&key));
+            }
+            assert_matches(&tree, &map);
+        }
+    }
+
+    #[test]
+    fn the_minimum_is_at_most_half_of_the_maximum() {
+        // Order 6 holds 5 keys, and two nodes of 2 keys and their separator fit in one node
+        let build = |min_keys_per_node| {
+            BTreeConfig::new()
+                .order(6)
+                .min_keys_per_node(min_keys_per_node)
+                .build::<u32, u32>()
+        };
+        assert_eq!(build(2).unwrap().min_keys_per_node(), 2);
+        for min_keys_per_node in [0, 3] {
+            assert_eq!(
+                build(min_keys_per_node).unwrap_err(),
+                ConfigError::MinKeysOutOfRange {
+                    min_keys_per_node,
+                    max_keys_per_node: 5
+                }
+            );
+        }
+        assert_eq!(
+            build(3).unwrap_err().to_string(),
+            "minimum number of keys per node must be between 1 and 2, got 3"
+        );
+    }
+
+    #[test]
+    fn node_bytes_fits_as_many_keys_as_possible() {
+        let small: BTree<u32, u32> = BTreeConfig::new().node_bytes(256).build().unwrap();
+        let large: BTree<u32, u32> = BTreeConfig::new().node_bytes(1024).build().unwrap();
+        assert!(small.max_keys_per_node() >= 2);
+        assert!(large.max_keys_per_node() > small.max_keys_per_node());
+
+        // Larger values leave room for fewer keys in the same node size
+        let wide: BTree<u32, [u64; 8]> = BTreeConfig::new().node_bytes(1024).build().unwrap();
+        assert!(wide.max_keys_per_node() < large.max_keys_per_node());
+    }
+
+    #[test]
+    fn node_bytes_on_the_boundary() {
+        let bytes_for = |keys| BTree::<u32, u32>::node_bytes(keys, keys, keys + 1);
+        let max_keys = |node_bytes| {
+            BTreeConfig::new()
+                .node_bytes(node_bytes)
+                .build::<u32, u32>()
+                .map(|tree| tree.max_keys_per_node())
+        };
+        assert_eq!(max_keys(bytes_for(2)), Ok(2));
+        assert_eq!(
+            max_keys(bytes_for(2) - 1),
+            Err(ConfigError::NodeBytesTooSmall {
+                node_bytes: bytes_for(2) - 1,
+                needed: bytes_for(2)
+            })
+        );
+        assert_eq!(max_keys(bytes_for(10)), Ok(10));
+        assert_eq!(max_keys(bytes_for(10) - 1), Ok(9));
+    }
+
+    #[test]
+    fn the_last_node_size_wins() {
+        let tree: BTree<u32, u32> = BTreeConfig::new()
+            .node_bytes(8)
+            .max_keys_per_node(4)
+            .build()
+            .unwrap();
+        assert_eq!(tree.max_keys_per_node(), 4);
+        assert!(BTreeConfig::new()
+            .max_keys_per_node(4)
+            .node_bytes(8)
+            .build::<u32, u32>()
+            .is_err());
+        // One key more than the largest order saturates instead of overflowing
+        let tree: BTree<u32, u32> = BTreeConfig::new()
+            .max_keys_per_node(usize::MAX)
+            .build()
+            .unwrap();
+        assert_eq!(tree.max_keys_per_node(), usize::MAX - 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "top-down insertion needs an order of at least 4")]
+    fn top_down_insertion_is_rejected_for_order_3() {
+        let _ = BTree::<u32, u32>::empty(2).with_insertion_mode(InsertionMode::TopDown);
+    }
+}
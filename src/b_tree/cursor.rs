@@ -0,0 +1,412 @@
+use core::cell::RefMut;
+use core::fmt::Debug;
+use core::ops::Bound;
+
+use super::{BTree, Summary};
+
+/// A read-only position in a `BTree` that can move in both directions
+///
+/// The cursor either points at an entry, or at the "ghost" position past the
+/// end of the tree, which sits between the last and the first entry. The
+/// position is remembered by key and every move looks up the neighbouring key,
+/// so it costs O(log n) but never refers to a node that was split or merged.
+/// Every move also clones the key it lands on, and the entry it returns.
+/// Walking a whole tree this way costs O(n log n), so use `BTree::range`
+/// for plain iteration, which keeps a stack of nodes and costs O(n).
+pub struct Cursor<'a, T, V, S = ()>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    tree: &'a BTree<T, V, S>,
+    current: Option<T>,
+}
+
+/// A position in a `BTree` that can move in both directions and edit the tree
+///
+/// Works like `Cursor`, and can also change the value of the current entry,
+/// insert entries directly before or after it, and remove it. Since the
+/// position is remembered by key, it stays valid when these edits split or
+/// merge nodes. Like for `Cursor`, every move searches the tree again from the
+/// root in O(log n) and clones the key and entry, and the edits cost as much
+/// as the `BTree` method they call.
+pub struct CursorMut<'a, T, V, S = ()>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    tree: &'a mut BTree<T, V, S>,
+    current: Option<T>,
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Cursor at the first entry whose key is inside `bound`, used as a lower bound
+    ///
+    /// Points at the ghost position if there is no such entry.
+    pub fn cursor_at(&self, bound: Bound<&T>) -> Cursor<'_, T, V, S> {
+        Cursor {
+            current: self.first_key_from(bound),
+            tree: self,
+        }
+    }
+
+    /// Cursor at the entry with the smallest key
+    pub fn cursor_front(&self) -> Cursor<'_, T, V, S> {
+        self.cursor_at(Bound::Unbounded)
+    }
+
+    /// Cursor at the entry with the largest key
+    pub fn cursor_back(&self) -> Cursor<'_, T, V, S> {
+        Cursor {
+            current: self.last_key_value().map(|(key, _)| key),
+            tree: self,
+        }
+    }
+
+    /// Mutable cursor at the first entry whose key is inside `bound`, used as a lower bound
+    pub fn cursor_at_mut(&mut self, bound: Bound<&T>) -> CursorMut<'_, T, V, S> {
+        CursorMut {
+            current: self.first_key_from(bound),
+            tree: self,
+        }
+    }
+
+    /// Mutable cursor at the entry with the smallest key
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T, V, S> {
+        self.cursor_at_mut(Bound::Unbounded)
+    }
+
+    /// Mutable cursor at the entry with the largest key
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T, V, S> {
+        CursorMut {
+            current: self.last_key_value().map(|(key, _)| key),
+            tree: self,
+        }
+    }
+
+    fn first_key_from(&self, bound: Bound<&T>) -> Option<T> {
+        let entry = match bound {
+            Bound::Included(key) => self.ceiling(key),
+            Bound::Excluded(key) => self.higher(key),
+            Bound::Unbounded => self.first_key_value(),
+        };
+        entry.map(|(key, _)| key)
+    }
+
+    /// The entry after `current`, or the first entry after the ghost position
+    fn entry_after(&self, current: &Option<T>) -> Option<(T, V)> {
+        match current {
+            Some(key) => self.higher(key),
+            None => self.first_key_value(),
+        }
+    }
+
+    /// The entry before `current`, or the last entry before the ghost position
+    fn entry_before(&self, current: &Option<T>) -> Option<(T, V)> {
+        match current {
+            Some(key) => self.lower(key),
+            None => self.last_key_value(),
+        }
+    }
+}
+
+impl<'a, T, V, S> Cursor<'a, T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Key of the current entry, None at the ghost position
+    pub fn key(&self) -> Option<T> {
+        self.current.clone()
+    }
+
+    /// Value of the current entry, None at the ghost position
+    pub fn value(&self) -> Option<V> {
+        self.tree.get(self.current.clone()?)
+    }
+
+    /// Move to the next entry and return it
+    ///
+    /// Moving past the last entry goes to the ghost position and returns None,
+    /// moving on from there starts again at the first entry.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(T, V)> {
+        let entry = self.tree.entry_after(&self.current);
+        self.current = entry.as_ref().map(|(key, _)| key.clone());
+        entry
+    }
+
+    /// Move to the previous entry and return it
+    pub fn prev(&mut self) -> Option<(T, V)> {
+        let entry = self.tree.entry_before(&self.current);
+        self.current = entry.as_ref().map(|(key, _)| key.clone());
+        entry
+    }
+
+    /// The entry `next` would move to, without moving
+    pub fn peek_next(&self) -> Option<(T, V)> {
+        self.tree.entry_after(&self.current)
+    }
+
+    /// The entry `prev` would move to, without moving
+    pub fn peek_prev(&self) -> Option<(T, V)> {
+        self.tree.entry_before(&self.current)
+    }
+}
+
+impl<'a, T, V> CursorMut<'a, T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    /// Mutable access to the value of the current entry, None at the ghost position
+    ///
+    /// Like `BTree::get_mut`, only available without a summary.
+    pub fn value_mut(&mut self) -> Option<RefMut<'_, V>> {
+        let key = self.current.clone()?;
+        self.tree.get_mut(key)
+    }
+}
+
+impl<'a, T, V, S> CursorMut<'a, T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Key of the current entry, None at the ghost position
+    pub fn key(&self) -> Option<T> {
+        self.current.clone()
+    }
+
+    /// Move to the next entry and return it
+    ///
+    /// Moving past the last entry goes to the ghost position and returns None,
+    /// moving on from there starts again at the first entry.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(T, V)> {
+        let entry = self.tree.entry_after(&self.current);
+        self.current = entry.as_ref().map(|(key, _)| key.clone());
+        entry
+    }
+
+    /// Move to the previous entry and return it
+    pub fn prev(&mut self) -> Option<(T, V)> {
+        let entry = self.tree.entry_before(&self.current);
+        self.current = entry.as_ref().map(|(key, _)| key.clone());
+        entry
+    }
+
+    /// The entry `next` would move to, without moving
+    pub fn peek_next(&self) -> Option<(T, V)> {
+        self.tree.entry_after(&self.current)
+    }
+
+    /// The entry `prev` would move to, without moving
+    pub fn peek_prev(&self) -> Option<(T, V)> {
+        self.tree.entry_before(&self.current)
+    }
+
+    /// Insert an entry between the previous and the current entry
+    ///
+    /// At the ghost position the entry goes after the last entry. Returns an
+    /// Err if the key does not sort between its new neighbours, the cursor
+    /// does not move.
+    pub fn insert_before(&mut self, key: T, value: V) -> Result<(), &'static str> {
+        let after_previous = match self.peek_prev() {
+            Some((previous_key, _)) => key > previous_key,
+            None => true,
+        };
+        let before_current = match &self.current {
+            Some(current_key) => key < *current_key,
+            None => true,
+        };
+        if !after_previous || !before_current {
+            return Err("Key out of order");
+        }
+        self.tree.insert(key, value)
+    }
+
+    /// Insert an entry between the current and the next entry
+    ///
+    /// At the ghost position the entry goes before the first entry. Returns an
+    /// Err if the key does not sort between its new neighbours, the cursor
+    /// does not move.
+    pub fn insert_after(&mut self, key: T, value: V) -> Result<(), &'static str> {
+        let after_current = match &self.current {
+            Some(current_key) => key > *current_key,
+            None => true,
+        };
+        let before_next = match self.peek_next() {
+            Some((next_key, _)) => key < next_key,
+            None => true,
+        };
+        if !after_current || !before_next {
+            return Err("Key out of order");
+        }
+        self.tree.insert(key, value)
+    }
+
+    /// Remove the current entry and move to the next one
+    ///
+    /// Returns None, and removes nothing, at the ghost position.
+    pub fn remove_current(&mut self) -> Option<(T, V)> {
+        let key = self.current.take()?;
+        self.current = self.tree.higher(&key).map(|(next_key, _)| next_key);
+        let value = self.tree.remove(key.clone()).ok()?;
+        Some((key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
+    use core::ops::Bound;
+
+    use super::BTree;
+    use crate::test_util::{assert_matches, configs, random_map, tree_from, Rng};
+
+    fn tree_of(keys: &[u32]) -> BTree<u32, u32> {
+        let mut tree = BTree::empty(3);
+        for key in keys {
+            tree.insert(*key, key * 2).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn cursor_at_starts_at_the_first_key_inside_the_bound() {
+        let tree = tree_of(&[10, 20, 30]);
+        assert_eq!(tree.cursor_at(Bound::Included(&20)).key(), Some(20));
+        assert_eq!(tree.cursor_at(Bound::Excluded(&20)).key(), Some(30));
+        assert_eq!(tree.cursor_at(Bound::Included(&15)).key(), Some(20));
+        assert_eq!(tree.cursor_at(Bound::Unbounded).key(), Some(10));
+        assert_eq!(tree.cursor_at(Bound::Excluded(&30)).key(), None);
+        assert_eq!(tree.cursor_front().key(), Some(10));
+        assert_eq!(tree.cursor_back().key(), Some(30));
+        assert_eq!(tree.cursor_back().value(), Some(60));
+
+        let empty: BTree<u32, u32> = BTree::empty(3);
+        assert_eq!(empty.cursor_front().key(), None);
+        assert_eq!(empty.cursor_back().key(), None);
+    }
+
+    #[test]
+    fn moving_past_either_end_wraps_around_through_the_ghost_position() {
+        let tree = tree_of(&[1, 2, 3]);
+        let mut cursor = tree.cursor_back();
+        assert_eq!(cursor.peek_next(), None);
+        assert_eq!(cursor.next(), None);
+        assert_eq!(cursor.key(), None);
+        assert_eq!(cursor.value(), None);
+        assert_eq!(cursor.peek_next(), Some((1, 2)));
+        assert_eq!(cursor.peek_prev(), Some((3, 6)));
+        assert_eq!(cursor.next(), Some((1, 2)));
+        assert_eq!(cursor.peek_prev(), None);
+        assert_eq!(cursor.prev(), None);
+        assert_eq!(cursor.prev(), Some((3, 6)));
+        assert_eq!(cursor.prev(), Some((2, 4)));
+        assert_eq!(cursor.key(), Some(2));
+    }
+
+    #[test]
+    fn walking_both_ways_matches_btreemap() {
+        let mut rng = Rng::new(33);
+        for config in configs() {
+            let map = random_map(&mut rng, 60, 200);
+            let tree = tree_from(&config, &map);
+            let entries: Vec<_> = map.iter().map(|(key, value)| (*key, *value)).collect();
+            let mut cursor = tree.cursor_front();
+            let mut forward = Vec::from([(cursor.key().unwrap(), cursor.value().unwrap())]);
+            while let Some(entry) = cursor.next() {
+                forward.push(entry);
+            }
+            assert_eq!(forward, entries);
+            let mut backward = Vec::new();
+            while let Some(entry) = cursor.prev() {
+                backward.push(entry);
+            }
+            backward.reverse();
+            assert_eq!(backward, entries);
+        }
+    }
+
+    #[test]
+    fn inserts_next_to_the_cursor_must_keep_the_order() {
+        let mut tree = tree_of(&[10, 20, 30]);
+        let mut cursor = tree.cursor_at_mut(Bound::Included(&20));
+        assert_eq!(cursor.insert_before(10, 0), Err("Key out of order"));
+        assert_eq!(cursor.insert_before(20, 0), Err("Key out of order"));
+        assert_eq!(cursor.insert_before(25, 0), Err("Key out of order"));
+        assert_eq!(cursor.insert_after(20, 0), Err("Key out of order"));
+        assert_eq!(cursor.insert_after(30, 0), Err("Key out of order"));
+        assert_eq!(cursor.insert_after(15, 0), Err("Key out of order"));
+        cursor.insert_before(15, 0).unwrap();
+        cursor.insert_after(25, 0).unwrap();
+        // The cursor stays where it was
+        assert_eq!(cursor.key(), Some(20));
+        assert_eq!(cursor.peek_prev(), Some((15, 0)));
+        assert_eq!(cursor.peek_next(), Some((25, 0)));
+
+        // At the ghost position, insert_before appends and insert_after prepends
+        let mut cursor = tree.cursor_back_mut();
+        cursor.next();
+        assert_eq!(cursor.insert_before(5, 0), Err("Key out of order"));
+        assert_eq!(cursor.insert_after(35, 0), Err("Key out of order"));
+        cursor.insert_before(35, 0).unwrap();
+        cursor.insert_after(5, 0).unwrap();
+        let keys: Vec<_> = tree.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, [5, 10, 15, 20, 25, 30, 35]);
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn value_mut_changes_the_current_entry() {
+        let mut tree = tree_of(&[1, 2, 3]);
+        let mut cursor = tree.cursor_front_mut();
+        cursor.next();
+        *cursor.value_mut().unwrap() = 100;
+        cursor.next();
+        cursor.next();
+        assert!(cursor.value_mut().is_none());
+        assert_eq!(tree.get(2), Some(100));
+    }
+
+    #[test]
+    fn removing_every_entry_through_the_cursor_keeps_the_tree_valid() {
+        let mut rng = Rng::new(34);
+        for config in configs() {
+            let mut map = random_map(&mut rng, 80, 300);
+            let mut tree = tree_from(&config, &map);
+            let mut cursor = tree.cursor_front_mut();
+            let mut removed = BTreeMap::new();
+            // Remove every other entry, moving over the ones in between
+            while cursor.key().is_some() {
+                let (key, value) = cursor.remove_current().unwrap();
+                removed.insert(key, value);
+                cursor.next();
+            }
+            assert_eq!(cursor.remove_current(), None);
+            map.retain(|key, _| !removed.contains_key(key));
+            assert_matches(&tree, &map);
+            let mut cursor = tree.cursor_back_mut();
+            while cursor.key().is_some() {
+                cursor.remove_current().unwrap();
+                cursor.prev();
+            }
+            // Removing the last entry moves to the ghost position, and moving
+            // back from there reaches the new last entry, until none are left
+            assert!(tree.is_empty());
+            tree.validate().unwrap();
+        }
+    }
+}
@@ -0,0 +1,573 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::Debug;
+use core::ops::{Bound, RangeBounds};
+
+use super::{BTree, Node, OwnedKeyPosition, Path, Summary, TreeObserver};
+
+/// Iterator that removes and yields the entries in a range that match a predicate
+///
+/// Created by `BTree::extract_if`. The range is cut out of the tree when the
+/// iterator is created, and is walked through in place until the predicate
+/// first matches. From then on the entries are moved out of the nodes, and the
+/// entries that are kept are built into new nodes. The range is joined back in
+/// when the iterator is dropped, with its original nodes if nothing was
+/// extracted. Entries that were not visited yet at that point are kept as well.
+/// An observer of the tree is told about both steps, so it sees the tree
+/// without the range while the iterator is alive.
+pub struct ExtractIf<'a, T, V, F, S = ()>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    F: FnMut(&T, &mut V) -> bool,
+    S: Summary<T, V>,
+{
+    tree: &'a mut BTree<T, V, S>,
+    // All entries inside the range, as long as none were extracted
+    middle: BTree<T, V, S>,
+    // Nodes of `middle` on the way to the next entry, with its position
+    stack: Path<T, V, S>,
+    // Number of entries of `middle` that the predicate did not match
+    visited: usize,
+    // The entries after the first extracted one, once they are moved out of `middle`
+    entries: Option<alloc::vec::IntoIter<(T, V)>>,
+    kept: Vec<(T, V)>,
+    // All entries after the range, joined back in last
+    after: BTree<T, V, S>,
+    predicate: F,
+}
+
+/// Iterator over the entries that `BTree::drain` removed, in key order
+///
+/// Owns the entries, which are moved out of the removed nodes when the
+/// iterator is created, so the tree can be used again right away.
+pub struct Drain<T, V> {
+    entries: alloc::vec::IntoIter<(T, V)>,
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Keep only the entries for which `keep` returns true
+    ///
+    /// Visits all entries in key order, and may change the values it keeps.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&T, &mut V) -> bool,
+    {
+        self.extract_if(.., |key, value| !keep(key, value))
+            .for_each(drop);
+    }
+
+    /// Remove the entries inside `range` for which `predicate` returns true
+    ///
+    /// Returns an iterator over the removed entries, in key order. Rather than
+    /// removing the entries one by one, the range is split off the tree and
+    /// the kept entries are built into new nodes in a single pass, so every
+    /// node in the range is only rebalanced once.
+    pub fn extract_if<R, F>(&mut self, range: R, predicate: F) -> ExtractIf<'_, T, V, F, S>
+    where
+        R: RangeBounds<T>,
+        F: FnMut(&T, &mut V) -> bool,
+    {
+        let (middle, after) =
+            self.observe_bulk(|tree, observer| tree.split_range(&range, observer));
+        let mut stack = Vec::new();
+        BTree::push_leftmost(&mut stack, Rc::clone(&middle.root));
+        ExtractIf {
+            middle,
+            stack,
+            visited: 0,
+            entries: None,
+            kept: Vec::new(),
+            after,
+            predicate,
+            tree: self,
+        }
+    }
+
+    /// Remove all entries inside `range`, returns how many were removed
+    ///
+    /// Cuts the tree at both ends of the range and joins the outer parts back
+    /// together, so only the nodes along the two boundary paths are touched.
+    /// Subtrees that lie entirely inside the range are dropped as a whole.
+    pub fn remove_range<R: RangeBounds<T>>(&mut self, range: R) -> usize {
+        self.observe_bulk(|tree, observer| {
+            let (middle, mut after) = tree.split_range(&range, observer);
+            tree.append_observed(&mut after, observer);
+            middle.len()
+        })
+    }
+
+    /// Remove all entries inside `range`, and iterate over them in key order
+    ///
+    /// Works like `remove_range`, the entries are removed from the tree right
+    /// away, even if the iterator is dropped before it is used up.
+    pub fn drain<R: RangeBounds<T>>(&mut self, range: R) -> Drain<T, V> {
+        let middle = self.observe_bulk(|tree, observer| {
+            let (middle, mut after) = tree.split_range(&range, observer);
+            tree.append_observed(&mut after, observer);
+            middle
+        });
+        Drain {
+            entries: middle.into_entries().into_iter(),
+        }
+    }
+
+    /// Split the tree into the entries before, inside and after `range`
+    ///
+    /// The entries before the range stay in this tree, the other two parts are
+    /// returned. Only the changes to this tree are reported to `observer`.
+    fn split_range<R, O>(&mut self, range: &R, observer: &mut O) -> (Self, Self)
+    where
+        R: RangeBounds<T>,
+        O: TreeObserver<T> + ?Sized,
+    {
+        let mut middle = match range.start_bound() {
+            Bound::Included(start) => self.split_off_observed(start, observer),
+            Bound::Excluded(start) => {
+                let mut middle = self.split_off_observed(start, observer);
+                if let Some((key, value)) = middle.pop_first_if(|key| key == start) {
+                    let _ = self.insert_observed(key, value, observer);
+                }
+                middle
+            }
+            Bound::Unbounded => {
+                // Only the nodes move into `middle`, the observer stays with this tree
+                let root =
+                    core::mem::replace(&mut self.root, Rc::new(RefCell::new(Node::new_empty())));
+                self.with_root(root)
+            }
+        };
+        let after = match range.end_bound() {
+            Bound::Included(end) => {
+                let mut after = middle.split_off(end);
+                if let Some((key, value)) = after.pop_first_if(|key| key == end) {
+                    let _ = middle.insert(key, value);
+                }
+                after
+            }
+            Bound::Excluded(end) => middle.split_off(end),
+            Bound::Unbounded => middle.with_root(Rc::new(RefCell::new(Node::new_empty()))),
+        };
+        (middle, after)
+    }
+
+    /// Push the path to the smallest key of the subtree below `node` onto `stack`
+    fn push_leftmost(stack: &mut Path<T, V, S>, node: Rc<RefCell<Node<T, V, S>>>) {
+        let mut current_node = node;
+        loop {
+            let child = current_node.borrow().children.first().cloned();
+            stack.push((current_node, 0));
+            match child {
+                Some(child) => current_node = child,
+                None => break,
+            }
+        }
+    }
+
+    /// Remove the entry with the smallest key, if that key matches `predicate`
+    fn pop_first_if<F: FnOnce(&T) -> bool>(&mut self, predicate: F) -> Option<(T, V)> {
+        let (key, _) = self.select(0)?;
+        if !predicate(key) {
+            return None;
+        }
+        self.pop_first()
+    }
+}
+
+impl<T, V> Iterator for Drain<T, V> {
+    type Item = (T, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+}
+
+impl<'a, T, V, F, S> Iterator for ExtractIf<'a, T, V, F, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    F: FnMut(&T, &mut V) -> bool,
+    S: Summary<T, V>,
+{
+    type Item = (T, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.entries.is_none() {
+            while let Some((node, i)) = self.next_in_middle() {
+                let extract = {
+                    let borrowed_node = &mut *node.borrow_mut();
+                    (self.predicate)(&borrowed_node.keys[i], &mut borrowed_node.values[i])
+                };
+                if extract {
+                    return self.move_out_of_middle();
+                }
+                self.visited += 1;
+            }
+            return None;
+        }
+        let entries = self.entries.as_mut()?;
+        for (key, mut value) in entries.by_ref() {
+            if (self.predicate)(&key, &mut value) {
+                return Some((key, value));
+            }
+            self.kept.push((key, value));
+        }
+        None
+    }
+}
+
+impl<'a, T, V, F, S> ExtractIf<'a, T, V, F, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    F: FnMut(&T, &mut V) -> bool,
+    S: Summary<T, V>,
+{
+    /// Move on to the next entry of `middle`, returns the node holding it and its position
+    ///
+    /// The predicate may have changed the values of a node, so the summary of
+    /// every node is recomputed once all entries below it are visited.
+    fn next_in_middle(&mut self) -> Option<OwnedKeyPosition<T, V, S>> {
+        loop {
+            let (node, i) = {
+                let (node, i) = self.stack.last_mut()?;
+                *i += 1;
+                (Rc::clone(node), *i - 1)
+            };
+            let child = {
+                let borrowed_node = node.borrow();
+                if i >= borrowed_node.keys.len() {
+                    drop(borrowed_node);
+                    BTree::update_summary(&node);
+                    self.stack.pop();
+                    continue;
+                }
+                borrowed_node.children.get(i + 1).cloned()
+            };
+            // The keys in the child after this key come next
+            if let Some(child) = child {
+                BTree::push_leftmost(&mut self.stack, child);
+            }
+            return Some((node, i));
+        }
+    }
+
+    /// Move all entries out of `middle`, and return the one that was just matched
+    ///
+    /// The entries before it are kept, the ones after it are visited next.
+    fn move_out_of_middle(&mut self) -> Option<(T, V)> {
+        self.stack.clear();
+        let empty = self
+            .middle
+            .with_root(Rc::new(RefCell::new(Node::new_empty())));
+        let mut entries = core::mem::replace(&mut self.middle, empty).into_entries();
+        let rest = entries.split_off(self.visited + 1);
+        let extracted = entries.pop();
+        self.kept = entries;
+        self.entries = Some(rest.into_iter());
+        extracted
+    }
+}
+
+impl<'a, T, V, F, S> Drop for ExtractIf<'a, T, V, F, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    F: FnMut(&T, &mut V) -> bool,
+    S: Summary<T, V>,
+{
+    fn drop(&mut self) {
+        match self.entries.take() {
+            Some(entries) => {
+                let mut kept = core::mem::take(&mut self.kept);
+                kept.extend(entries);
+                let root = self
+                    .tree
+                    .build_from_sorted(kept, self.tree.max_keys_per_node);
+                self.middle.root = root;
+            }
+            None => {
+                // Nothing was extracted, so the nodes of the range can be joined
+                // back as they are. Only the nodes that were not left yet still
+                // need their summaries recomputed, deepest first.
+                for (node, _) in self.stack.drain(..).rev() {
+                    BTree::update_summary(&node);
+                }
+            }
+        }
+        // Both parts come after everything in the tree, so they are joined on
+        self.tree.observe_bulk(|tree, observer| {
+            tree.append_observed(&mut self.middle, observer);
+            tree.append_observed(&mut self.after, observer);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
+    use core::ops::{Bound, RangeBounds};
+
+    use super::Summary;
+    use crate::test_util::{assert_matches, configs, random_map, tree_from, Rng};
+    use crate::BTree;
+
+    fn random_bounds(rng: &mut Rng) -> (Bound<u32>, Bound<u32>) {
+        let bound = |rng: &mut Rng| {
+            let key = rng.below(600);
+            match rng.below(3) {
+                0 => Bound::Included(key),
+                1 => Bound::Excluded(key),
+                _ => Bound::Unbounded,
+            }
+        };
+        let start = bound(rng);
+        let end = bound(rng);
+        match (start, end) {
+            // BTreeMap panics on ranges that end before they start
+            (Bound::Included(a) | Bound::Excluded(a), Bound::Included(b) | Bound::Excluded(b))
+                if a >= b =>
+            {
+                (Bound::Included(b), Bound::Included(a))
+            }
+            bounds => bounds,
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Sum(u64);
+
+    impl Summary<u32, u32> for Sum {
+        fn identity() -> Self {
+            Sum(0)
+        }
+
+        fn from_entry(_key: &u32, value: &u32) -> Self {
+            Sum(u64::from(*value))
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            Sum(self.0 + other.0)
+        }
+    }
+
+    #[test]
+    fn extracting_nothing_keeps_the_nodes_and_updates_changed_values() {
+        for config in configs() {
+            let mut tree: BTree<u32, u32, Sum> = config.build_with_summary().unwrap();
+            for key in 0..200 {
+                tree.insert(key, 1).unwrap();
+            }
+            // Doubles the values in the range without extracting any of them
+            let extracted = tree
+                .extract_if(50..150, |_, value| {
+                    *value *= 2;
+                    false
+                })
+                .count();
+            assert_eq!(extracted, 0);
+            assert_eq!(tree.aggregate(..), Sum(300));
+            tree.validate().unwrap();
+
+            // Dropped before it is used, nothing is visited
+            drop(tree.extract_if(.., |_, _| true));
+            assert_eq!(tree.len(), 200);
+
+            let levels = tree.levels();
+            let extracted = tree
+                .extract_if(.., |_, value| {
+                    *value += 1;
+                    false
+                })
+                .count();
+            assert_eq!(extracted, 0);
+            assert_eq!(tree.aggregate(..), Sum(500));
+            // The whole tree was the range, and its nodes were put back as they were
+            assert_eq!(tree.levels(), levels);
+
+            let mut extract_if = tree.extract_if(100.., |_, value| {
+                *value = 0;
+                false
+            });
+            assert_eq!(extract_if.next(), None);
+            drop(extract_if);
+            assert_eq!(tree.aggregate(..), Sum(250));
+            tree.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn extract_if_matches_btreemap() {
+        let mut rng = Rng::new(5);
+        for config in configs() {
+            for _ in 0..10 {
+                let len = rng.below(300) as usize;
+                let mut map = random_map(&mut rng, len, 600);
+                let mut tree = tree_from(&config, &map);
+                let bounds = random_bounds(&mut rng);
+                let divisor = rng.below(3) + 1;
+                let extracted: Vec<_> = tree
+                    .extract_if(bounds, |key, _| key % divisor == 0)
+                    .collect();
+                let expected: Vec<_> = map
+                    .range(bounds)
+                    .filter(|(key, _)| *key % divisor == 0)
+                    .map(|(key, value)| (*key, *value))
+                    .collect();
+                for (key, _) in &expected {
+                    map.remove(key);
+                }
+                assert_eq!(extracted, expected);
+                assert_matches(&tree, &map);
+            }
+        }
+    }
+
+    #[test]
+    fn dropped_extract_if_keeps_the_unvisited_entries() {
+        let mut rng = Rng::new(6);
+        for config in configs() {
+            for _ in 0..10 {
+                let len = rng.below(300) as usize;
+                let mut map = random_map(&mut rng, len, 600);
+                let mut tree = tree_from(&config, &map);
+                let bounds = random_bounds(&mut rng);
+                let taken = rng.below(20) as usize;
+                let extracted: Vec<_> = tree.extract_if(bounds, |_, _| true).take(taken).collect();
+                let expected: Vec<_> = map
+                    .range(bounds)
+                    .take(taken)
+                    .map(|(key, value)| (*key, *value))
+                    .collect();
+                for (key, _) in &expected {
+                    map.remove(key);
+                }
+                assert_eq!(extracted, expected);
+                assert_matches(&tree, &map);
+            }
+        }
+    }
+
+    #[test]
+    fn extract_if_at_the_ends_of_the_range() {
+        for config in configs() {
+            let map: BTreeMap<_, _> = (0..100).map(|key| (key, key)).collect();
+            let mut tree = tree_from(&config, &map);
+            // Only the first and the last entry of the range match
+            let extracted: Vec<_> = tree
+                .extract_if(20..=60, |key, _| *key == 20 || *key == 60)
+                .collect();
+            assert_eq!(extracted, [(20, 20), (60, 60)]);
+            let mut expected = map.clone();
+            expected.remove(&20);
+            expected.remove(&60);
+            assert_matches(&tree, &expected);
+
+            // Stopping right after the first match keeps the rest of the range
+            let first = tree.extract_if(.., |key, _| *key >= 90).next();
+            assert_eq!(first, Some((90, 90)));
+            expected.remove(&90);
+            assert_matches(&tree, &expected);
+        }
+    }
+
+    #[test]
+    fn retain_everything_or_nothing() {
+        for config in configs() {
+            let map: BTreeMap<_, _> = (0..100).map(|key| (key, key)).collect();
+            let mut tree = tree_from(&config, &map);
+            tree.retain(|_, _| true);
+            assert_matches(&tree, &map);
+            tree.retain(|_, _| false);
+            assert!(tree.is_empty());
+            tree.validate().unwrap();
+            tree.retain(|_, _| unreachable!());
+        }
+    }
+
+    #[test]
+    fn retain_drain_and_remove_range_match_btreemap() {
+        let mut rng = Rng::new(7);
+        for config in configs() {
+            let mut map = random_map(&mut rng, 300, 600);
+            let mut tree = tree_from(&config, &map);
+            tree.retain(|key, value| {
+                *value += 1;
+                key % 3 != 0
+            });
+            map.retain(|key, value| {
+                *value += 1;
+                key % 3 != 0
+            });
+            assert_matches(&tree, &map);
+
+            for _ in 0..10 {
+                let bounds = random_bounds(&mut rng);
+                let drained: Vec<_> = tree.drain(bounds).collect();
+                let expected: Vec<_> = map
+                    .range(bounds)
+                    .map(|(key, value)| (*key, *value))
+                    .collect();
+                for (key, _) in &expected {
+                    map.remove(key);
+                }
+                assert_eq!(drained, expected);
+                assert_matches(&tree, &map);
+
+                let extra = random_map(&mut rng, 50, 600);
+                for (key, value) in extra {
+                    if tree.insert(key, value).is_ok() {
+                        map.insert(key, value);
+                    }
+                }
+
+                let bounds = random_bounds(&mut rng);
+                let removed = map.range(bounds).count();
+                assert_eq!(tree.remove_range(bounds), removed);
+                map.retain(|key, _| !bounds.contains(key));
+                assert_matches(&tree, &map);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_ranges_leave_the_tree_unchanged() {
+        for config in configs() {
+            let map: BTreeMap<_, _> = (0..100).map(|key| (key * 2, key)).collect();
+            let mut tree = tree_from(&config, &map);
+            assert_eq!(tree.remove_range(1000..), 0);
+            assert_eq!(tree.remove_range(51..52), 0);
+            assert_eq!(tree.drain(..0).count(), 0);
+            assert_matches(&tree, &map);
+        }
+    }
+
+    #[test]
+    fn a_dropped_drain_still_removes_the_whole_range() {
+        for config in configs() {
+            let map: BTreeMap<_, _> = (0..100).map(|key| (key, key)).collect();
+            let mut tree = tree_from(&config, &map);
+            let mut drain = tree.drain(10..90);
+            assert_eq!(drain.next(), Some((10, 10)));
+            drop(drain);
+            let expected: BTreeMap<_, _> = map
+                .iter()
+                .filter(|(key, _)| !(10..90).contains(*key))
+                .map(|(key, value)| (*key, *value))
+                .collect();
+            assert_matches(&tree, &expected);
+
+            assert_eq!(tree.remove_range(..), expected.len());
+            assert!(tree.is_empty());
+            tree.validate().unwrap();
+            assert_eq!(tree.drain(..).next(), None);
+        }
+    }
+}
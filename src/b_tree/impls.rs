@@ -0,0 +1,238 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+use core::hash::{Hash, Hasher};
+use core::ops::Index;
+
+use super::{BTree, Node, Summary};
+
+/// Copies every node, so the clone does not share any nodes with the original
+impl<T, V, S> Clone for BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    fn clone(&self) -> Self {
+        self.with_root(BTree::clone_node(&self.root))
+    }
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Copy `node` and everything below it, with the parent links pointing at the copies
+    fn clone_node(node: &Rc<RefCell<Node<T, V, S>>>) -> Rc<RefCell<Node<T, V, S>>> {
+        let borrowed_node = node.borrow();
+        let copy = Rc::new(RefCell::new(Node {
+            keys: borrowed_node.keys.clone(),
+            values: borrowed_node.values.clone(),
+            children: borrowed_node
+                .children
+                .iter()
+                .map(BTree::clone_node)
+                .collect(),
+            parent: None,
+            size: borrowed_node.size,
+            summary: borrowed_node.summary.clone(),
+        }));
+        BTree::adopt_children(&copy);
+        copy
+    }
+}
+
+/// Trees are equal when they hold the same entries, however their nodes are laid out
+impl<T, V, S> PartialEq for BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug + PartialEq,
+    S: Summary<T, V>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T, V, S> Eq for BTree<T, V, S>
+where
+    T: Ord + Clone + Debug,
+    V: Clone + Debug + Eq,
+    S: Summary<T, V>,
+{
+}
+
+/// Compares the entries of both trees in key order, like two sorted lists
+impl<T, V, S> PartialOrd for BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug + PartialOrd,
+    S: Summary<T, V>,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T, V, S> Ord for BTree<T, V, S>
+where
+    T: Ord + Clone + Debug,
+    V: Clone + Debug + Ord,
+    S: Summary<T, V>,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+/// Hashes the entries in key order, so equal trees have equal hashes
+impl<T, V, S> Hash for BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug + Hash,
+    V: Clone + Debug + Hash,
+    S: Summary<T, V>,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for (key, value) in self.iter() {
+            key.hash(state);
+            value.hash(state);
+        }
+    }
+}
+
+/// `tree[&key]` returns a reference to the value stored under `key`
+///
+/// Panics if the key is not in the tree.
+impl<T, V, S, Q> Index<&Q> for BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug + core::borrow::Borrow<Q>,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+    Q: PartialOrd + ?Sized,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get_ref(key).expect("key not found in BTree")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cmp::Ordering;
+    use core::hash::{Hash, Hasher};
+
+    use crate::test_util::{configs, random_map, tree_from, Rng};
+    use crate::{BTree, BTreeConfig, OverflowPolicy};
+
+    /// FNV-1a, so the tests do not need the standard library's hasher
+    struct Fnv(u64);
+
+    impl Hasher for Fnv {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3);
+            }
+        }
+    }
+
+    fn hash_of(tree: &BTree<u32, u32>) -> u64 {
+        let mut hasher = Fnv(0xcbf2_9ce4_8422_2325);
+        tree.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn clones_do_not_share_nodes_with_the_original() {
+        let mut rng = Rng::new(42);
+        for config in configs() {
+            let map = random_map(&mut rng, 200, 1000);
+            let mut tree = tree_from(&config, &map);
+            let mut copy = tree.clone();
+            copy.validate().unwrap();
+            assert_eq!(copy, tree);
+            for key in map.keys().take(100) {
+                copy.remove(*key).unwrap();
+            }
+            tree.insert(5000, 0).unwrap();
+            *tree.get_mut(*map.keys().last().unwrap()).unwrap() = 1;
+            tree.validate().unwrap();
+            copy.validate().unwrap();
+            assert_eq!(tree.len(), 201);
+            assert_eq!(copy.len(), 100);
+            let last = map.iter().last().unwrap();
+            assert_eq!(copy.get(*last.0), Some(*last.1));
+        }
+    }
+
+    #[test]
+    fn trees_with_the_same_entries_but_a_different_shape_are_equal() {
+        let split = BTreeConfig::new().order(3);
+        let redistribute = BTreeConfig::new()
+            .order(9)
+            .overflow_policy(OverflowPolicy::Redistribute);
+        let mut ascending: BTree<u32, u32> = split.build().unwrap();
+        let mut descending: BTree<u32, u32> = redistribute.build().unwrap();
+        for key in 0..100 {
+            ascending.insert(key, key * 3).unwrap();
+            descending.insert(99 - key, (99 - key) * 3).unwrap();
+        }
+        assert_ne!(ascending.levels().len(), descending.levels().len());
+        assert_eq!(ascending, descending);
+        assert_eq!(ascending.cmp(&descending), Ordering::Equal);
+        assert_eq!(ascending.partial_cmp(&descending), Some(Ordering::Equal));
+        assert_eq!(hash_of(&ascending), hash_of(&descending));
+
+        descending.insert(100, 0).unwrap();
+        assert_ne!(ascending, descending);
+        assert_ne!(hash_of(&ascending), hash_of(&descending));
+    }
+
+    #[test]
+    fn trees_compare_like_sorted_lists_of_entries() {
+        let tree_of = |entries: &[(u32, u32)]| {
+            let mut tree: BTree<u32, u32> = BTree::empty(3);
+            for (key, value) in entries {
+                tree.insert(*key, *value).unwrap();
+            }
+            tree
+        };
+        let empty = tree_of(&[]);
+        let short = tree_of(&[(1, 1)]);
+        let long = tree_of(&[(1, 1), (2, 2)]);
+        let larger_value = tree_of(&[(1, 2)]);
+        let larger_key = tree_of(&[(0, 0), (3, 0)]);
+        assert!(empty < short);
+        assert!(short < long);
+        assert!(long < larger_value);
+        assert!(larger_key < short);
+        assert_eq!(empty.cmp(&tree_of(&[])), Ordering::Equal);
+        assert_eq!(hash_of(&empty), hash_of(&tree_of(&[])));
+    }
+
+    #[test]
+    fn index_returns_the_value() {
+        let mut tree: BTree<u32, u32> = BTree::empty(3);
+        for key in 0..20 {
+            tree.insert(key, key * 10).unwrap();
+        }
+        assert_eq!(tree[&0], 0);
+        assert_eq!(tree[&19], 190);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found in BTree")]
+    fn index_panics_on_a_missing_key() {
+        let mut tree: BTree<u32, u32> = BTree::empty(3);
+        tree.insert(1, 1).unwrap();
+        let _ = tree[&2];
+    }
+}
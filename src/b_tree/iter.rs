@@ -0,0 +1,151 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::Debug;
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+
+use super::{BTree, Node, OwnedKeyPosition, Path, Summary};
+
+/// Iterator over the entries of a `BTree` in key order
+///
+/// Created by `BTree::iter` and `BTree::range`. Yields clones of the keys and
+/// values. Keeps the path from the root to the next entry as a stack of nodes,
+/// with for each node the position of the next key to visit. Borrows the
+/// tree, so it can not change while it is iterated over.
+pub struct Range<'a, T, V, S = ()>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    stack: Path<T, V, S>,
+    end: Bound<T>,
+    tree: PhantomData<&'a BTree<T, V, S>>,
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Iterate over all entries in key order
+    pub fn iter(&self) -> Range<'_, T, V, S> {
+        self.range(..)
+    }
+
+    /// Iterate over the entries with keys inside `range`, in key order
+    ///
+    /// Descends the tree once to find the first entry in the range. Every
+    /// node on the way down is remembered, so the iterator can continue with
+    /// the parent when it runs out of keys in a child.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Range<'_, T, V, S> {
+        let mut stack = Vec::new();
+        let mut current_node = Rc::clone(&self.root);
+        loop {
+            let (i, found, child) = {
+                let borrowed_node = current_node.borrow();
+                let i = match range.start_bound() {
+                    Bound::Included(start) => borrowed_node.keys.partition_point(|key| key < start),
+                    Bound::Excluded(start) => {
+                        borrowed_node.keys.partition_point(|key| key <= start)
+                    }
+                    Bound::Unbounded => 0,
+                };
+                // The start key itself is in this node, the keys in the child
+                // before it are all smaller
+                let found = match range.start_bound() {
+                    Bound::Included(start) => {
+                        i < borrowed_node.keys.len() && borrowed_node.keys[i] == *start
+                    }
+                    _ => false,
+                };
+                (i, found, borrowed_node.children.get(i).cloned())
+            };
+            stack.push((current_node, i));
+            match child {
+                Some(child) if !found => current_node = child,
+                _ => break,
+            }
+        }
+        Range {
+            stack,
+            end: range.end_bound().cloned(),
+            tree: PhantomData,
+        }
+    }
+}
+
+impl<T, V, S> Range<'_, T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Move on to the next entry, returns the node holding it and its position there
+    ///
+    /// Nothing is cloned but the node pointer, so wrappers like `MultiRange`
+    /// can read just the parts of the entry they need.
+    pub(super) fn advance(&mut self) -> Option<OwnedKeyPosition<T, V, S>> {
+        loop {
+            let (node, i) = {
+                let (node, i) = self.stack.last_mut()?;
+                *i += 1;
+                (Rc::clone(node), *i - 1)
+            };
+            let borrowed_node = node.borrow();
+            if i >= borrowed_node.keys.len() {
+                // All keys of this node are visited, continue with the parent
+                self.stack.pop();
+                continue;
+            }
+            let in_range = match &self.end {
+                Bound::Included(end) => borrowed_node.keys[i] <= *end,
+                Bound::Excluded(end) => borrowed_node.keys[i] < *end,
+                Bound::Unbounded => true,
+            };
+            if !in_range {
+                self.stack.clear();
+                return None;
+            }
+            // The keys in the child after this key come next
+            if let Some(child) = borrowed_node.children.get(i + 1) {
+                self.descend_leftmost(Rc::clone(child));
+            }
+            drop(borrowed_node);
+            return Some((node, i));
+        }
+    }
+
+    /// Push the path to the smallest key of the subtree below `node`
+    fn descend_leftmost(&mut self, node: Rc<RefCell<Node<T, V, S>>>) {
+        let mut current_node = node;
+        loop {
+            let child = current_node.borrow().children.first().cloned();
+            self.stack.push((current_node, 0));
+            match child {
+                Some(child) => current_node = child,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<T, V, S> Iterator for Range<'_, T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    type Item = (T, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, i) = self.advance()?;
+        let borrowed_node = node.borrow();
+        Some((
+            borrowed_node.keys[i].clone(),
+            borrowed_node.values[i].clone(),
+        ))
+    }
+}
@@ -1,29 +1,118 @@
-use std::cell::{RefCell, RefMut};
-use std::fmt;
-use std::fmt::Debug;
-use std::rc::Rc;
+use alloc::boxed::Box;
+use alloc::rc::{Rc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{RefCell, RefMut};
+use core::fmt;
+use core::fmt::Debug;
+use core::mem::size_of;
 
-struct Node<T, V>
+pub use compact::CompactStats;
+pub use compressed::{CompressedBTree, CompressedIter};
+pub use config::{BTreeConfig, ConfigError};
+pub use cursor::{Cursor, CursorMut};
+pub use extract::{Drain, ExtractIf};
+pub use iter::Range;
+pub use multimap::BTreeMultiMap;
+use observer::Restructuring;
+pub use observer::TreeObserver;
+pub use prefix::Prefixed;
+pub use set::BTreeSet;
+pub use stats::{KeyMemory, LevelStats, TreeStats};
+pub use summary::Summary;
+
+mod compact;
+mod compressed;
+mod config;
+mod cursor;
+mod extract;
+mod impls;
+mod iter;
+mod multimap;
+mod nearest;
+mod observer;
+mod order_statistics;
+mod partition;
+mod prefix;
+mod set;
+mod stats;
+mod summary;
+mod validate;
+
+struct Node<T, V, S>
 where
     T: PartialOrd + Clone + Debug,
     V: Clone + Debug,
+    S: Summary<T, V>,
 {
     keys: Vec<T>,
     values: Vec<V>,
-    children: Vec<Rc<RefCell<Node<T, V>>>>,
-    parent: Option<Rc<RefCell<Node<T, V>>>>,
+    children: Vec<Rc<RefCell<Node<T, V, S>>>>,
+    // Weak, so a child does not keep its parent alive and the tree can be dropped
+    parent: Option<Weak<RefCell<Node<T, V, S>>>>,
+    // Number of entries in this node and all nodes below it
+    size: usize,
+    // Summary of the entries in this node and all nodes below it
+    summary: S,
     // max_keys: i32,
 }
+
+// Nodes on the way down from the root, each with the position of a key or child
+type Path<T, V, S> = Vec<(Rc<RefCell<Node<T, V, S>>>, usize)>;
+
+// A node, and the position of a key in that node
+type KeyPosition<'a, T, V, S> = (&'a RefCell<Node<T, V, S>>, usize);
+
+// An owned pointer to a node, and the position of a key in that node
+type OwnedKeyPosition<T, V, S> = (Rc<RefCell<Node<T, V, S>>>, usize);
+
+// Set when the root was split and the tree grew one level taller
+type NewRoot<T, V, S> = Option<Rc<RefCell<Node<T, V, S>>>>;
+
+/// How a node that goes over the maximum number of keys is fixed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Split the full node in half and move the middle key to the parent,
+    /// leaving both nodes about half full.
+    #[default]
+    Split,
+    /// B*-tree style: first shift keys into an adjacent sibling with room,
+    /// through the separator in the parent, or on through a full sibling into
+    /// the one after it. Only when those are full as well, split the node and
+    /// a sibling into three nodes, which keeps nodes at least two thirds full.
+    /// Ascending or descending inserts fill the nodes almost completely.
+    Redistribute,
+}
+
+/// Where full nodes are fixed during insertion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InsertionMode {
+    /// Insert the key in its leaf first, and fix nodes that went over the
+    /// maximum number of keys on the way back up through the parent links.
+    #[default]
+    BottomUp,
+    /// Fix every full node encountered on the way down to the leaf, so that
+    /// its parent always has room for a separator and the insertion finishes
+    /// in a single pass. With an even `max_keys_per_node` this can leave a
+    /// node with one key less than half full. Needs at least 3 keys per node,
+    /// an order of at least 4.
+    TopDown,
+}
+
 /// BTree
 ///
-#[derive(Debug)]
-pub struct BTree<T, V>
+pub struct BTree<T, V, S = ()>
 where
     T: PartialOrd + Clone + Debug,
     V: Clone + Debug,
+    S: Summary<T, V>,
 {
     max_keys_per_node: usize,
-    root: Rc<RefCell<Node<T, V>>>,
+    min_keys_per_node: usize,
+    overflow_policy: OverflowPolicy,
+    insertion_mode: InsertionMode,
+    root: Rc<RefCell<Node<T, V, S>>>,
+    observer: Option<Box<dyn TreeObserver<T>>>,
 }
 
 impl<T, V> BTree<T, V>
@@ -32,84 +121,660 @@ where
     V: Clone + Debug,
 {
     pub fn new(first_key: T, first_value: V, max_keys_per_node: usize) -> Self {
-        if max_keys_per_node < 4 {
-            // TODO: change to error
-            panic!("max_keys_per_node must be at least 4");
-        }
-        if max_keys_per_node % 2 != 0 {
-            // TODO: change to error
-            panic!("max_keys_per_node must be an even number");
-        }
+        let mut tree = BTree::empty(max_keys_per_node);
         let root = Rc::new(RefCell::new(Node {
             keys: vec![first_key],
             values: vec![first_value],
             children: Vec::new(),
             parent: None,
+            size: 1,
+            summary: (),
             // max_keys: max_keys_per_node,
         }));
-        let tree = BTree {
-            max_keys_per_node,
-            root,
-        };
+        tree.root = root;
         tree
     }
 
+    /// Create a tree without any keys
+    ///
+    /// Panics if `max_keys_per_node` is below 2, see `BTreeConfig` to get an
+    /// error instead and for more settings.
+    pub fn empty(max_keys_per_node: usize) -> Self {
+        BTree::empty_with_summary(max_keys_per_node)
+    }
+
+    /// Mutable access to the value stored under `key`
+    ///
+    /// Only available without a summary, since changing a value in place
+    /// would leave the cached summaries out of date.
+    pub fn get_mut(&mut self, key: T) -> Option<RefMut<'_, V>> {
+        let (node, i) = self.find_cell(&key)?;
+        Some(RefMut::map(node.borrow_mut(), |node| &mut node.values[i]))
+    }
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Create a tree without any keys, that caches a summary `S` in every node
+    ///
+    /// The summaries are kept up to date by every insertion and removal, and
+    /// are combined by `aggregate`.
+    ///
+    /// Panics if `max_keys_per_node` is below 2, see
+    /// `BTreeConfig::build_with_summary` to get an error instead.
+    pub fn empty_with_summary(max_keys_per_node: usize) -> Self {
+        match BTreeConfig::new()
+            .max_keys_per_node(max_keys_per_node)
+            .build_with_summary()
+        {
+            Ok(tree) => tree,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    pub fn max_keys_per_node(&self) -> usize {
+        self.max_keys_per_node
+    }
+
+    /// Use `overflow_policy` to fix nodes that go over the maximum number of keys
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// Use `insertion_mode` to decide when full nodes are fixed during insertion
+    ///
+    /// Panics for `InsertionMode::TopDown` with fewer than 3 keys per node,
+    /// which `BTreeConfig::build` rejects as well.
+    pub fn with_insertion_mode(mut self, insertion_mode: InsertionMode) -> Self {
+        if insertion_mode == InsertionMode::TopDown && self.max_keys_per_node < 3 {
+            panic!(
+                "{}",
+                ConfigError::TopDownOrderTooSmall {
+                    order: self.max_keys_per_node + 1
+                }
+            );
+        }
+        self.insertion_mode = insertion_mode;
+        self
+    }
+
+    pub fn insertion_mode(&self) -> InsertionMode {
+        self.insertion_mode
+    }
+
     // Returns an Err when the key already exists
-    pub fn insert(&mut self, key: T, value: V) -> Result<(), &str> {
-        println!("Insertking key: {key:?}, value: {value:?}");
-        let root_len = self.root.borrow_mut().keys.len();
-        if root_len < self.max_keys_per_node && self.root.borrow().children.len() == 0 {
-            let borrowed_root = self.root.borrow_mut();
-            return BTree::insert_key_in_node(borrowed_root, key, value, self.max_keys_per_node);
+    pub fn insert(&mut self, key: T, value: V) -> Result<(), &'static str> {
+        match self.observer.take() {
+            Some(mut observer) => {
+                let result = self.insert_observed(key, value, observer.as_mut());
+                self.observer = Some(observer);
+                result
+            }
+            None => self.insert_observed(key, value, &mut ()),
         }
-        let result = BTree::traverse_insert(
+    }
+
+    fn insert_observed<O: TreeObserver<T> + ?Sized>(
+        &mut self,
+        key: T,
+        value: V,
+        observer: &mut O,
+    ) -> Result<(), &'static str> {
+        if self.insertion_mode == InsertionMode::TopDown {
+            if self.root.borrow().keys.len() >= self.max_keys_per_node {
+                // Split a full root up front, the tree grows taller here
+                let new_root = Rc::new(RefCell::new(Node::new_empty()));
+                BTree::split_child(
+                    &new_root,
+                    self.root.clone(),
+                    self.max_keys_per_node,
+                    observer,
+                );
+                self.root = new_root;
+            }
+            return BTree::top_down_insert(
+                self.root.clone(),
+                key,
+                value,
+                self.max_keys_per_node,
+                self.overflow_policy,
+                observer,
+            );
+        }
+        let new_root = BTree::traverse_insert(
             self.root.clone(),
-            key.clone(),
-            value.clone(),
+            key,
+            value,
             self.max_keys_per_node,
-        );
-        match result {
-            Ok(node) => {
-                self.root = node;
-                return Ok(());
-            }
-            Err(e) if e == "Node is full" => {
-                // NOTE: why do we know this is the root? Should it be 'Node is full'?
-                println!("Root node is full, splitting");
-                // FIXME: this is not called when splitting the root later.
-                let result = BTree::split_node(self.root.clone(), self.max_keys_per_node);
-                match result {
-                    Ok(node) => {
-                        self.root = node;
-                        return Ok(());
-                    }
-                    Err(e) => return Err(e),
-                }
-            }
-            Err(e) => Err(e),
+            self.overflow_policy,
+            observer,
+        )?;
+        // Splitting the root is the only way the tree grows taller
+        if let Some(node) = new_root {
+            self.root = node;
         }
+        Ok(())
     }
 
     // Returns an Err when the key does not exist
-    pub fn remove(&mut self, _key: T) -> Result<V, ()> {
-        Err(())
+    pub fn remove(&mut self, key: T) -> Result<V, &'static str> {
+        match self.observer.take() {
+            Some(mut observer) => {
+                let result = self.remove_observed(&key, observer.as_mut());
+                self.observer = Some(observer);
+                result
+            }
+            None => self.remove_observed(&key, &mut ()),
+        }
+    }
+
+    fn remove_observed<O: TreeObserver<T> + ?Sized>(
+        &mut self,
+        key: &T,
+        observer: &mut O,
+    ) -> Result<V, &'static str> {
+        let min_keys_per_node = self.min_keys_per_node();
+        let value = BTree::traverse_remove(self.root.clone(), key, min_keys_per_node, observer)?;
+        self.shrink_root(observer);
+        Ok(value)
     }
 
     pub fn exists(&self, key: T) -> bool {
         // TODO: traverse_search can return an Err
         let does_exist = BTree::traverse_search(self.root.clone(), key);
         match does_exist {
-            Ok(..) => return true,
-            Err(..) => return false,
-        };
+            Ok(..) => true,
+            Err(..) => false,
+        }
     }
 
     pub fn get(&self, key: T) -> Option<V> {
-        let does_exist = BTree::traverse_search(self.root.clone(), key);
-        match does_exist {
-            Ok(value) => return Some(value),
-            Err(..) => return None,
+        BTree::traverse_search(self.root.clone(), key).ok()
+    }
+
+    /// The entry with the smallest key, found by following the first child down
+    pub fn first_key_value(&self) -> Option<(T, V)> {
+        let mut current_node = self.root.clone();
+        loop {
+            let child = {
+                let borrowed_node = current_node.borrow();
+                match borrowed_node.children.first() {
+                    Some(child) => Rc::clone(child),
+                    None => {
+                        let key = borrowed_node.keys.first()?.clone();
+                        return Some((key, borrowed_node.values[0].clone()));
+                    }
+                }
+            };
+            current_node = child;
+        }
+    }
+
+    /// The entry with the largest key, found by following the last child down
+    pub fn last_key_value(&self) -> Option<(T, V)> {
+        let mut current_node = self.root.clone();
+        loop {
+            let child = {
+                let borrowed_node = current_node.borrow();
+                match borrowed_node.children.last() {
+                    Some(child) => Rc::clone(child),
+                    None => {
+                        let key = borrowed_node.keys.last()?.clone();
+                        let value = borrowed_node.values.last()?.clone();
+                        return Some((key, value));
+                    }
+                }
+            };
+            current_node = child;
+        }
+    }
+
+    /// Remove and return the entry with the smallest key
+    pub fn pop_first(&mut self) -> Option<(T, V)> {
+        match self.observer.take() {
+            Some(mut observer) => {
+                let entry = self.pop_observed(true, observer.as_mut());
+                self.observer = Some(observer);
+                entry
+            }
+            None => self.pop_observed(true, &mut ()),
+        }
+    }
+
+    /// Remove and return the entry with the largest key
+    pub fn pop_last(&mut self) -> Option<(T, V)> {
+        match self.observer.take() {
+            Some(mut observer) => {
+                let entry = self.pop_observed(false, observer.as_mut());
+                self.observer = Some(observer);
+                entry
+            }
+            None => self.pop_observed(false, &mut ()),
+        }
+    }
+
+    /// Remove the entry with the smallest key if `first` is set, the largest otherwise
+    fn pop_observed<O: TreeObserver<T> + ?Sized>(
+        &mut self,
+        first: bool,
+        observer: &mut O,
+    ) -> Option<(T, V)> {
+        if self.root.borrow().keys.is_empty() {
+            return None;
+        }
+        let min_keys_per_node = self.min_keys_per_node();
+        let entry = if first {
+            BTree::remove_first(self.root.clone(), min_keys_per_node, observer)
+        } else {
+            BTree::remove_last(self.root.clone(), min_keys_per_node, observer)
         };
+        self.shrink_root(observer);
+        Some(entry)
+    }
+
+    /// Minimum number of keys in any node but the root, below which removal
+    /// borrows from or merges with a sibling
+    pub fn min_keys_per_node(&self) -> usize {
+        self.min_keys_per_node
+    }
+
+    /// Fewest keys a node but the root can have, which is below the minimum
+    /// for removal when top-down insertion leaves a node one key short of half full
+    fn min_keys_allowed(&self) -> usize {
+        if self.insertion_mode == InsertionMode::TopDown {
+            self.min_keys_per_node.min((self.max_keys_per_node - 1) / 2)
+        } else {
+            self.min_keys_per_node
+        }
+    }
+
+    /// Find the node holding `key`, and the position of the key in that node
+    ///
+    /// Walks down the tree without holding on to any `Ref` guards, so that the
+    /// node can be handed out for as long as the tree is borrowed.
+    fn find_cell<Q>(&self, key: &Q) -> Option<KeyPosition<'_, T, V, S>>
+    where
+        T: core::borrow::Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+    {
+        let mut cell: &RefCell<Node<T, V, S>> = &self.root;
+        loop {
+            // SAFETY: nodes are only ever mutated by methods taking `&mut self`,
+            // and none of them keep a borrow alive when they return. Whatever
+            // reads the nodes later, like `Range`, `Cursor` or the reference
+            // returned by `Index`, borrows the tree for as long as it lives, and
+            // only takes shared borrows. So while `self` is borrowed nothing
+            // can mutate or drop this node.
+            let node = unsafe { cell.try_borrow_unguarded() }.ok()?;
+            // `Borrow::borrow`, not the `RefCell` method
+            let i = node
+                .keys
+                .partition_point(|current_key| core::borrow::Borrow::borrow(current_key) < key);
+            if i < node.keys.len() && core::borrow::Borrow::borrow(&node.keys[i]) == key {
+                return Some((cell, i));
+            }
+            cell = node.children.get(i)?;
+        }
+    }
+
+    /// The value stored under `key`, borrowed for as long as the tree is
+    fn get_ref<Q>(&self, key: &Q) -> Option<&V>
+    where
+        T: core::borrow::Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+    {
+        let (cell, i) = self.find_cell(key)?;
+        // SAFETY: see `find_cell`, the node stays untouched while `self` is borrowed
+        let node = unsafe { cell.try_borrow_unguarded() }.ok()?;
+        Some(&node.values[i])
+    }
+
+    /// Remove a key from the subtree below `current_node`
+    ///
+    /// Recursively descends to the node holding the key. A key in a leaf is
+    /// removed directly, a key in an internal node is replaced by its
+    /// predecessor, the largest key of the subtree to its left.
+    ///
+    /// The recursion keeps the parent in hand, so after removing from a child
+    /// the child is rebalanced if it went below the minimum number of keys.
+    fn traverse_remove<O: TreeObserver<T> + ?Sized>(
+        current_node: Rc<RefCell<Node<T, V, S>>>,
+        key: &T,
+        min_keys_per_node: usize,
+        observer: &mut O,
+    ) -> Result<V, &'static str> {
+        let (i, found, child_to_traverse) = {
+            let mut borrowed_node = current_node.borrow_mut();
+            let i = borrowed_node
+                .keys
+                .partition_point(|current_key| current_key < key);
+            let found = i < borrowed_node.keys.len() && borrowed_node.keys[i] == *key;
+            if borrowed_node.children.is_empty() {
+                if !found {
+                    return Err("Key not found");
+                }
+                borrowed_node.keys.remove(i);
+                let value = borrowed_node.values.remove(i);
+                drop(borrowed_node);
+                BTree::update_summary(&current_node);
+                return Ok(value);
+            }
+            (i, found, Rc::clone(&borrowed_node.children[i]))
+        };
+
+        let value = if found {
+            let (predecessor_key, predecessor_value) =
+                BTree::remove_last(Rc::clone(&child_to_traverse), min_keys_per_node, observer);
+            let mut borrowed_node = current_node.borrow_mut();
+            borrowed_node.keys[i] = predecessor_key;
+            core::mem::replace(&mut borrowed_node.values[i], predecessor_value)
+        } else {
+            BTree::traverse_remove(
+                Rc::clone(&child_to_traverse),
+                key,
+                min_keys_per_node,
+                observer,
+            )?
+        };
+        if child_to_traverse.borrow().keys.len() < min_keys_per_node {
+            BTree::rebalance_child(&current_node, i, min_keys_per_node, observer);
+        }
+        BTree::update_summary(&current_node);
+        Ok(value)
+    }
+
+    /// Remove and return the smallest entry of the subtree below `current_node`
+    fn remove_first<O: TreeObserver<T> + ?Sized>(
+        current_node: Rc<RefCell<Node<T, V, S>>>,
+        min_keys_per_node: usize,
+        observer: &mut O,
+    ) -> (T, V) {
+        let child_to_traverse = {
+            let mut borrowed_node = current_node.borrow_mut();
+            if borrowed_node.children.is_empty() {
+                let key = borrowed_node.keys.remove(0);
+                let value = borrowed_node.values.remove(0);
+                drop(borrowed_node);
+                BTree::update_summary(&current_node);
+                return (key, value);
+            }
+            Rc::clone(&borrowed_node.children[0])
+        };
+        let entry = BTree::remove_first(Rc::clone(&child_to_traverse), min_keys_per_node, observer);
+        if child_to_traverse.borrow().keys.len() < min_keys_per_node {
+            BTree::rebalance_child(&current_node, 0, min_keys_per_node, observer);
+        }
+        BTree::update_summary(&current_node);
+        entry
+    }
+
+    /// Remove and return the largest entry of the subtree below `current_node`
+    fn remove_last<O: TreeObserver<T> + ?Sized>(
+        current_node: Rc<RefCell<Node<T, V, S>>>,
+        min_keys_per_node: usize,
+        observer: &mut O,
+    ) -> (T, V) {
+        let (i, child_to_traverse) = {
+            let mut borrowed_node = current_node.borrow_mut();
+            if borrowed_node.children.is_empty() {
+                let key = borrowed_node.keys.pop().unwrap();
+                let value = borrowed_node.values.pop().unwrap();
+                drop(borrowed_node);
+                BTree::update_summary(&current_node);
+                return (key, value);
+            }
+            let i = borrowed_node.children.len() - 1;
+            (i, Rc::clone(&borrowed_node.children[i]))
+        };
+        let entry = BTree::remove_last(Rc::clone(&child_to_traverse), min_keys_per_node, observer);
+        if child_to_traverse.borrow().keys.len() < min_keys_per_node {
+            BTree::rebalance_child(&current_node, i, min_keys_per_node, observer);
+        }
+        BTree::update_summary(&current_node);
+        entry
+    }
+
+    /// Fix child `i` of `parent` after it went below the minimum number of keys
+    ///
+    /// Borrows a key through the parent from the left or right sibling if one of
+    /// them has keys to spare. Otherwise the child is merged with a sibling,
+    /// pulling the separator between them down from the parent.
+    fn rebalance_child<O: TreeObserver<T> + ?Sized>(
+        parent: &Rc<RefCell<Node<T, V, S>>>,
+        i: usize,
+        min_keys_per_node: usize,
+        observer: &mut O,
+    ) {
+        let siblings = parent.borrow().children.len();
+        if i > 0 {
+            let left_len = parent.borrow().children[i - 1].borrow().keys.len();
+            if left_len > min_keys_per_node {
+                BTree::rotate_right(parent, i - 1, observer);
+                return;
+            }
+        }
+        if i + 1 < siblings {
+            let right_len = parent.borrow().children[i + 1].borrow().keys.len();
+            if right_len > min_keys_per_node {
+                BTree::rotate_left(parent, i + 1, observer);
+                return;
+            }
+        }
+        let left_index = if i > 0 { i - 1 } else { i };
+        BTree::merge_children(parent, left_index, observer);
+    }
+
+    /// Merge child `left_index + 1` of `parent` into child `left_index`
+    ///
+    /// The separator between the two children moves down from the parent into
+    /// the merged node, so the parent loses a key.
+    fn merge_children<O: TreeObserver<T> + ?Sized>(
+        parent: &Rc<RefCell<Node<T, V, S>>>,
+        left_index: usize,
+        observer: &mut O,
+    ) {
+        let (left, right) = {
+            let mut borrowed_parent = parent.borrow_mut();
+            let right = borrowed_parent.children.remove(left_index + 1);
+            (Rc::clone(&borrowed_parent.children[left_index]), right)
+        };
+        {
+            let mut borrowed_parent = parent.borrow_mut();
+            let mut borrowed_left = left.borrow_mut();
+            let mut borrowed_right = right.borrow_mut();
+            let separator_index = borrowed_left.keys.len();
+            borrowed_left
+                .keys
+                .push(borrowed_parent.keys.remove(left_index));
+            borrowed_left
+                .values
+                .push(borrowed_parent.values.remove(left_index));
+            borrowed_left.keys.append(&mut borrowed_right.keys);
+            borrowed_left.values.append(&mut borrowed_right.values);
+            borrowed_left.children.append(&mut borrowed_right.children);
+            observer.on_merge(&borrowed_left.keys, &borrowed_left.keys[separator_index]);
+        }
+        BTree::adopt_children(&left);
+        BTree::update_summary(&left);
+    }
+
+    /// Replace a root without keys by its only child
+    ///
+    /// Merging the last two children of the root leaves it empty, which is the
+    /// only way the tree gets shorter.
+    fn shrink_root<O: TreeObserver<T> + ?Sized>(&mut self, observer: &mut O) {
+        let child = {
+            let borrowed_root = self.root.borrow();
+            if !borrowed_root.keys.is_empty() || borrowed_root.children.is_empty() {
+                return;
+            }
+            Rc::clone(&borrowed_root.children[0])
+        };
+        child.borrow_mut().parent = None;
+        self.root = child;
+        observer.on_root_change(&self.root.borrow().keys);
+        let height = BTree::subtree_height(&self.root);
+        observer.on_height_change(height + 1, height);
+    }
+
+    /// Run a bulk `operation` with the registered observer, and report the new
+    /// root and height once it is done
+    ///
+    /// The operation gets an observer that only passes on splits, merges and
+    /// borrows, so the parts it cuts off and joins are not reported as roots.
+    fn observe_bulk<R, F>(&mut self, operation: F) -> R
+    where
+        F: FnOnce(&mut Self, &mut dyn TreeObserver<T>) -> R,
+    {
+        let mut observer = match self.observer.take() {
+            Some(observer) => observer,
+            None => return operation(self, &mut ()),
+        };
+        let old_root = Rc::clone(&self.root);
+        let old_height = BTree::subtree_height(&self.root);
+        let result = operation(self, &mut Restructuring(observer.as_mut()));
+        if !Rc::ptr_eq(&old_root, &self.root) {
+            observer.on_root_change(&self.root.borrow().keys);
+        }
+        let height = BTree::subtree_height(&self.root);
+        if height != old_height {
+            observer.on_height_change(old_height, height);
+        }
+        self.observer = Some(observer);
+        result
+    }
+
+    /// Number of levels of nodes in the subtree below `node`, including `node`
+    fn subtree_height(node: &Rc<RefCell<Node<T, V, S>>>) -> usize {
+        let mut height = 1;
+        let mut current_node = Rc::clone(node);
+        loop {
+            let child = current_node.borrow().children.first().cloned();
+            match child {
+                Some(child) => current_node = child,
+                None => return height,
+            }
+            height += 1;
+        }
+    }
+
+    /// Memory taken up by a node with room for this many keys, values and children
+    ///
+    /// Counts the node and `size_of` every element, but not memory the keys
+    /// or values point to.
+    fn node_bytes(keys: usize, values: usize, children: usize) -> usize {
+        // An `Rc` allocation also holds the strong and weak counts
+        size_of::<RefCell<Node<T, V, S>>>()
+            + 2 * size_of::<usize>()
+            + keys * size_of::<T>()
+            + values * size_of::<V>()
+            + children * size_of::<Rc<RefCell<Node<T, V, S>>>>()
+    }
+
+    /// A tree with the same settings as this one, with `root` as its root
+    fn with_root(&self, root: Rc<RefCell<Node<T, V, S>>>) -> Self {
+        root.borrow_mut().parent = None;
+        BTree {
+            max_keys_per_node: self.max_keys_per_node,
+            min_keys_per_node: self.min_keys_per_node,
+            overflow_policy: self.overflow_policy,
+            insertion_mode: self.insertion_mode,
+            root,
+            observer: None,
+        }
+    }
+
+    /// Build the nodes of a tree from entries that are sorted by key, without duplicates
+    ///
+    /// Works bottom-up one level at a time. The entries of a level are spread
+    /// evenly over as few nodes as possible with at most `keys_per_node` keys
+    /// each, but never below the minimum number of keys. The entries between
+    /// two nodes are left over as the separators, which form the level above
+    /// with the nodes as their children. Returns the root of the new tree.
+    fn build_from_sorted(
+        &self,
+        entries: Vec<(T, V)>,
+        keys_per_node: usize,
+    ) -> Rc<RefCell<Node<T, V, S>>> {
+        let min_keys_per_node = self.min_keys_per_node();
+        let keys_per_node = keys_per_node.clamp(min_keys_per_node.max(1), self.max_keys_per_node);
+        let mut entries = entries;
+        let mut children = Vec::new();
+        loop {
+            let n = entries.len();
+            // `nodes` nodes hold all entries but the `nodes - 1` separators
+            let mut nodes = (n + 1).div_ceil(keys_per_node + 1).max(1);
+            while nodes > 1 && (n + 1 - nodes) / nodes < min_keys_per_node {
+                nodes -= 1;
+            }
+            let keys = n + 1 - nodes;
+            let mut entries_left = entries.into_iter();
+            let mut children_left = children.into_iter();
+            let mut level = Vec::new();
+            let mut separators = Vec::new();
+            for j in 0..nodes {
+                let node_len = keys / nodes + usize::from(j < keys % nodes);
+                let (node_keys, node_values) = entries_left.by_ref().take(node_len).unzip();
+                let node_children = children_left.by_ref().take(node_len + 1).collect();
+                let node = Rc::new(RefCell::new(Node {
+                    keys: node_keys,
+                    values: node_values,
+                    children: node_children,
+                    parent: None,
+                    size: 0,
+                    summary: S::identity(),
+                }));
+                BTree::adopt_children(&node);
+                BTree::update_summary(&node);
+                level.push(node);
+                if j + 1 < nodes {
+                    separators.extend(entries_left.next());
+                }
+            }
+            if level.len() == 1 {
+                return level.pop().unwrap();
+            }
+            entries = separators;
+            children = level;
+        }
+    }
+
+    /// All entries of the tree in key order, moved out of the nodes
+    fn into_entries(self) -> Vec<(T, V)> {
+        let mut entries = Vec::with_capacity(self.len());
+        BTree::move_entries(self.root, &mut entries);
+        entries
+    }
+
+    /// Move the entries of the subtree below `node` to the end of `entries`, in key order
+    fn move_entries(node: Rc<RefCell<Node<T, V, S>>>, entries: &mut Vec<(T, V)>) {
+        let (keys, values, children) = {
+            let mut borrowed_node = node.borrow_mut();
+            (
+                core::mem::take(&mut borrowed_node.keys),
+                core::mem::take(&mut borrowed_node.values),
+                core::mem::take(&mut borrowed_node.children),
+            )
+        };
+        let mut children = children.into_iter();
+        for entry in keys.into_iter().zip(values) {
+            if let Some(child) = children.next() {
+                BTree::move_entries(child, entries);
+            }
+            entries.push(entry);
+        }
+        if let Some(child) = children.next() {
+            BTree::move_entries(child, entries);
+        }
     }
 
     /// Traverse over the children of a node to find the node in which to insert
@@ -119,307 +784,583 @@ where
     /// into this node. If the `current_node` has children, loop over keys of
     /// the node to find the child node to which to recurse.
     ///
-    /// If the insertion into the current node fails, the error is passed to
-    /// the parent node.
-    /// If the recursive `traverse_insert` call on a child node fails, the
-    /// child node needs to be split into two nodes, both of which become children
-    /// of the `current_node`. If then the `current_node` is full, the
-    /// `traverse_insert` call returns an error to the parent, which then
-    /// recursively splits nodes.
-    fn traverse_insert(
-        current_node: Rc<RefCell<Node<T, V>>>,
+    /// If the leaf goes over the maximum number of keys, it is fixed according
+    /// to the `overflow_policy`, which walks up the tree through the parent
+    /// links as long as nodes keep overflowing. If this splits the root, the
+    /// new root is returned.
+    fn traverse_insert<O: TreeObserver<T> + ?Sized>(
+        current_node: Rc<RefCell<Node<T, V, S>>>,
         key: T,
         value: V,
         max_keys_per_node: usize,
-    ) -> Result<Rc<RefCell<Node<T, V>>>, &'static str> {
-        println!("Traversing node: {current_node:#?}");
-
+        overflow_policy: OverflowPolicy,
+        observer: &mut O,
+    ) -> Result<NewRoot<T, V, S>, &'static str> {
         // Only insert key in current node if it is a leaf node
-        {
+        let child_to_traverse = {
             let borrowed_node = current_node.borrow_mut();
-            if borrowed_node.children.len() == 0 {
-                // insert key in current node
-                println!("Inserting key in leaf node");
+            if borrowed_node.children.is_empty() {
                 let result =
                     BTree::insert_key_in_node(borrowed_node, key, value, max_keys_per_node);
-                match result {
-                    Ok(_) => return Ok(current_node),
-                    // TODO: split child?
-                    Err(e) => {
-                        println!("Inserting key gave error: {e}");
-                        return Err(e);
-                    } // Node is full, pass error to parent or split child.
+                if result != Err("Key already exists") {
+                    BTree::update_summary(&current_node);
                 }
+                return match result {
+                    Ok(()) => Ok(None),
+                    Err("Node is full") => Ok(BTree::handle_overflow(
+                        current_node,
+                        max_keys_per_node,
+                        overflow_policy,
+                        observer,
+                    )),
+                    Err(e) => Err(e),
+                };
             }
-        }
-        let keys = {
-            let borrowed_node = current_node.borrow();
-            borrowed_node.keys.clone()
-        };
-
-        for (i, current_key) in keys.iter().enumerate() {
-            println!("Iterating key: {current_key:?}");
-            if key == *current_key {
+            let i = borrowed_node
+                .keys
+                .partition_point(|current_key| *current_key < key);
+            if i < borrowed_node.keys.len() && borrowed_node.keys[i] == key {
                 return Err("Key already exists");
             }
-            if key < *current_key {
-                let child_to_traverse = {
-                    let borrowed_node = current_node.borrow();
-                    Rc::clone(&borrowed_node.children[i])
-                };
-                let result = BTree::traverse_insert(
-                    child_to_traverse,
-                    key.clone(),
-                    value.clone(),
-                    max_keys_per_node,
-                );
-                let child_to_traverse = {
-                    let borrowed_node = current_node.borrow();
-                    Rc::clone(&borrowed_node.children[i])
-                };
-                match result {
-                    Ok(node) => {
-                        println!("Inserted key in child node, Ok. Returning {node:#?}");
-                        return Ok(node);
-                    }
-                    Err(e) if e == "Node is full" => {
-                        println!("Inserted key in child node, Node is full, splitting");
-                        let result = BTree::split_node(child_to_traverse, max_keys_per_node);
-                        match result {
-                            Ok(node) => {
-                                return Ok(node);
-                            }
-                            // Or should we split the child here?
-                            Err(e) => return Err(e),
-                        };
-                    }
-                    // Or here?
-                    Err(e) if e == "Key already exists" => {
-                        println!("Key already exists!");
-                        return Err(e);
-                    }
-                    Err(_) => panic!("Invalid state?"),
-                };
-            }
-        }
-        let child_to_traverse = {
-            let borrowed_node = current_node.borrow();
-            Rc::clone(&borrowed_node.children.last().unwrap())
+            Rc::clone(&borrowed_node.children[i])
         };
         let result = BTree::traverse_insert(
             child_to_traverse,
-            key.clone(),
-            value.clone(),
+            key,
+            value,
             max_keys_per_node,
+            overflow_policy,
+            observer,
         );
-        let child_to_traverse = {
-            let borrowed_node = current_node.borrow();
-            Rc::clone(&borrowed_node.children.last().unwrap())
-        };
-        match result {
-            Ok(node) => {
-                println!("Inserted key in child node, Ok. Returning {node:#?}");
-                return Ok(node);
-            }
-            Err(e) if e == "Node is full" => {
-                println!("Inserted key in child node after loop, Node is full, splitting");
-                let result = BTree::split_node(child_to_traverse, max_keys_per_node);
-                match result {
-                    Ok(node) => {
-                        return Ok(node);
+        if result.is_ok() {
+            // Any splits below are done, so the children are up to date
+            BTree::update_summary(&current_node);
+        }
+        result
+    }
+
+    /// Insert the key-value pair in a single pass from the root to a leaf
+    ///
+    /// Keeps the parent of the next node in hand while walking down, instead of
+    /// relying on the parent links. Whenever the child to descend into is full,
+    /// it is fixed before descending, according to the `overflow_policy`. This
+    /// adds at most one key to the current node, which is never full itself, so
+    /// the leaf always has room for the new key and nothing is propagated back
+    /// up. The root must not be full when this is called.
+    fn top_down_insert<O: TreeObserver<T> + ?Sized>(
+        root: Rc<RefCell<Node<T, V, S>>>,
+        key: T,
+        value: V,
+        max_keys_per_node: usize,
+        overflow_policy: OverflowPolicy,
+        observer: &mut O,
+    ) -> Result<(), &'static str> {
+        // Nodes on the way down, to update their summaries afterwards
+        let mut path = Vec::new();
+        let mut current_node = root;
+        loop {
+            if current_node.borrow().children.is_empty() {
+                let result = BTree::insert_key_in_node(
+                    current_node.borrow_mut(),
+                    key,
+                    value,
+                    max_keys_per_node,
+                );
+                if result.is_ok() {
+                    path.push(current_node);
+                    for node in path.iter().rev() {
+                        BTree::update_summary(node);
                     }
-                    // Or should we split the child here?
-                    Err(e) => return Err(e),
-                };
+                }
+                return result;
             }
-            // Or here?
-            Err(e) if e == "Key already exists" => {
-                println!("Key already exists!");
-                return Err(e);
+            let child_to_traverse = loop {
+                let child = {
+                    let borrowed_node = current_node.borrow();
+                    let i = borrowed_node
+                        .keys
+                        .partition_point(|current_key| *current_key < key);
+                    if i < borrowed_node.keys.len() && borrowed_node.keys[i] == key {
+                        return Err("Key already exists");
+                    }
+                    Rc::clone(&borrowed_node.children[i])
+                };
+                if child.borrow().keys.len() < max_keys_per_node {
+                    break child;
+                }
+                // Fixing the child can move keys around, so look for the child again
+                let redistributed = overflow_policy == OverflowPolicy::Redistribute
+                    && BTree::redistribute_or_split(
+                        Rc::clone(&current_node),
+                        Rc::clone(&child),
+                        max_keys_per_node - 1,
+                        observer,
+                    );
+                if !redistributed {
+                    BTree::split_child(&current_node, child, max_keys_per_node, observer);
+                }
+            };
+            path.push(current_node);
+            current_node = child_to_traverse;
+        }
+    }
+
+    /// Fix a node that has one key more than the maximum
+    ///
+    /// With `OverflowPolicy::Split`, and always for the root, the node is split
+    /// in two. With `OverflowPolicy::Redistribute`, keys are shifted into the
+    /// left or right sibling if one of them has room, and otherwise the node and
+    /// a full sibling are split into three nodes.
+    ///
+    /// Both splitting strategies add a key to the parent, so if the parent then
+    /// goes over the maximum number of keys it is fixed the same way. Returns
+    /// the new root if the root had to be split.
+    fn handle_overflow<O: TreeObserver<T> + ?Sized>(
+        node: Rc<RefCell<Node<T, V, S>>>,
+        max_keys_per_node: usize,
+        overflow_policy: OverflowPolicy,
+        observer: &mut O,
+    ) -> NewRoot<T, V, S> {
+        let parent = match &node.borrow().parent {
+            Some(parent) => parent.upgrade(),
+            None => None,
+        };
+        let is_root = parent.is_none();
+        let parent = match (overflow_policy, parent) {
+            (OverflowPolicy::Redistribute, Some(parent)) => {
+                let redistributed = BTree::redistribute_or_split(
+                    Rc::clone(&parent),
+                    Rc::clone(&node),
+                    max_keys_per_node,
+                    observer,
+                );
+                if redistributed {
+                    parent
+                } else {
+                    BTree::split_node(node, max_keys_per_node, observer)
+                }
             }
-            Err(_) => panic!("Invalid state?"),
+            _ => BTree::split_node(node, max_keys_per_node, observer),
+        };
+        if parent.borrow().keys.len() > max_keys_per_node {
+            return BTree::handle_overflow(parent, max_keys_per_node, overflow_policy, observer);
+        }
+        if is_root {
+            return Some(parent);
         }
+        None
     }
 
     /// Split a node into two nodes if it has too many keys
     ///
-    /// Split the `child_to_split` node into two nodes. The keys and values are
-    /// split in half, with the middle key and value being moved to the parent node.
-    /// A new node is created with the right half of the keys and values, and the
-    /// right half of the children. The parent node is updated to include the new
-    /// right node, and the parent node is returned.
-    /// If the parent node goes over the maximum number of keys, the parent node is
-    /// split as well recursively.
+    /// Finds the parent of `child_to_split` through its parent link and splits
+    /// the child with `split_child`. The parent node is returned, and may now
+    /// go over the maximum number of keys, which is left to the caller to fix.
     ///
     /// If the `child_to_split` is the root node, a new root node is created and
     /// the old root node is split into two nodes. The new root node is connected
     /// to the two new nodes and returned.
-    fn split_node(
-        child_to_split: Rc<RefCell<Node<T, V>>>,
+    fn split_node<O: TreeObserver<T> + ?Sized>(
+        child_to_split: Rc<RefCell<Node<T, V, S>>>,
         max_keys_per_node: usize,
-    ) -> Result<Rc<RefCell<Node<T, V>>>, &'static str> {
-        // If we split a node, do we always create two nodes?
-        // When we split the root, we need to create a new root node and split the old root node.
-        //
-        // We do need to create a new parent when we split the root. Return the parent?
-        // If the `current_node` is not None, we insert the remainder values in it.
-        // Connecting the new children to the parent requres some logic
-        //
-        // The step of inserting into the parent, if it already existed, could require a recursive
-        // call to `split_node`.
-        //
+        observer: &mut O,
+    ) -> Rc<RefCell<Node<T, V, S>>> {
         // The tree only gets taller when we split the root!
-        let parent_exists;
-        let parent = match child_to_split.borrow().parent.clone() {
-            Some(node) => {
-                parent_exists = true;
-                Rc::clone(&node)
+        let parent = match &child_to_split.borrow().parent {
+            Some(parent) => parent.upgrade(),
+            None => None,
+        };
+        let parent = parent.unwrap_or_else(|| Rc::new(RefCell::new(Node::new_empty())));
+        BTree::split_child(&parent, child_to_split, max_keys_per_node, observer);
+        parent
+    }
+
+    /// Split `child_to_split` in two halves below the given `parent`
+    ///
+    /// The keys and values are split in half, with the middle key and value being
+    /// moved to the parent node. A new node is created with the right half of the
+    /// keys and values, and the right half of the children, and is connected to
+    /// the parent directly after the split child.
+    ///
+    /// The parent is passed in explicitly, so this works both when walking back
+    /// up through the parent links and when splitting on the way down.
+    fn split_child<O: TreeObserver<T> + ?Sized>(
+        parent: &Rc<RefCell<Node<T, V, S>>>,
+        child_to_split: Rc<RefCell<Node<T, V, S>>>,
+        max_keys_per_node: usize,
+        observer: &mut O,
+    ) {
+        let (new_right_node, spare_key, spare_value) = {
+            let mut borrowed_child = child_to_split.borrow_mut();
+            let mid = borrowed_child.keys.len() / 2;
+            let right_children = if borrowed_child.children.is_empty() {
+                Vec::new()
+            } else {
+                borrowed_child.children.split_off(mid + 1)
+            };
+            let new_right_node = Node {
+                keys: borrowed_child.keys.split_off(mid + 1),
+                values: borrowed_child.values.split_off(mid + 1),
+                children: right_children,
+                parent: None,
+                size: 0,
+                summary: S::identity(),
+            };
+            let spare_key = borrowed_child.keys.pop().unwrap();
+            let spare_value = borrowed_child.values.pop().unwrap();
+            observer.on_split(&borrowed_child.keys, &new_right_node.keys, &spare_key);
+            (
+                Rc::new(RefCell::new(new_right_node)),
+                spare_key,
+                spare_value,
+            )
+        };
+        BTree::adopt_children(&new_right_node);
+
+        // The separator goes between the two halves, and is never a duplicate
+        let _ = BTree::insert_key_in_node(
+            parent.borrow_mut(),
+            spare_key,
+            spare_value,
+            max_keys_per_node,
+        );
+        BTree::update_summary(&child_to_split);
+        BTree::update_summary(&new_right_node);
+        BTree::connect_children_to_parent(parent, child_to_split, new_right_node, observer);
+        BTree::update_summary(parent);
+    }
+
+    /// Shift keys into a sibling of the overflowing `node`, or split it into three
+    ///
+    /// Prefers moving keys into the left sibling, then the right sibling, so that
+    /// both nodes end up with about the same number of keys. If both are full,
+    /// keys are passed on through one of them into the sibling after it.
+    /// Returns false if there was no sibling to work with, in which case
+    /// nothing was changed. Otherwise, when all of those siblings are full, the
+    /// node is split together with one of them, which adds a key to the `parent`.
+    ///
+    /// Afterwards none of the nodes involved has more than `max_keys_per_node`
+    /// keys. Top-down insertion passes one less than the real maximum, so the
+    /// nodes also have room for the key that is being inserted.
+    fn redistribute_or_split<O: TreeObserver<T> + ?Sized>(
+        parent: Rc<RefCell<Node<T, V, S>>>,
+        node: Rc<RefCell<Node<T, V, S>>>,
+        max_keys_per_node: usize,
+        observer: &mut O,
+    ) -> bool {
+        let (i, siblings) = {
+            let borrowed_parent = parent.borrow();
+            let i = BTree::child_position(&borrowed_parent, &node);
+            (i, borrowed_parent.children.len())
+        };
+        if siblings < 2 {
+            return false;
+        }
+        let node_len = node.borrow().keys.len();
+        if i > 0 {
+            let left_len = parent.borrow().children[i - 1].borrow().keys.len();
+            if left_len < max_keys_per_node {
+                for _ in 0..(node_len - left_len).div_ceil(2) {
+                    BTree::rotate_left(&parent, i, observer);
+                }
+                return true;
             }
-            None => {
-                parent_exists = false;
-                Rc::new(RefCell::new(Node {
-                    keys: Vec::new(),
-                    values: Vec::new(),
-                    children: Vec::new(),
-                    parent: None,
-                }))
+        }
+        if i + 1 < siblings {
+            let right_len = parent.borrow().children[i + 1].borrow().keys.len();
+            if right_len < max_keys_per_node {
+                for _ in 0..(node_len - right_len).div_ceil(2) {
+                    BTree::rotate_right(&parent, i, observer);
+                }
+                return true;
             }
-        };
+        }
+        // Both neighbours are full, but a sibling one further away can still
+        // take keys, passed on through the full neighbour. Without this,
+        // ascending or descending inserts split every time they reach the end
+        // of a node, and leave the nodes behind them two thirds full at best.
+        if i > 1 {
+            let far_len = parent.borrow().children[i - 2].borrow().keys.len();
+            if far_len < max_keys_per_node {
+                for _ in 0..(node_len - far_len).div_ceil(2) {
+                    BTree::rotate_left(&parent, i - 1, observer);
+                    BTree::rotate_left(&parent, i, observer);
+                }
+                return true;
+            }
+        }
+        if i + 2 < siblings {
+            let far_len = parent.borrow().children[i + 2].borrow().keys.len();
+            if far_len < max_keys_per_node {
+                for _ in 0..(node_len - far_len).div_ceil(2) {
+                    BTree::rotate_right(&parent, i + 1, observer);
+                    BTree::rotate_right(&parent, i, observer);
+                }
+                return true;
+            }
+        }
+        let left_index = if i + 1 < siblings { i } else { i - 1 };
+        BTree::split_two_into_three(&parent, left_index, max_keys_per_node, observer);
+        true
+    }
 
-        let mut new_right_node = Node {
-            keys: Vec::new(),
-            values: Vec::new(),
-            children: Vec::new(),
-            parent: Some(Rc::clone(&parent)),
+    /// Move the first entry of child `i` to the end of child `i - 1`
+    ///
+    /// The entry goes up into the parent, replacing the separator between the
+    /// two children, and the old separator comes down into the left child. The
+    /// first subtree of child `i` moves along to become the last subtree of the
+    /// left child.
+    fn rotate_left<O: TreeObserver<T> + ?Sized>(
+        parent: &Rc<RefCell<Node<T, V, S>>>,
+        i: usize,
+        observer: &mut O,
+    ) {
+        let (left, right) = {
+            let borrowed_parent = parent.borrow();
+            (
+                Rc::clone(&borrowed_parent.children[i - 1]),
+                Rc::clone(&borrowed_parent.children[i]),
+            )
         };
-        let spare_key;
-        let spare_value;
         {
-            let mut borrowed_child = child_to_split.borrow_mut();
-            borrowed_child.parent = Some(Rc::clone(&parent));
-            println!("Borrowed child keys: {0:?}", borrowed_child.keys);
-            println!(
-                "Borrowed child children: {0:?}",
-                borrowed_child.children.len()
+            let mut borrowed_parent = parent.borrow_mut();
+            let mut borrowed_left = left.borrow_mut();
+            let mut borrowed_right = right.borrow_mut();
+            let key = core::mem::replace(
+                &mut borrowed_parent.keys[i - 1],
+                borrowed_right.keys.remove(0),
             );
-            // TODO: insert key and value into the correct node
-            for i in max_keys_per_node / 2 + 1..max_keys_per_node + 1 {
-                // TODO: pop instead of remove. This is inefficient.
-                println!("Moving key and value with index {i} to new right node");
-                new_right_node
-                    .keys
-                    .push(borrowed_child.keys.remove(max_keys_per_node / 2 + 1));
-                new_right_node
-                    .values
-                    .push(borrowed_child.values.remove(max_keys_per_node / 2 + 1));
-                if borrowed_child.children.len() > max_keys_per_node / 2 {
-                    // if borrowed_child.children.len() > 0 ??
-                    println!("Moving child with index {i} to new right node");
-                    new_right_node
-                        .children
-                        .push(Rc::clone(&borrowed_child.children[i]));
-                    // TODO: needs to be called +1 times since the children vec is longer than
-                    // the keys vec.
-                }
+            let value = core::mem::replace(
+                &mut borrowed_parent.values[i - 1],
+                borrowed_right.values.remove(0),
+            );
+            borrowed_left.keys.push(key);
+            borrowed_left.values.push(value);
+            if !borrowed_right.children.is_empty() {
+                let child = borrowed_right.children.remove(0);
+                child.borrow_mut().parent = Some(Rc::downgrade(&left));
+                borrowed_left.children.push(child);
             }
-            if borrowed_child.children.len() > max_keys_per_node / 2 {
-                // if borrowed_child.children.len() > 0 ??
-                println!("Moving child with index -1 to new right node");
-                new_right_node
-                    .children
-                    .push(Rc::clone(&borrowed_child.children.last().unwrap()));
-                // TODO: needs to be called +1 times since the children vec is longer than
-                // the keys vec.
-            }
-            spare_key = borrowed_child.keys.remove(max_keys_per_node / 2);
-            spare_value = borrowed_child.values.remove(max_keys_per_node / 2);
-            println!("Spare key: {spare_key:?}, Spare value: {spare_value:?}");
-            // FIXME: Should be a different value, truncating too many children
-            // The +2 is a temporary fix
-            let truncate_size = borrowed_child.keys.len() / 2;
-            // borrowed_child.keys.truncate(truncate_size);
-            // borrowed_child.values.truncate(truncate_size);
-            borrowed_child.children.truncate(truncate_size + 2);
-            println!("Borrowed (left) child keys: {0:?}", borrowed_child.keys);
-            println!("New right node keys: {0:?}", new_right_node.keys);
-        }
-
-        let result = {
-            let borrowed_parent = parent.borrow_mut();
-            BTree::insert_key_in_node(
-                borrowed_parent,
-                spare_key.clone(),
-                spare_value.clone(),
-                max_keys_per_node,
+            observer.on_borrow(
+                &borrowed_right.keys,
+                &borrowed_left.keys,
+                &borrowed_parent.keys[i - 1],
+            );
+        }
+        BTree::update_summary(&left);
+        BTree::update_summary(&right);
+    }
+
+    /// Move the last entry of child `i` to the front of child `i + 1`
+    ///
+    /// Mirror image of `rotate_left`.
+    fn rotate_right<O: TreeObserver<T> + ?Sized>(
+        parent: &Rc<RefCell<Node<T, V, S>>>,
+        i: usize,
+        observer: &mut O,
+    ) {
+        let (left, right) = {
+            let borrowed_parent = parent.borrow();
+            (
+                Rc::clone(&borrowed_parent.children[i]),
+                Rc::clone(&borrowed_parent.children[i + 1]),
             )
         };
-        println!("Parent node before connecting children: {0:?}", parent);
-        // If the parent Node already existed, only the new right node needs to be connected
-        // How can we check if the parent already existed? At this stage, we have created
-        // the parent if it did not exist.
-
-        if parent_exists {
-            println!("Parent already existed");
-            BTree::connect_children_to_parent(
-                parent.borrow_mut(),
-                None,
-                Rc::new(RefCell::new(new_right_node)),
+        {
+            let mut borrowed_parent = parent.borrow_mut();
+            let mut borrowed_left = left.borrow_mut();
+            let mut borrowed_right = right.borrow_mut();
+            let key = core::mem::replace(
+                &mut borrowed_parent.keys[i],
+                borrowed_left.keys.pop().unwrap(),
             );
-        } else {
-            println!("Parent did not exist");
-            BTree::connect_children_to_parent(
-                parent.borrow_mut(),
-                Some(Rc::clone(&child_to_split)),
-                Rc::new(RefCell::new(new_right_node)),
+            let value = core::mem::replace(
+                &mut borrowed_parent.values[i],
+                borrowed_left.values.pop().unwrap(),
+            );
+            borrowed_right.keys.insert(0, key);
+            borrowed_right.values.insert(0, value);
+            if let Some(child) = borrowed_left.children.pop() {
+                child.borrow_mut().parent = Some(Rc::downgrade(&right));
+                borrowed_right.children.insert(0, child);
+            }
+            observer.on_borrow(
+                &borrowed_left.keys,
+                &borrowed_right.keys,
+                &borrowed_parent.keys[i],
             );
         }
-        println!("Parent node after connecting children: {0:?}", parent);
-        match result {
-            Ok(_) => return Ok(parent),
-            Err(e) if e == "Node is full" => {
-                println!("Parent node is also full, splitting");
-                return BTree::split_node(Rc::clone(&parent), max_keys_per_node);
+        BTree::update_summary(&left);
+        BTree::update_summary(&right);
+    }
+
+    /// Split children `left_index` and `left_index + 1` into three nodes
+    ///
+    /// All entries of both children and the separator between them are spread
+    /// evenly over the two existing nodes and a new third node, with two
+    /// separators ending up in the parent. The parent gains one key, which can
+    /// make it overflow.
+    fn split_two_into_three<O: TreeObserver<T> + ?Sized>(
+        parent: &Rc<RefCell<Node<T, V, S>>>,
+        left_index: usize,
+        max_keys_per_node: usize,
+        observer: &mut O,
+    ) {
+        let (left, middle) = {
+            let borrowed_parent = parent.borrow();
+            (
+                Rc::clone(&borrowed_parent.children[left_index]),
+                Rc::clone(&borrowed_parent.children[left_index + 1]),
+            )
+        };
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        let mut children = Vec::new();
+        {
+            let mut borrowed_parent = parent.borrow_mut();
+            let mut borrowed_left = left.borrow_mut();
+            let mut borrowed_middle = middle.borrow_mut();
+            keys.append(&mut borrowed_left.keys);
+            values.append(&mut borrowed_left.values);
+            children.append(&mut borrowed_left.children);
+            keys.push(borrowed_parent.keys.remove(left_index));
+            values.push(borrowed_parent.values.remove(left_index));
+            keys.append(&mut borrowed_middle.keys);
+            values.append(&mut borrowed_middle.values);
+            children.append(&mut borrowed_middle.children);
+        }
+
+        // Two of the keys become separators in the parent
+        let left_len = (keys.len() - 2) / 3;
+        let middle_len = (keys.len() - 2 - left_len) / 2;
+        let right = Node {
+            keys: keys.split_off(left_len + middle_len + 2),
+            values: values.split_off(left_len + middle_len + 2),
+            children: if children.is_empty() {
+                Vec::new()
+            } else {
+                children.split_off(left_len + middle_len + 2)
+            },
+            parent: None,
+            size: 0,
+            summary: S::identity(),
+        };
+        let right = Rc::new(RefCell::new(right));
+        let second_key = keys.pop().unwrap();
+        let second_value = values.pop().unwrap();
+        {
+            let mut borrowed_middle = middle.borrow_mut();
+            borrowed_middle.keys = keys.split_off(left_len + 1);
+            borrowed_middle.values = values.split_off(left_len + 1);
+            if !children.is_empty() {
+                borrowed_middle.children = children.split_off(left_len + 1);
             }
-            Err(_) => panic!("Invalid state?"),
         }
+        let first_key = keys.pop().unwrap();
+        let first_value = values.pop().unwrap();
+        {
+            let mut borrowed_left = left.borrow_mut();
+            borrowed_left.keys = keys;
+            borrowed_left.values = values;
+            borrowed_left.children = children;
+        }
+        for node in [&left, &middle, &right] {
+            BTree::adopt_children(node);
+            BTree::update_summary(node);
+        }
+        observer.on_split(&middle.borrow().keys, &right.borrow().keys, &second_key);
+
+        let _ = BTree::insert_key_in_node(
+            parent.borrow_mut(),
+            first_key,
+            first_value,
+            max_keys_per_node,
+        );
+        let _ = BTree::insert_key_in_node(
+            parent.borrow_mut(),
+            second_key,
+            second_value,
+            max_keys_per_node,
+        );
+        BTree::connect_children_to_parent(parent, middle, right, observer);
     }
 
-    fn connect_children_to_parent(
-        mut parent: RefMut<Node<T, V>>,
-        left_child: Option<Rc<RefCell<Node<T, V>>>>,
-        right_child: Rc<RefCell<Node<T, V>>>,
+    /// Connect the `right_child` to the parent, directly after the `left_child`
+    ///
+    /// If the `left_child` is not a child of the parent yet, the parent is a new
+    /// root and the `left_child` is connected first.
+    fn connect_children_to_parent<O: TreeObserver<T> + ?Sized>(
+        parent: &Rc<RefCell<Node<T, V, S>>>,
+        left_child: Rc<RefCell<Node<T, V, S>>>,
+        right_child: Rc<RefCell<Node<T, V, S>>>,
+        observer: &mut O,
     ) {
-        // NOTE: can we assume both left and right have keys? I think so
-        let keys = parent.keys.clone();
-        if left_child.is_none() {
-            let right_child_keys = &right_child.borrow().keys;
-            let right_child_min_key = &right_child_keys[0];
-            let right_child_max_key = &right_child_keys[right_child_keys.len() - 1];
-            for (idx, key) in keys.iter().enumerate() {
-                if idx == keys.len() - 1 {
-                    parent.children.push(Rc::clone(&right_child));
-                    return;
-                }
-                if right_child_min_key > key && right_child_max_key < &keys[idx + 1] {
-                    parent.children.insert(idx + 1, Rc::clone(&right_child));
-                    return;
-                }
+        let mut borrowed_parent = parent.borrow_mut();
+        let position = borrowed_parent
+            .children
+            .iter()
+            .position(|child| Rc::ptr_eq(child, &left_child));
+        let idx = match position {
+            Some(idx) => idx,
+            None => {
+                // Only a new root has no children yet, the tree grows taller here
+                observer.on_root_change(&borrowed_parent.keys);
+                let height = BTree::subtree_height(&left_child);
+                observer.on_height_change(height, height + 1);
+                borrowed_parent.children.push(Rc::clone(&left_child));
+                borrowed_parent.children.len() - 1
             }
-        } else {
-            let left_child = left_child.unwrap();
-            let right_child_min_key = &right_child.borrow().keys[0];
-            let left_child_keys = &left_child.borrow().keys;
-            let left_child_max_key = &left_child_keys[left_child_keys.len() - 1];
-            for (idx, key) in keys.iter().enumerate() {
-                if left_child_max_key < key && key < right_child_min_key {
-                    parent.children.insert(idx, Rc::clone(&left_child));
-                    parent.children.insert(idx + 1, Rc::clone(&right_child));
-                    return;
-                }
+        };
+        left_child.borrow_mut().parent = Some(Rc::downgrade(parent));
+        right_child.borrow_mut().parent = Some(Rc::downgrade(parent));
+        borrowed_parent.children.insert(idx + 1, right_child);
+    }
+
+    /// Recount the entries below `node` and recompute its summary
+    ///
+    /// Both are built from the entries of the node itself and the cached
+    /// values of its children, so the children must be up to date already.
+    fn update_summary(node: &Rc<RefCell<Node<T, V, S>>>) {
+        let mut borrowed_node = node.borrow_mut();
+        let mut size = borrowed_node.keys.len();
+        let mut summary = S::identity();
+        for (i, (key, value)) in borrowed_node
+            .keys
+            .iter()
+            .zip(borrowed_node.values.iter())
+            .enumerate()
+        {
+            if let Some(child) = borrowed_node.children.get(i) {
+                let borrowed_child = child.borrow();
+                size += borrowed_child.size;
+                summary = summary.combine(&borrowed_child.summary);
             }
+            summary = summary.combine(&S::from_entry(key, value));
+        }
+        if let Some(child) = borrowed_node.children.get(borrowed_node.keys.len()) {
+            let borrowed_child = child.borrow();
+            size += borrowed_child.size;
+            summary = summary.combine(&borrowed_child.summary);
+        }
+        borrowed_node.size = size;
+        borrowed_node.summary = summary;
+    }
+
+    /// Point the parent link of all children of `node` back at `node`
+    fn adopt_children(node: &Rc<RefCell<Node<T, V, S>>>) {
+        for child in node.borrow().children.iter() {
+            child.borrow_mut().parent = Some(Rc::downgrade(node));
         }
     }
 
+    fn child_position(parent: &Node<T, V, S>, child: &Rc<RefCell<Node<T, V, S>>>) -> usize {
+        parent
+            .children
+            .iter()
+            .position(|current_child| Rc::ptr_eq(current_child, child))
+            .expect("Node is not a child of its parent")
+    }
+
     /// Insert key and value into the node
     ///
     /// Iterate over th existing keys in the node to find the correct position. If the
@@ -428,7 +1369,7 @@ where
     /// If the node would go over the maximum number of keys, do insert it but return
     /// an error.
     fn insert_key_in_node(
-        mut current_node: RefMut<Node<T, V>>,
+        mut current_node: RefMut<Node<T, V, S>>,
         key: T,
         value: V,
         max_keys_per_node: usize,
@@ -443,7 +1384,7 @@ where
                 current_node.keys.insert(i, key);
                 current_node.values.insert(i, value);
                 let len = current_node.keys.len();
-                if len >= max_keys_per_node + 1 {
+                if len > max_keys_per_node {
                     return Err("Node is full");
                 }
                 return Ok(());
@@ -453,23 +1394,26 @@ where
         current_node.values.push(value);
 
         let len = current_node.keys.len();
-        if len >= max_keys_per_node + 1 {
+        if len > max_keys_per_node {
             return Err("Node is full");
         }
-        return Ok(());
+        Ok(())
     }
 
-    fn traverse_search(current_node: Rc<RefCell<Node<T, V>>>, key: T) -> Result<V, &'static str> {
+    fn traverse_search(
+        current_node: Rc<RefCell<Node<T, V, S>>>,
+        key: T,
+    ) -> Result<V, &'static str> {
         let borrowed_node = current_node.borrow();
-        if borrowed_node.children.len() > 0 {
+        if !borrowed_node.children.is_empty() {
             BTree::iterate_over_node_with_children(current_node.clone(), key)
         } else {
             BTree::iterate_over_node_without_children(current_node.clone(), key)
         }
     }
 
-    fn iterate_over_node_with_children<'a>(
-        current_node: Rc<RefCell<Node<T, V>>>,
+    fn iterate_over_node_with_children(
+        current_node: Rc<RefCell<Node<T, V, S>>>,
         key: T,
     ) -> Result<V, &'static str> {
         for (i, current_key) in current_node.borrow().keys.iter().enumerate() {
@@ -486,8 +1430,8 @@ where
         );
     }
 
-    fn iterate_over_node_without_children<'a>(
-        current_node: Rc<RefCell<Node<T, V>>>,
+    fn iterate_over_node_without_children(
+        current_node: Rc<RefCell<Node<T, V, S>>>,
         key: T,
     ) -> Result<V, &'static str> {
         for (i, current_key) in current_node.borrow().keys.iter().enumerate() {
@@ -495,24 +1439,71 @@ where
                 return Ok(current_node.borrow().values[i].clone());
             }
         }
-        return Err("Key not found");
+        Err("Key not found")
     }
 }
 
-impl<T, V> fmt::Display for Node<T, V>
+impl<T, V, S> Node<T, V, S>
 where
     T: PartialOrd + Clone + Debug,
     V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    fn new_empty() -> Self {
+        Node {
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
+            parent: None,
+            size: 0,
+            summary: S::identity(),
+        }
+    }
+}
+
+impl<T, V, S> fmt::Display for Node<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, key) in self.keys.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{key:?}")?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Shows the keys of every node, one node per line, indented by its depth
+impl<T, V, S> fmt::Display for BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // TODO: implement Display for Node
-        write!(f, "Node")
+        // Children are pushed in reverse, so the leftmost child is shown first
+        let mut stack = vec![(Rc::clone(&self.root), 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let borrowed_node = node.borrow();
+            writeln!(f, "{:indent$}{borrowed_node}", "", indent = 2 * depth)?;
+            for child in borrowed_node.children.iter().rev() {
+                stack.push((Rc::clone(child), depth + 1));
+            }
+        }
+        Ok(())
     }
 }
-impl<T, V> fmt::Debug for Node<T, V>
+impl<T, V, S> fmt::Debug for Node<T, V, S>
 where
     T: PartialOrd + Clone + Debug,
     V: Clone + Debug,
+    S: Summary<T, V>,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let parent = match &self.parent {
@@ -526,3 +1517,242 @@ where
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::vec;
+
+    use super::{BTree, BTreeConfig, InsertionMode, OverflowPolicy};
+    use crate::test_util::{assert_matches, configs, random_map, tree_from, Rng};
+
+    /// Fill factors after ascending, descending and random inserts
+    fn fill_factors(config: BTreeConfig) -> [f64; 3] {
+        let mut rng = Rng::new(16);
+        let mut ascending: BTree<u32, u32> = config.build().unwrap();
+        let mut descending: BTree<u32, u32> = config.build().unwrap();
+        let mut random: BTree<u32, u32> = config.build().unwrap();
+        for key in 0..3000 {
+            ascending.insert(key, key).unwrap();
+            descending.insert(3000 - key, key).unwrap();
+            let _ = random.insert(rng.below(1_000_000), key);
+        }
+        [ascending, descending, random].map(|tree| tree.stats().fill_factor)
+    }
+
+    #[test]
+    fn redistribution_keeps_nodes_two_thirds_full() {
+        for order in 4..=9 {
+            for insertion_mode in [InsertionMode::BottomUp, InsertionMode::TopDown] {
+                let config = BTreeConfig::new()
+                    .order(order)
+                    .insertion_mode(insertion_mode);
+                let split = fill_factors(config.overflow_policy(OverflowPolicy::Split));
+                let redistribute =
+                    fill_factors(config.overflow_policy(OverflowPolicy::Redistribute));
+                for (split, redistribute) in split.into_iter().zip(redistribute) {
+                    assert!(
+                        redistribute > split,
+                        "{config:?}: {redistribute} <= {split}"
+                    );
+                    // Top-down insertion splits a node before it overflows, one key early
+                    if insertion_mode == InsertionMode::BottomUp || order > 4 {
+                        assert!(redistribute >= 2.0 / 3.0, "{config:?}: {redistribute}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sorted_inserts_and_removes_match_btreemap() {
+        // Inserting in order keeps filling the last node, so with
+        // `OverflowPolicy::Redistribute` full siblings are split two into three
+        for config in configs() {
+            for descending in [false, true] {
+                let mut tree = config.build().unwrap();
+                let mut map = BTreeMap::new();
+                for i in 0..400 {
+                    let key = if descending { 400 - i } else { i };
+                    tree.insert(key, i).unwrap();
+                    map.insert(key, i);
+                    if i % 20 == 0 {
+                        assert_matches(&tree, &map);
+                    }
+                }
+                assert_matches(&tree, &map);
+                for i in 0..400 {
+                    let key = if descending { i + 1 } else { 399 - i };
+                    assert_eq!(tree.remove(key).ok(), map.remove(&key));
+                    if i % 20 == 0 {
+                        assert_matches(&tree, &map);
+                    }
+                }
+                assert!(tree.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn redistribution_shifts_keys_before_splitting() {
+        let mut tree: BTree<u32, u32> = BTreeConfig::new()
+            .order(5)
+            .overflow_policy(OverflowPolicy::Redistribute)
+            .build()
+            .unwrap();
+        let mut insert = |keys: core::ops::RangeInclusive<u32>| {
+            for key in keys {
+                tree.insert(key, key).unwrap();
+            }
+            tree.levels()
+        };
+        // The root has no siblings, so it is split in half
+        assert_eq!(
+            insert(1..=5),
+            vec![vec![vec![3]], vec![vec![1, 2], vec![4, 5]]]
+        );
+        // The full right leaf shifts keys into its left sibling
+        assert_eq!(
+            insert(6..=8),
+            vec![vec![vec![5]], vec![vec![1, 2, 3, 4], vec![6, 7, 8]]]
+        );
+        // Both leaves are full, so they are split into three
+        assert_eq!(
+            insert(9..=10),
+            vec![
+                vec![vec![3, 7]],
+                vec![vec![1, 2], vec![4, 5, 6], vec![8, 9, 10]]
+            ]
+        );
+        // The left neighbour of the last leaf is full, the keys are passed on through it
+        assert_eq!(
+            insert(11..=13),
+            vec![
+                vec![vec![5, 10]],
+                vec![vec![1, 2, 3, 4], vec![6, 7, 8, 9], vec![11, 12, 13]]
+            ]
+        );
+    }
+
+    #[test]
+    fn random_operations_match_btreemap() {
+        let mut rng = Rng::new(1);
+        for config in configs() {
+            let mut tree = config.build().unwrap();
+            let mut map = BTreeMap::new();
+            for step in 0..2000 {
+                let key = rng.below(300);
+                match rng.below(10) {
+                    0..=4 => {
+                        let fresh = !map.contains_key(&key);
+                        assert_eq!(tree.insert(key, step).is_ok(), fresh);
+                        map.entry(key).or_insert(step);
+                    }
+                    5..=7 => assert_eq!(tree.remove(key).ok(), map.remove(&key)),
+                    8 => assert_eq!(tree.pop_first(), map.pop_first()),
+                    _ => assert_eq!(tree.pop_last(), map.pop_last()),
+                }
+                assert_eq!(tree.get(key), map.get(&key).copied());
+                if step % 50 == 0 {
+                    assert_matches(&tree, &map);
+                }
+            }
+            assert_matches(&tree, &map);
+        }
+    }
+
+    #[test]
+    fn top_down_insertion_splits_full_nodes_on_the_way_down() {
+        let build = |insertion_mode| {
+            let mut tree: BTree<u32, u32> = BTreeConfig::new()
+                .order(5)
+                .insertion_mode(insertion_mode)
+                .build()
+                .unwrap();
+            for key in (10..=130).step_by(10).chain([135, 5]) {
+                tree.insert(key, key).unwrap();
+            }
+            tree
+        };
+        let leaves = vec![
+            vec![5, 10, 20],
+            vec![40, 50],
+            vec![70, 80],
+            vec![100, 110],
+            vec![130, 135],
+        ];
+
+        // 5 fits in its leaf, so bottom-up insertion leaves the full root alone
+        let bottom_up = build(InsertionMode::BottomUp);
+        assert_eq!(
+            bottom_up.levels(),
+            vec![vec![vec![30, 60, 90, 120]], leaves.clone()]
+        );
+
+        // Top-down insertion splits the full root before it looks at the leaf.
+        // With 4 keys per node, the split leaves one node with a single key,
+        // one less than the minimum for removal.
+        let mut top_down = build(InsertionMode::TopDown);
+        assert_eq!(
+            top_down.levels(),
+            vec![vec![vec![90]], vec![vec![30, 60], vec![120]], leaves]
+        );
+        top_down.validate().unwrap();
+
+        // Removal still rebalances that node like any other
+        for key in [100, 110, 130, 135, 90] {
+            top_down.remove(key).unwrap();
+            top_down.validate().unwrap();
+        }
+        assert_eq!(top_down.len(), 10);
+    }
+
+    #[test]
+    fn popping_from_both_ends_matches_btreemap() {
+        let mut rng = Rng::new(31);
+        for config in configs() {
+            let mut map = random_map(&mut rng, 150, 1000);
+            let mut tree = tree_from(&config, &map);
+            while !map.is_empty() {
+                assert_eq!(
+                    tree.first_key_value(),
+                    map.first_key_value().map(|(key, value)| (*key, *value))
+                );
+                assert_eq!(
+                    tree.last_key_value(),
+                    map.last_key_value().map(|(key, value)| (*key, *value))
+                );
+                if rng.below(2) == 0 {
+                    assert_eq!(tree.pop_first(), map.pop_first());
+                } else {
+                    assert_eq!(tree.pop_last(), map.pop_last());
+                }
+                assert_matches(&tree, &map);
+            }
+            assert_eq!(tree.first_key_value(), None);
+            assert_eq!(tree.last_key_value(), None);
+            assert_eq!(tree.pop_first(), None);
+            assert_eq!(tree.pop_last(), None);
+        }
+    }
+
+    #[test]
+    fn first_and_last_of_a_single_entry_are_the_same() {
+        let mut tree = BTree::empty(3);
+        tree.insert(7, "seven").unwrap();
+        assert_eq!(tree.first_key_value(), Some((7, "seven")));
+        assert_eq!(tree.last_key_value(), Some((7, "seven")));
+        assert_eq!(tree.pop_last(), Some((7, "seven")));
+        assert_eq!(tree.pop_first(), None);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn errors_can_be_kept_while_the_tree_changes() {
+        let mut tree = BTree::empty(3);
+        tree.insert(1, 10).unwrap();
+        let errors = [tree.insert(1, 11).unwrap_err(), tree.remove(2).unwrap_err()];
+        tree.insert(2, 20).unwrap();
+        assert_eq!(errors, ["Key already exists", "Key not found"]);
+    }
+}
@@ -0,0 +1,285 @@
+use alloc::collections::vec_deque;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::ops::RangeBounds;
+
+use super::{BTree, OwnedKeyPosition, Range};
+
+/// BTreeMultiMap
+///
+/// A `BTree` that allows the same key to be inserted more than once, for
+/// indexing non-unique attributes. Every key is stored once in the tree, with
+/// all values inserted under it kept in insertion order.
+#[derive(Debug)]
+pub struct BTreeMultiMap<T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    tree: BTree<T, VecDeque<V>>,
+    len: usize,
+}
+
+/// Iterator over the entries of a `BTreeMultiMap` in key order
+///
+/// Created by `BTreeMultiMap::iter` and `BTreeMultiMap::range`. Yields every
+/// value stored under a key, in insertion order, before moving to the next key.
+/// The values stay in the tree, only the one being yielded is cloned.
+pub struct MultiRange<'a, T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    entries: Range<'a, T, VecDeque<V>>,
+    // The node holding the current key and its position there
+    current: Option<OwnedKeyPosition<T, VecDeque<V>, ()>>,
+    // Position of the next value to yield among the values of the current key
+    next_value: usize,
+}
+
+/// Iterator over the values stored under one key of a `BTreeMultiMap`
+///
+/// Created by `BTreeMultiMap::get_all`, yields nothing if the key is missing.
+pub struct Values<'a, V> {
+    values: Option<vec_deque::Iter<'a, V>>,
+}
+
+impl<'a, V> Iterator for Values<'a, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.values.as_mut()?.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.values
+            .as_ref()
+            .map_or((0, Some(0)), Iterator::size_hint)
+    }
+}
+
+impl<V> DoubleEndedIterator for Values<'_, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.values.as_mut()?.next_back()
+    }
+}
+
+impl<V> ExactSizeIterator for Values<'_, V> {}
+
+impl<T, V> BTreeMultiMap<T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    pub fn new(max_keys_per_node: usize) -> Self {
+        BTreeMultiMap {
+            tree: BTree::empty(max_keys_per_node),
+            len: 0,
+        }
+    }
+
+    /// Total number of values, counting every duplicate
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert the value after any values already stored under `key`
+    pub fn insert(&mut self, key: T, value: V) {
+        let value = match self.tree.get_mut(key.clone()) {
+            Some(mut values) => {
+                values.push_back(value);
+                None
+            }
+            None => Some(value),
+        };
+        if let Some(value) = value {
+            let _ = self.tree.insert(key, VecDeque::from([value]));
+        }
+        self.len += 1;
+    }
+
+    pub fn contains_key(&self, key: &T) -> bool {
+        self.tree.exists(key.clone())
+    }
+
+    /// Iterate over all values stored under `key`, in insertion order
+    pub fn get_all(&self, key: &T) -> Values<'_, V> {
+        Values {
+            values: self.tree.get_ref(key).map(VecDeque::iter),
+        }
+    }
+
+    /// Number of values stored under `key`
+    pub fn count(&self, key: &T) -> usize {
+        self.tree.get_ref(key).map_or(0, VecDeque::len)
+    }
+
+    /// Remove the oldest value stored under `key`
+    ///
+    /// The key itself is removed from the tree together with its last value.
+    pub fn remove_one(&mut self, key: &T) -> Option<V> {
+        let (value, is_empty) = {
+            let mut values = self.tree.get_mut(key.clone())?;
+            let value = values.pop_front()?;
+            (value, values.is_empty())
+        };
+        if is_empty {
+            let _ = self.tree.remove(key.clone());
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Remove all values stored under `key`, returning them in insertion order
+    pub fn remove_all(&mut self, key: &T) -> Vec<V> {
+        let values = self.tree.remove(key.clone()).unwrap_or_default();
+        self.len -= values.len();
+        Vec::from(values)
+    }
+
+    /// Iterate over all entries in key order, including every duplicate
+    pub fn iter(&self) -> MultiRange<'_, T, V> {
+        self.range(..)
+    }
+
+    /// Iterate over the entries with keys inside `range`, including every duplicate
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> MultiRange<'_, T, V> {
+        MultiRange {
+            entries: self.tree.range(range),
+            current: None,
+            next_value: 0,
+        }
+    }
+}
+
+impl<T, V> Iterator for MultiRange<'_, T, V>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+{
+    type Item = (T, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((node, i)) = &self.current {
+                let borrowed_node = node.borrow();
+                if let Some(value) = borrowed_node.values[*i].get(self.next_value) {
+                    self.next_value += 1;
+                    return Some((borrowed_node.keys[*i].clone(), value.clone()));
+                }
+            }
+            let (node, i) = self.entries.advance()?;
+            self.current = Some((node, i));
+            self.next_value = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
+
+    use super::BTreeMultiMap;
+    use crate::test_util::Rng;
+
+    #[test]
+    fn random_operations_match_a_map_of_vectors() {
+        let mut rng = Rng::new(13);
+        for max_keys_per_node in 2..=8 {
+            let mut multimap = BTreeMultiMap::new(max_keys_per_node);
+            let mut expected: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+            for step in 0..1000 {
+                let key = rng.below(100);
+                match rng.below(6) {
+                    0..=2 => {
+                        multimap.insert(key, step);
+                        expected.entry(key).or_default().push(step);
+                    }
+                    3 | 4 => {
+                        let removed = expected.get_mut(&key).map(|values| values.remove(0));
+                        if expected.get(&key).is_some_and(Vec::is_empty) {
+                            expected.remove(&key);
+                        }
+                        assert_eq!(multimap.remove_one(&key), removed);
+                    }
+                    _ => {
+                        let removed = expected.remove(&key).unwrap_or_default();
+                        assert_eq!(multimap.remove_all(&key), removed);
+                    }
+                }
+                let values = expected.get(&key).map_or(&[][..], Vec::as_slice);
+                let stored: Vec<_> = multimap.get_all(&key).copied().collect();
+                assert_eq!(stored, values);
+                assert_eq!(multimap.count(&key), values.len());
+                assert_eq!(multimap.contains_key(&key), !values.is_empty());
+            }
+            let entries: Vec<_> = multimap.iter().collect();
+            let flattened: Vec<_> = expected
+                .iter()
+                .flat_map(|(key, values)| values.iter().map(|value| (*key, *value)))
+                .collect();
+            assert_eq!(multimap.len(), flattened.len());
+            assert_eq!(entries, flattened);
+        }
+    }
+
+    #[test]
+    fn remove_one_takes_the_oldest_value_and_drops_the_key_with_the_last() {
+        let mut multimap = BTreeMultiMap::new(3);
+        for value in ["a", "b", "c"] {
+            multimap.insert(1, value);
+        }
+        assert_eq!(multimap.remove_one(&1), Some("a"));
+        assert_eq!(multimap.get_all(&1).len(), 2);
+        assert_eq!(multimap.remove_one(&1), Some("b"));
+        assert_eq!(multimap.remove_one(&1), Some("c"));
+        assert!(!multimap.contains_key(&1));
+        assert_eq!(multimap.remove_one(&1), None);
+        assert!(multimap.is_empty());
+    }
+
+    #[test]
+    fn missing_keys_have_no_values() {
+        let mut multimap = BTreeMultiMap::new(3);
+        assert_eq!(multimap.get_all(&7).next(), None);
+        assert_eq!(multimap.get_all(&7).len(), 0);
+        assert_eq!(multimap.count(&7), 0);
+        assert_eq!(multimap.remove_all(&7), Vec::<u32>::new());
+        multimap.insert(5, 50);
+        assert_eq!(multimap.get_all(&7).next(), None);
+        assert_eq!(multimap.len(), 1);
+    }
+
+    #[test]
+    fn get_all_iterates_from_both_ends() {
+        let mut multimap = BTreeMultiMap::new(3);
+        for value in 0..5 {
+            multimap.insert("key", value);
+        }
+        let reversed: Vec<_> = multimap.get_all(&"key").rev().copied().collect();
+        assert_eq!(reversed, [4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn range_yields_every_duplicate_of_the_keys_inside_it() {
+        let mut multimap = BTreeMultiMap::new(2);
+        for key in 0..10 {
+            for copy in 0..=key % 3 {
+                multimap.insert(key, key * 10 + copy);
+            }
+        }
+        let entries: Vec<_> = multimap.range(3..6).collect();
+        assert_eq!(
+            entries,
+            [(3, 30), (4, 40), (4, 41), (5, 50), (5, 51), (5, 52)]
+        );
+        assert_eq!(multimap.range(20..).next(), None);
+        assert_eq!(multimap.iter().count(), multimap.len());
+    }
+}
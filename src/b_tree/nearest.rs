@@ -0,0 +1,142 @@
+use alloc::rc::Rc;
+use core::fmt::Debug;
+
+use super::{BTree, Summary};
+
+/// Which side of the searched key an entry should be on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Below,
+    Above,
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// The entry with the largest key that is smaller than or equal to `key`
+    pub fn floor(&self, key: &T) -> Option<(T, V)> {
+        self.nearest(key, Side::Below, true)
+    }
+
+    /// The entry with the smallest key that is larger than or equal to `key`
+    pub fn ceiling(&self, key: &T) -> Option<(T, V)> {
+        self.nearest(key, Side::Above, true)
+    }
+
+    /// The entry with the largest key that is strictly smaller than `key`
+    pub fn lower(&self, key: &T) -> Option<(T, V)> {
+        self.nearest(key, Side::Below, false)
+    }
+
+    /// The entry with the smallest key that is strictly larger than `key`
+    pub fn higher(&self, key: &T) -> Option<(T, V)> {
+        self.nearest(key, Side::Above, false)
+    }
+
+    /// Find the entry closest to `key` on the given side in a single descent
+    ///
+    /// In every node on the way down, the closest key on the right side of
+    /// `key` is a candidate. The child that is descended into only holds keys
+    /// between that candidate and `key`, so any entry found further down is
+    /// closer and replaces the candidate. With `inclusive`, an exact match
+    /// ends the search early.
+    fn nearest(&self, key: &T, side: Side, inclusive: bool) -> Option<(T, V)> {
+        let mut candidate = None;
+        let mut current_node = self.root.clone();
+        loop {
+            let child = {
+                let borrowed_node = current_node.borrow();
+                let i = borrowed_node
+                    .keys
+                    .partition_point(|current_key| current_key < key);
+                let found = i < borrowed_node.keys.len() && borrowed_node.keys[i] == *key;
+                if found && inclusive {
+                    return Some((key.clone(), borrowed_node.values[i].clone()));
+                }
+                // Keys before `i` are smaller than `key`, keys after an exact
+                // match are larger
+                let closest = match side {
+                    Side::Below => i.checked_sub(1),
+                    Side::Above if found => Some(i + 1),
+                    Side::Above => Some(i),
+                };
+                if let Some(j) = closest.filter(|j| *j < borrowed_node.keys.len()) {
+                    let entry_key = borrowed_node.keys[j].clone();
+                    candidate = Some((entry_key, borrowed_node.values[j].clone()));
+                }
+                let child = match (side, found) {
+                    (Side::Above, true) => i + 1,
+                    _ => i,
+                };
+                match borrowed_node.children.get(child) {
+                    Some(child) => Rc::clone(child),
+                    None => return candidate,
+                }
+            };
+            current_node = child;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ops::Bound;
+
+    use super::BTree;
+    use crate::test_util::{configs, random_map, tree_from, Rng};
+
+    #[test]
+    fn nearest_entries_match_btreemap() {
+        let mut rng = Rng::new(15);
+        for config in configs() {
+            let map = random_map(&mut rng, 100, 400);
+            let tree = tree_from(&config, &map);
+            let copied = |entry: Option<(&u32, &u32)>| entry.map(|(key, value)| (*key, *value));
+            for key in 0..410 {
+                assert_eq!(tree.floor(&key), copied(map.range(..=key).next_back()));
+                assert_eq!(tree.ceiling(&key), copied(map.range(key..).next()));
+                assert_eq!(tree.lower(&key), copied(map.range(..key).next_back()));
+                let above = (Bound::Excluded(key), Bound::Unbounded);
+                assert_eq!(tree.higher(&key), copied(map.range(above).next()));
+            }
+        }
+    }
+
+    #[test]
+    fn exact_matches_count_only_for_floor_and_ceiling() {
+        let mut tree = BTree::empty(3);
+        for key in [10, 20, 30, 40, 50, 60, 70] {
+            tree.insert(key, key / 10).unwrap();
+        }
+        assert_eq!(tree.floor(&40), Some((40, 4)));
+        assert_eq!(tree.ceiling(&40), Some((40, 4)));
+        assert_eq!(tree.lower(&40), Some((30, 3)));
+        assert_eq!(tree.higher(&40), Some((50, 5)));
+        // Between two keys, the inclusive and strict lookups agree
+        assert_eq!(tree.floor(&45), tree.lower(&45));
+        assert_eq!(tree.ceiling(&45), tree.higher(&45));
+    }
+
+    #[test]
+    fn keys_outside_the_tree_find_one_side_only() {
+        let mut tree = BTree::empty(3);
+        for key in [10, 20, 30, 40, 50] {
+            tree.insert(key, ()).unwrap();
+        }
+        assert_eq!(tree.floor(&5), None);
+        assert_eq!(tree.lower(&10), None);
+        assert_eq!(tree.ceiling(&5), Some((10, ())));
+        assert_eq!(tree.ceiling(&55), None);
+        assert_eq!(tree.higher(&50), None);
+        assert_eq!(tree.floor(&55), Some((50, ())));
+
+        let empty: BTree<u32, ()> = BTree::empty(3);
+        assert_eq!(empty.floor(&1), None);
+        assert_eq!(empty.ceiling(&1), None);
+        assert_eq!(empty.lower(&1), None);
+        assert_eq!(empty.higher(&1), None);
+    }
+}
@@ -0,0 +1,336 @@
+use alloc::boxed::Box;
+use core::fmt;
+use core::fmt::Debug;
+
+use super::{BTree, Summary};
+
+/// Callbacks for the changes to the structure of a `BTree`
+///
+/// Registered with `BTree::set_observer`, and called by `insert`, `remove`,
+/// `pop_first` and `pop_last` while they restructure the tree. Nodes are
+/// passed as their keys, after the change. All methods do nothing by default,
+/// so an observer only implements the events it is interested in.
+///
+/// Operations that cut the tree apart and join the parts back together, like
+/// `append`, `split_off`, `retain`, `extract_if` and the range removals,
+/// report the splits, merges and borrows done while joining. The root and the
+/// height are reported once the operation is done, if they changed, and not
+/// for the parts that are built along the way. `compact` builds all nodes from
+/// scratch, so it only reports the new root and height.
+pub trait TreeObserver<T> {
+    /// A node was split in two, with `separator` moved up into the parent
+    ///
+    /// A B*-style split of two full siblings into three nodes is reported as a
+    /// split of the second sibling, with `right` as the new node.
+    fn on_split(&mut self, left: &[T], right: &[T], separator: &T) {
+        let _ = (left, right, separator);
+    }
+
+    /// Two siblings were merged into one node, with `separator` pulled down from the parent
+    fn on_merge(&mut self, merged: &[T], separator: &T) {
+        let _ = (merged, separator);
+    }
+
+    /// An entry moved from `lender` into its sibling `borrower` through the parent,
+    /// where `separator` now separates the two
+    fn on_borrow(&mut self, lender: &[T], borrower: &[T], separator: &T) {
+        let _ = (lender, borrower, separator);
+    }
+
+    /// Another node became the root
+    fn on_root_change(&mut self, root: &[T]) {
+        let _ = root;
+    }
+
+    /// The number of levels of nodes changed, when the root was split or merged away
+    fn on_height_change(&mut self, old_height: usize, new_height: usize) {
+        let _ = (old_height, new_height);
+    }
+}
+
+/// Observes nothing, passed around when no observer is registered
+///
+/// All calls on it are empty and are compiled away, so a tree without an
+/// observer does not pay for the hooks.
+impl<T> TreeObserver<T> for () {}
+
+/// Passes the splits, merges and borrows of a bulk operation on to `observer`,
+/// but not the roots and heights of the parts it builds along the way
+pub struct Restructuring<'a, T>(pub &'a mut dyn TreeObserver<T>);
+
+impl<T> TreeObserver<T> for Restructuring<'_, T> {
+    fn on_split(&mut self, left: &[T], right: &[T], separator: &T) {
+        self.0.on_split(left, right, separator);
+    }
+
+    fn on_merge(&mut self, merged: &[T], separator: &T) {
+        self.0.on_merge(merged, separator);
+    }
+
+    fn on_borrow(&mut self, lender: &[T], borrower: &[T], separator: &T) {
+        self.0.on_borrow(lender, borrower, separator);
+    }
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Register `observer` to be told about every split, merge and borrow,
+    /// replacing the observer registered before
+    ///
+    /// Trees made from this one, like clones and the tree returned by
+    /// `split_off`, do not get the observer.
+    pub fn set_observer<O: TreeObserver<T> + 'static>(&mut self, observer: O) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Unregister the observer and return it
+    pub fn take_observer(&mut self) -> Option<Box<dyn TreeObserver<T>>> {
+        self.observer.take()
+    }
+
+    pub fn has_observer(&self) -> bool {
+        self.observer.is_some()
+    }
+}
+
+/// Like a derived `Debug`, but only shows whether there is an observer
+impl<T, V, S> fmt::Debug for BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BTree")
+            .field("max_keys_per_node", &self.max_keys_per_node)
+            .field("min_keys_per_node", &self.min_keys_per_node)
+            .field("overflow_policy", &self.overflow_policy)
+            .field("insertion_mode", &self.insertion_mode)
+            .field("root", &self.root)
+            .field("has_observer", &self.observer.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    use super::super::BTree;
+    use super::TreeObserver;
+
+    struct Silent;
+
+    impl<T> TreeObserver<T> for Silent {}
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Event {
+        Split(u32),
+        Merge(u32),
+        Borrow(u32),
+        Root(Vec<u32>),
+        Height(usize, usize),
+    }
+
+    /// Keeps the events in a list that the test holds on to as well
+    #[derive(Clone, Default)]
+    struct Recorder(Rc<RefCell<Vec<Event>>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<Event> {
+            core::mem::take(&mut *self.0.borrow_mut())
+        }
+    }
+
+    impl TreeObserver<u32> for Recorder {
+        fn on_split(&mut self, _left: &[u32], _right: &[u32], separator: &u32) {
+            self.0.borrow_mut().push(Event::Split(*separator));
+        }
+
+        fn on_merge(&mut self, _merged: &[u32], separator: &u32) {
+            self.0.borrow_mut().push(Event::Merge(*separator));
+        }
+
+        fn on_borrow(&mut self, _lender: &[u32], _borrower: &[u32], separator: &u32) {
+            self.0.borrow_mut().push(Event::Borrow(*separator));
+        }
+
+        fn on_root_change(&mut self, root: &[u32]) {
+            self.0.borrow_mut().push(Event::Root(root.to_vec()));
+        }
+
+        fn on_height_change(&mut self, old_height: usize, new_height: usize) {
+            self.0
+                .borrow_mut()
+                .push(Event::Height(old_height, new_height));
+        }
+    }
+
+    fn observed_tree() -> BTree<u32, u32> {
+        let mut tree = BTree::new(0, 0, 4);
+        for key in 1..200 {
+            tree.insert(key, key).unwrap();
+        }
+        tree.set_observer(Silent);
+        tree
+    }
+
+    /// A tree with the keys in `keys`, and a recorder that has not seen them go in
+    fn recorded_tree(keys: impl Iterator<Item = u32>) -> (BTree<u32, u32>, Recorder) {
+        let mut tree = BTree::empty(4);
+        for key in keys {
+            tree.insert(key, key).unwrap();
+        }
+        let recorder = Recorder::default();
+        tree.set_observer(recorder.clone());
+        (tree, recorder)
+    }
+
+    /// Check that one bulk operation reported the root and height it left behind,
+    /// and nothing about the parts it built along the way
+    fn assert_reports_final_shape(events: &[Event], old_height: usize, tree: &BTree<u32, u32>) {
+        let roots: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Root(keys) => Some(keys),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(roots, [&tree.root.borrow().keys]);
+        let heights: Vec<_> = events
+            .iter()
+            .filter(|event| matches!(event, Event::Height(..)))
+            .collect();
+        let height = tree.levels().len();
+        if height == old_height {
+            assert!(heights.is_empty());
+        } else {
+            assert_eq!(heights, [&Event::Height(old_height, height)]);
+        }
+    }
+
+    #[test]
+    fn bulk_operations_keep_the_observer() {
+        let mut tree = observed_tree();
+        tree.retain(|key, _| key % 3 != 0);
+        assert!(tree.has_observer());
+        tree.remove_range(..5);
+        assert!(tree.has_observer());
+        tree.remove_range(190..);
+        assert!(tree.has_observer());
+        let _ = tree.extract_if(.., |key, _| key % 5 == 0).count();
+        assert!(tree.has_observer());
+        let _ = tree.drain(..).count();
+        assert!(tree.has_observer());
+
+        let mut tree = observed_tree();
+        let mut high = tree.split_off(&100);
+        assert!(tree.has_observer());
+        assert!(!high.has_observer());
+        tree.append(&mut high);
+        assert!(tree.has_observer());
+        tree.append_sorted((300..320).map(|key| (key, key)).collect::<Vec<_>>())
+            .unwrap();
+        assert!(tree.has_observer());
+        tree.compact(1.0).unwrap();
+        assert!(tree.has_observer());
+        tree.shrink_to_fit();
+        assert!(tree.has_observer());
+    }
+
+    #[test]
+    fn split_off_reports_the_shorter_tree() {
+        let (mut tree, recorder) = recorded_tree(0..200);
+        let height = tree.levels().len();
+        let high = tree.split_off(&3);
+        assert_eq!(high.len(), 197);
+        let events = recorder.take();
+        assert_reports_final_shape(&events, height, &tree);
+        assert_eq!(events.last(), Some(&Event::Height(height, 1)));
+    }
+
+    #[test]
+    fn append_reports_the_split_where_the_shorter_tree_is_hung_in() {
+        let (mut tree, recorder) = recorded_tree(0..10);
+        let mut high = BTree::empty(4);
+        for key in 100..400 {
+            high.insert(key, key).unwrap();
+        }
+        // All nodes below the root are full, so the node that takes the shorter tree splits
+        high.compact(1.0).unwrap();
+        let height = tree.levels().len();
+        tree.append(&mut high);
+        let events = recorder.take();
+        let separators: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Split(separator) => Some(*separator),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(separators.len(), 1);
+        assert!(tree.root.borrow().keys.contains(&separators[0]));
+        assert_reports_final_shape(&events, height, &tree);
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn appending_an_empty_tree_reports_nothing() {
+        let (mut tree, recorder) = recorded_tree(0..50);
+        tree.append(&mut BTree::empty(4));
+        assert!(recorder.take().is_empty());
+    }
+
+    #[test]
+    fn range_removals_report_the_merges_and_the_final_root() {
+        let (mut tree, recorder) = recorded_tree(0..200);
+        let height = tree.levels().len();
+        assert_eq!(tree.remove_range(10..190), 180);
+        let events = recorder.take();
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::Merge(_) | Event::Borrow(_))));
+        assert_reports_final_shape(&events, height, &tree);
+
+        let height = tree.levels().len();
+        assert_eq!(tree.drain(..15).count(), 10);
+        assert_reports_final_shape(&recorder.take(), height, &tree);
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn retain_reports_the_cut_and_the_join() {
+        let (mut tree, recorder) = recorded_tree(0..200);
+        let height = tree.levels().len();
+        tree.retain(|key, _| key % 7 == 0);
+        let events = recorder.take();
+        // The whole tree is the range, so it is empty until the kept entries are joined back
+        assert_eq!(
+            events[..2],
+            [Event::Root(Vec::new()), Event::Height(height, 1)]
+        );
+        assert_reports_final_shape(&events[2..], 1, &tree);
+    }
+
+    #[test]
+    fn compact_reports_only_the_new_root_and_height() {
+        let (mut tree, recorder) = recorded_tree(0..200);
+        for key in (0..200).filter(|key| key % 4 != 0) {
+            tree.remove(key).unwrap();
+        }
+        recorder.take();
+        let height = tree.levels().len();
+        tree.compact(1.0).unwrap();
+        let events = recorder.take();
+        assert!(events
+            .iter()
+            .all(|event| matches!(event, Event::Root(_) | Event::Height(..))));
+        assert_reports_final_shape(&events, height, &tree);
+    }
+}
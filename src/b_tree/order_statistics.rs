@@ -0,0 +1,180 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt::Debug;
+
+use super::{BTree, KeyPosition, Node, Summary};
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Number of entries in the tree
+    ///
+    /// Every node keeps count of the entries below it, so this is read from
+    /// the root.
+    pub fn len(&self) -> usize {
+        self.root.borrow().size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of entries with a key smaller than `key`
+    ///
+    /// This is the position `key` has, or would have, in the sorted order of
+    /// all keys, starting at 0.
+    pub fn rank(&self, key: &T) -> usize {
+        self.partition_point(|current_key| current_key < key)
+    }
+
+    /// Number of entries at the start of the tree for which `predicate` is true
+    ///
+    /// Like `slice::partition_point`, the predicate must be true for all keys
+    /// up to some point in the key order, and false for all keys after it.
+    pub fn partition_point<F: Fn(&T) -> bool>(&self, predicate: F) -> usize {
+        let mut count = 0;
+        let mut current_node = self.root.clone();
+        loop {
+            let child = {
+                let borrowed_node = current_node.borrow();
+                let i = borrowed_node.keys.partition_point(&predicate);
+                // Everything in the children before `i` comes first as well
+                count += i;
+                for child in &borrowed_node.children[..i.min(borrowed_node.children.len())] {
+                    count += child.borrow().size;
+                }
+                match borrowed_node.children.get(i) {
+                    Some(child) => Rc::clone(child),
+                    None => return count,
+                }
+            };
+            current_node = child;
+        }
+    }
+
+    /// The entry at position `index` in key order, starting at 0
+    ///
+    /// Uses the entry counts of the children to skip whole subtrees on the
+    /// way down, so only a single path from the root is visited.
+    pub fn select(&self, index: usize) -> Option<(&T, &V)> {
+        let (cell, i) = self.select_cell(index)?;
+        // SAFETY: see `find_cell`, the node stays untouched while `self` is borrowed
+        let node = unsafe { cell.try_borrow_unguarded() }.ok()?;
+        Some((&node.keys[i], &node.values[i]))
+    }
+
+    /// Find the node holding the entry at position `index`, and the position
+    /// of the entry in that node
+    ///
+    /// Like `find_cell`, walks down the tree without holding on to any `Ref`
+    /// guards.
+    fn select_cell(&self, index: usize) -> Option<KeyPosition<'_, T, V, S>> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut index = index;
+        let mut cell: &RefCell<Node<T, V, S>> = &self.root;
+        loop {
+            // SAFETY: see `find_cell`
+            let node = unsafe { cell.try_borrow_unguarded() }.ok()?;
+            if node.children.is_empty() {
+                return Some((cell, index));
+            }
+            let mut found = None;
+            for (i, child) in node.children.iter().enumerate() {
+                let child_size = child.borrow().size;
+                if index < child_size {
+                    found = Some(child);
+                    break;
+                }
+                index -= child_size;
+                // The key after this child comes next
+                if index == 0 {
+                    return Some((cell, i));
+                }
+                index -= 1;
+            }
+            cell = found?;
+        }
+    }
+
+    /// Remove the entry at position `index` in key order, starting at 0
+    pub fn remove_nth(&mut self, index: usize) -> Option<(T, V)> {
+        let key = self.select(index)?.0.clone();
+        let value = self.remove(key.clone()).ok()?;
+        Some((key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BTree;
+    use crate::test_util::{assert_matches, configs, random_map, tree_from, Rng};
+
+    #[test]
+    fn select_rank_and_remove_nth_match_btreemap() {
+        let mut rng = Rng::new(10);
+        for config in configs() {
+            let mut map = random_map(&mut rng, 300, 1000);
+            let mut tree = tree_from(&config, &map);
+            for (index, (key, value)) in map.iter().enumerate() {
+                assert_eq!(tree.select(index), Some((key, value)));
+                assert_eq!(tree.rank(key), index);
+            }
+            assert_eq!(tree.select(map.len()), None);
+            assert_eq!(tree.rank(&1000), map.len());
+
+            for _ in 0..100 {
+                let index = rng.below(map.len() as u32 + 1) as usize;
+                let expected = map.iter().nth(index).map(|(key, value)| (*key, *value));
+                if let Some((key, _)) = expected {
+                    map.remove(&key);
+                }
+                assert_eq!(tree.remove_nth(index), expected);
+            }
+            assert_matches(&tree, &map);
+        }
+    }
+
+    #[test]
+    fn absent_keys_rank_where_they_would_be_inserted() {
+        let mut tree = BTree::empty(3);
+        for key in [10, 20, 30, 40, 50] {
+            tree.insert(key, ()).unwrap();
+        }
+        assert_eq!(tree.rank(&5), 0);
+        assert_eq!(tree.rank(&10), 0);
+        assert_eq!(tree.rank(&25), 2);
+        assert_eq!(tree.rank(&55), 5);
+        assert_eq!(tree.partition_point(|key| *key <= 30), 3);
+        assert_eq!(tree.partition_point(|_| true), 5);
+        assert_eq!(tree.partition_point(|_| false), 0);
+    }
+
+    #[test]
+    fn out_of_range_indices_find_and_remove_nothing() {
+        let mut empty: BTree<u32, u32> = BTree::empty(3);
+        assert_eq!(empty.select(0), None);
+        assert_eq!(empty.rank(&1), 0);
+        assert_eq!(empty.remove_nth(0), None);
+
+        let mut tree = BTree::empty(3);
+        for key in 0..10 {
+            tree.insert(key, key).unwrap();
+        }
+        assert_eq!(tree.select(10), None);
+        assert_eq!(tree.select(usize::MAX), None);
+        assert_eq!(tree.remove_nth(10), None);
+        assert_eq!(tree.len(), 10);
+        // Removing the first and last positions over and over empties the tree
+        for step in 0..10 {
+            let index = if step % 2 == 0 { 0 } else { tree.len() - 1 };
+            assert!(tree.remove_nth(index).is_some());
+            tree.validate().unwrap();
+        }
+        assert!(tree.is_empty());
+    }
+}
@@ -0,0 +1,582 @@
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+
+use super::{BTree, Node, Summary, TreeObserver};
+
+/// A tree together with its height, so joining trees does not have to measure them
+///
+/// A tree with only a root has height 0.
+struct Part<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    tree: BTree<T, V, S>,
+    height: usize,
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Move all entries with a key larger than or equal to `key` into a new tree
+    ///
+    /// Walks down the path to `key` once. Every node on the path is cut in a
+    /// left and a right part, and the parts on each side are joined back
+    /// together on the way up. Each join only works on the nodes along one edge
+    /// of the taller tree, so this takes O(log n) node operations in total.
+    pub fn split_off(&mut self, key: &T) -> Self {
+        self.observe_bulk(|tree, observer| tree.split_off_observed(key, observer))
+    }
+
+    /// `split_off`, reporting the joins on this side of the cut to `observer`
+    pub(super) fn split_off_observed<O: TreeObserver<T> + ?Sized>(
+        &mut self,
+        key: &T,
+        observer: &mut O,
+    ) -> Self {
+        let height = self.height();
+        let root = core::mem::replace(&mut self.root, Rc::new(RefCell::new(Node::new_empty())));
+        let (left, right) = self.split_part(root, height, key, observer);
+        self.root = left.tree.root;
+        right.tree
+    }
+
+    /// Move all entries of `other` into this tree, leaving `other` empty
+    ///
+    /// When all keys of one tree are smaller than all keys of the other, the
+    /// shorter tree is hung into the edge of the taller tree, which takes
+    /// O(log n) node operations. Otherwise, or if the nodes of `other` can
+    /// hold more keys or fewer keys than this tree allows, the entries of both
+    /// trees are merged and the tree is built up again. That includes an
+    /// `other` with top-down insertion, which can leave nodes below the minimum
+    /// of a tree with bottom-up insertion. A key in both trees gets the value
+    /// from `other`.
+    pub fn append(&mut self, other: &mut Self) {
+        self.observe_bulk(|tree, observer| tree.append_observed(other, observer));
+    }
+
+    /// `append`, reporting the joins to `observer`
+    pub(super) fn append_observed<O: TreeObserver<T> + ?Sized>(
+        &mut self,
+        other: &mut Self,
+        observer: &mut O,
+    ) {
+        if other.is_empty() {
+            return;
+        }
+        let empty = Rc::new(RefCell::new(Node::new_empty()));
+        let disjoint = match (self.last_key(), other.first_key()) {
+            (None, _) => true,
+            (Some(last), Some(first)) if last < first => true,
+            _ => match (other.last_key(), self.first_key()) {
+                (Some(last), Some(first)) => last < first,
+                _ => false,
+            },
+        };
+        let nodes_fit = self.max_keys_per_node == other.max_keys_per_node
+            && other.min_keys_allowed() >= self.min_keys_allowed();
+        if !disjoint || !nodes_fit {
+            // Both trees are taken apart, so the entries are moved instead of cloned
+            let entries = merge_sorted(
+                self.take_part().tree.into_entries().into_iter(),
+                other.take_part().tree.into_entries().into_iter(),
+            );
+            self.root = self.build_from_sorted(entries, self.max_keys_per_node);
+            return;
+        }
+        if self.is_empty() {
+            self.root = core::mem::replace(&mut other.root, empty);
+            return;
+        }
+        let (low, mut high) = if self.first_key() < other.first_key() {
+            (self.take_part(), other.take_part())
+        } else {
+            (other.take_part(), self.take_part())
+        };
+        // The smallest entry of the upper tree separates the two trees
+        let separator = high.tree.pop_observed(true, observer).unwrap();
+        high.height = high.tree.height();
+        let joined = self.join(low, separator, high, observer);
+        self.root = joined.tree.root;
+        other.root = empty;
+    }
+
+    /// Move `entries`, which must be sorted by key without duplicates, into this tree
+    ///
+    /// The entries are packed into nodes bottom-up in O(n) instead of being
+    /// inserted one by one, and then added like `append`, so a key that is
+    /// already in the tree gets the value from `entries`. Returns an Err and
+    /// leaves the tree unchanged if the keys are not strictly increasing.
+    pub fn append_sorted(&mut self, entries: Vec<(T, V)>) -> Result<(), &'static str> {
+        let increasing =
+            |pair: &[(T, V)]| pair[0].0.partial_cmp(&pair[1].0) == Some(Ordering::Less);
+        if !entries.windows(2).all(increasing) {
+            return Err("Keys are not sorted");
+        }
+        let root = self.build_from_sorted(entries, self.max_keys_per_node);
+        let mut other = self.with_root(root);
+        self.append(&mut other);
+        Ok(())
+    }
+
+    /// The smallest key, borrowed instead of cloned like `first_key_value` does
+    fn first_key(&self) -> Option<&T> {
+        self.select(0).map(|(key, _)| key)
+    }
+
+    /// The largest key, borrowed instead of cloned like `last_key_value` does
+    fn last_key(&self) -> Option<&T> {
+        let index = self.len().checked_sub(1)?;
+        self.select(index).map(|(key, _)| key)
+    }
+
+    /// Number of levels below the root
+    fn height(&self) -> usize {
+        let mut height = 0;
+        let mut current_node = self.root.clone();
+        loop {
+            let child = current_node.borrow().children.first().cloned();
+            match child {
+                Some(child) => current_node = child,
+                None => return height,
+            }
+            height += 1;
+        }
+    }
+
+    /// Move the whole tree out into a `Part`, leaving this tree empty
+    fn take_part(&mut self) -> Part<T, V, S> {
+        let height = self.height();
+        let root = core::mem::replace(&mut self.root, Rc::new(RefCell::new(Node::new_empty())));
+        self.part_with_root(root, height)
+    }
+
+    /// A `Part` with the same settings as this tree, with `root` as its root
+    fn part_with_root(&self, root: Rc<RefCell<Node<T, V, S>>>, height: usize) -> Part<T, V, S> {
+        Part {
+            tree: self.with_root(root),
+            height,
+        }
+    }
+
+    /// A tree with a new root at `height` that holds the given entries and children
+    ///
+    /// A root without keys but with a single child is replaced by that child.
+    fn part_from_entries(
+        &self,
+        keys: Vec<T>,
+        values: Vec<V>,
+        children: Vec<Rc<RefCell<Node<T, V, S>>>>,
+        height: usize,
+    ) -> Part<T, V, S> {
+        if keys.is_empty() && children.len() == 1 {
+            let child = children.into_iter().next().unwrap();
+            return self.part_with_root(child, height - 1);
+        }
+        let height = if children.is_empty() { 0 } else { height };
+        let root = Rc::new(RefCell::new(Node {
+            keys,
+            values,
+            children,
+            parent: None,
+            size: 0,
+            summary: S::identity(),
+        }));
+        BTree::adopt_children(&root);
+        BTree::update_summary(&root);
+        self.part_with_root(root, height)
+    }
+
+    /// Cut the subtree below `node` into the entries before and from `key`
+    ///
+    /// The node is taken apart into the keys and children to the left of the
+    /// path to `key`, the child on the path, and the keys and children to the
+    /// right of it. The child is cut recursively, after which each side is
+    /// joined with its half of the child, using the key next to the child as
+    /// the separator. Only the joins on the left side are reported to
+    /// `observer`, the right side becomes another tree.
+    fn split_part<O: TreeObserver<T> + ?Sized>(
+        &self,
+        node: Rc<RefCell<Node<T, V, S>>>,
+        height: usize,
+        key: &T,
+        observer: &mut O,
+    ) -> (Part<T, V, S>, Part<T, V, S>) {
+        let (mut keys, mut values, mut children) = {
+            let mut borrowed_node = node.borrow_mut();
+            (
+                core::mem::take(&mut borrowed_node.keys),
+                core::mem::take(&mut borrowed_node.values),
+                core::mem::take(&mut borrowed_node.children),
+            )
+        };
+        let i = keys.partition_point(|current_key| current_key < key);
+        let found = i < keys.len() && keys[i] == *key;
+        let mut right_keys = keys.split_off(i);
+        let mut right_values = values.split_off(i);
+        if children.is_empty() {
+            return (
+                self.part_from_entries(keys, values, Vec::new(), 0),
+                self.part_from_entries(right_keys, right_values, Vec::new(), 0),
+            );
+        }
+        let right_children = children.split_off(i + 1);
+        let child = children.pop().unwrap();
+        // All keys in the child before an exact match are smaller than `key`
+        let (child_left, child_right) = if found {
+            let empty = Rc::new(RefCell::new(Node::new_empty()));
+            (
+                self.part_with_root(child, height - 1),
+                self.part_with_root(empty, 0),
+            )
+        } else {
+            self.split_part(child, height - 1, key, observer)
+        };
+        let left = match (keys.pop(), values.pop()) {
+            (Some(separator_key), Some(separator_value)) => {
+                let rest = self.part_from_entries(keys, values, children, height);
+                self.join(rest, (separator_key, separator_value), child_left, observer)
+            }
+            _ => child_left,
+        };
+        let right = if right_keys.is_empty() {
+            child_right
+        } else {
+            let separator = (right_keys.remove(0), right_values.remove(0));
+            let rest = self.part_from_entries(right_keys, right_values, right_children, height);
+            self.join(child_right, separator, rest, &mut ())
+        };
+        (left, right)
+    }
+
+    /// Join two trees, with all keys of `left` smaller than `separator`, and
+    /// all keys of `right` larger
+    ///
+    /// The root of the shorter tree becomes a child of the node at the same
+    /// height along the right edge of `left` or the left edge of `right`, with
+    /// the separator in front of it. That node may then overflow, which is
+    /// fixed like after an insertion, and the old root may have too few keys
+    /// to be a child, which is fixed like after a removal.
+    fn join<O: TreeObserver<T> + ?Sized>(
+        &self,
+        left: Part<T, V, S>,
+        separator: (T, V),
+        right: Part<T, V, S>,
+        observer: &mut O,
+    ) -> Part<T, V, S> {
+        let (key, value) = separator;
+        if left.tree.is_empty() || right.tree.is_empty() {
+            let mut part = if left.tree.is_empty() { right } else { left };
+            let old_root = Rc::clone(&part.tree.root);
+            let _ = part.tree.insert_observed(key, value, observer);
+            if !Rc::ptr_eq(&old_root, &part.tree.root) {
+                part.height += 1;
+            }
+            return part;
+        }
+        let min_keys_per_node = self.min_keys_per_node();
+        if left.height == right.height {
+            let root = Rc::new(RefCell::new(Node {
+                keys: vec![key],
+                values: vec![value],
+                children: vec![left.tree.root, right.tree.root],
+                parent: None,
+                size: 0,
+                summary: S::identity(),
+            }));
+            BTree::adopt_children(&root);
+            BTree::fill_child(&root, 0, min_keys_per_node, observer);
+            BTree::fill_child(&root, 1, min_keys_per_node, observer);
+            BTree::update_summary(&root);
+            let mut part = self.part_with_root(root, left.height + 1);
+            if part.tree.root.borrow().keys.is_empty() {
+                part.tree.shrink_root(observer);
+                part.height -= 1;
+            }
+            return part;
+        }
+
+        let (mut taller, shorter, left_is_taller) = if left.height > right.height {
+            (left, right, true)
+        } else {
+            (right, left, false)
+        };
+        // Walk down the edge to the node whose children are as tall as the shorter tree
+        let mut node = Rc::clone(&taller.tree.root);
+        for _ in 0..taller.height - shorter.height - 1 {
+            let child = {
+                let borrowed_node = node.borrow();
+                let edge_child = if left_is_taller {
+                    borrowed_node.children.last()
+                } else {
+                    borrowed_node.children.first()
+                };
+                Rc::clone(edge_child.unwrap())
+            };
+            node = child;
+        }
+        let i = {
+            let mut borrowed_node = node.borrow_mut();
+            shorter.tree.root.borrow_mut().parent = Some(Rc::downgrade(&node));
+            if left_is_taller {
+                borrowed_node.keys.push(key);
+                borrowed_node.values.push(value);
+                borrowed_node.children.push(shorter.tree.root);
+                borrowed_node.children.len() - 1
+            } else {
+                borrowed_node.keys.insert(0, key);
+                borrowed_node.values.insert(0, value);
+                borrowed_node.children.insert(0, shorter.tree.root);
+                0
+            }
+        };
+        BTree::fill_child(&node, i, min_keys_per_node, observer);
+        let new_root = if node.borrow().keys.len() > self.max_keys_per_node {
+            BTree::handle_overflow(
+                Rc::clone(&node),
+                self.max_keys_per_node,
+                self.overflow_policy,
+                observer,
+            )
+        } else {
+            None
+        };
+        // Everything above the node now holds more entries
+        let mut current_node = Some(node);
+        while let Some(node) = current_node {
+            BTree::update_summary(&node);
+            current_node = node
+                .borrow()
+                .parent
+                .as_ref()
+                .and_then(|parent| parent.upgrade());
+        }
+        if let Some(root) = new_root {
+            taller.tree.root = root;
+            taller.height += 1;
+        }
+        taller
+    }
+
+    /// Fix child `i` of `parent` until it has the minimum number of keys
+    ///
+    /// Unlike after a removal, the child can be short by more than one key, so
+    /// keys are borrowed from a sibling until it has enough. Stops when the
+    /// child was merged with a sibling.
+    fn fill_child<O: TreeObserver<T> + ?Sized>(
+        parent: &Rc<RefCell<Node<T, V, S>>>,
+        i: usize,
+        min_keys_per_node: usize,
+        observer: &mut O,
+    ) {
+        loop {
+            let short = match parent.borrow().children.get(i) {
+                Some(child) => child.borrow().keys.len() < min_keys_per_node,
+                None => false,
+            };
+            if !short || parent.borrow().children.len() < 2 {
+                return;
+            }
+            BTree::rebalance_child(parent, i, min_keys_per_node, observer);
+        }
+    }
+}
+
+/// All entries of two sorted iterators in order, with the entry from `right`
+/// for keys that are in both
+fn merge_sorted<T, V, I, J>(left: I, right: J) -> Vec<(T, V)>
+where
+    T: PartialOrd,
+    I: Iterator<Item = (T, V)>,
+    J: Iterator<Item = (T, V)>,
+{
+    let mut left = left.peekable();
+    let mut right = right.peekable();
+    let mut entries = Vec::new();
+    loop {
+        let (take_left, take_right) = match (left.peek(), right.peek()) {
+            (None, None) => return entries,
+            (Some(_), None) => (true, false),
+            (None, Some(_)) => (false, true),
+            (Some((left_key, _)), Some((right_key, _))) if left_key < right_key => (true, false),
+            (Some((left_key, _)), Some((right_key, _))) if left_key > right_key => (false, true),
+            (Some(_), Some(_)) => (true, true),
+        };
+        let entry = if take_left { left.next() } else { None };
+        let entry = if take_right { right.next() } else { entry };
+        entries.extend(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
+
+    use crate::test_util::{assert_matches, configs, random_map, tree_from, Rng};
+    use crate::{BTreeConfig, InsertionMode};
+
+    #[test]
+    fn append_rebuilds_nodes_that_are_too_small_for_this_tree() {
+        // Top-down insertion leaves nodes with (order - 1) / 2 keys, one less
+        // than the minimum of a tree with bottom-up insertion and order 5
+        let bottom_up = BTreeConfig::new().order(5);
+        let top_down = bottom_up.insertion_mode(InsertionMode::TopDown);
+        let low: BTreeMap<_, _> = (0..100).map(|key| (key, key)).collect();
+        let high: BTreeMap<_, _> = (1000..1100).map(|key| (key, key)).collect();
+        let mut expected = low.clone();
+        expected.extend(high.clone());
+        for (this, other) in [(bottom_up, top_down), (top_down, bottom_up)] {
+            let mut tree = tree_from(&this, &low);
+            let mut appended = tree_from(&other, &high);
+            tree.append(&mut appended);
+            assert_matches(&tree, &expected);
+            assert!(appended.is_empty());
+
+            let mut tree = tree_from(&this, &high);
+            let mut appended = tree_from(&other, &low);
+            tree.append(&mut appended);
+            assert_matches(&tree, &expected);
+
+            // An empty tree takes the nodes of the other tree as they are
+            let mut tree = tree_from(&this, &BTreeMap::new());
+            let mut appended = tree_from(&other, &low);
+            tree.append(&mut appended);
+            assert_matches(&tree, &low);
+        }
+    }
+
+    #[test]
+    fn split_off_matches_btreemap() {
+        let mut rng = Rng::new(2);
+        for config in configs() {
+            for _ in 0..10 {
+                let len = rng.below(400) as usize;
+                let mut map = random_map(&mut rng, len, 1000);
+                let mut tree = tree_from(&config, &map);
+                let key = rng.below(1100);
+                let mut right = tree.split_off(&key);
+                let mut right_map = map.split_off(&key);
+                assert_matches(&tree, &map);
+                assert_matches(&right, &right_map);
+
+                tree.append(&mut right);
+                map.append(&mut right_map);
+                assert_matches(&tree, &map);
+                assert!(right.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn append_joins_trees_of_different_heights() {
+        // Disjoint trees are joined by hanging the shorter one into the edge
+        // of the taller one, which has to fill up the nodes along that edge
+        let mut rng = Rng::new(3);
+        for config in configs() {
+            for (left_len, right_len) in [(1, 300), (300, 1), (5, 60), (60, 5), (200, 200)] {
+                let left_map: BTreeMap<_, _> = (0..left_len).map(|key| (key, key)).collect();
+                let right_map: BTreeMap<_, _> =
+                    (1000..1000 + right_len).map(|key| (key, key)).collect();
+                let mut expected = left_map.clone();
+                expected.extend(right_map.clone());
+
+                let mut left = tree_from(&config, &left_map);
+                let mut right = tree_from(&config, &right_map);
+                left.append(&mut right);
+                assert_matches(&left, &expected);
+
+                // And the other way around, with the larger keys in this tree
+                let mut left = tree_from(&config, &left_map);
+                let mut right = tree_from(&config, &right_map);
+                right.append(&mut left);
+                assert_matches(&right, &expected);
+            }
+
+            // Overlapping trees are merged, the value from the appended tree wins
+            let mut map = random_map(&mut rng, 150, 400);
+            let other_map: BTreeMap<_, _> = random_map(&mut rng, 150, 400)
+                .into_keys()
+                .map(|key| (key, key + 1))
+                .collect();
+            let mut tree = tree_from(&config, &map);
+            let mut other = tree_from(&config, &other_map);
+            tree.append(&mut other);
+            map.extend(other_map);
+            assert_matches(&tree, &map);
+        }
+    }
+
+    #[test]
+    fn append_sorted_matches_btreemap() {
+        let mut rng = Rng::new(4);
+        for config in configs() {
+            let mut map = random_map(&mut rng, 100, 500);
+            let mut tree = tree_from(&config, &map);
+            let entries: Vec<_> = random_map(&mut rng, 200, 1000).into_iter().collect();
+            tree.append_sorted(entries.clone()).unwrap();
+            map.extend(entries);
+            assert_matches(&tree, &map);
+
+            assert!(tree.append_sorted(Vec::from([(2, 0), (1, 0)])).is_err());
+            assert_matches(&tree, &map);
+        }
+    }
+
+    #[test]
+    fn split_off_at_the_edges_of_the_tree() {
+        for config in configs() {
+            let map: BTreeMap<_, _> = (10..60).map(|key| (key, key * 2)).collect();
+            // Below the smallest key, at it, at a key in the middle, above the largest
+            for key in [0, 10, 35, 59, 60, 100] {
+                let mut tree = tree_from(&config, &map);
+                let mut left_map = map.clone();
+                let right_map = left_map.split_off(&key);
+                let right = tree.split_off(&key);
+                assert_matches(&tree, &left_map);
+                assert_matches(&right, &right_map);
+                // The tree that was split off keeps the settings of the original
+                assert_eq!(right.max_keys_per_node(), tree.max_keys_per_node());
+                assert_eq!(right.min_keys_per_node(), tree.min_keys_per_node());
+            }
+
+            let mut empty = tree_from(&config, &BTreeMap::new());
+            let right = empty.split_off(&5);
+            assert!(empty.is_empty());
+            assert!(right.is_empty());
+        }
+    }
+
+    #[test]
+    fn append_with_empty_trees() {
+        for config in configs() {
+            let map: BTreeMap<_, _> = (0..40).map(|key| (key, key)).collect();
+            let mut tree = tree_from(&config, &map);
+            let mut empty = tree_from(&config, &BTreeMap::new());
+            tree.append(&mut empty);
+            assert_matches(&tree, &map);
+            empty.append(&mut tree);
+            assert_matches(&empty, &map);
+            assert!(tree.is_empty());
+
+            tree.append_sorted(Vec::new()).unwrap();
+            assert!(tree.is_empty());
+            tree.append_sorted(Vec::from([(1, 1)])).unwrap();
+            assert_eq!(tree.len(), 1);
+            // Duplicate keys are not strictly increasing
+            assert_eq!(
+                tree.append_sorted(Vec::from([(2, 0), (2, 0)])),
+                Err("Keys are not sorted")
+            );
+            assert_eq!(tree.len(), 1);
+        }
+    }
+}
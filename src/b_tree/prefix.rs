@@ -0,0 +1,235 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::ops::Bound;
+
+use super::{BTree, Range, Summary};
+
+/// A key that is made up of bytes, for prefix scans
+///
+/// Keys must be ordered the same way as their bytes, which holds for strings
+/// and byte vectors. The keys starting with a prefix then form a single run in
+/// the tree, which ends before the successor of the prefix.
+pub trait Prefixed {
+    /// The bytes of the key, in the order they are compared
+    fn key_bytes(&self) -> &[u8];
+}
+
+impl Prefixed for str {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Prefixed for String {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Prefixed for [u8] {
+    fn key_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Prefixed for Vec<u8> {
+    fn key_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<P: Prefixed + ?Sized> Prefixed for &P {
+    fn key_bytes(&self) -> &[u8] {
+        (**self).key_bytes()
+    }
+}
+
+/// The smallest byte string after all byte strings that start with `prefix`
+///
+/// Trailing 0xFF bytes can not be increased, so they are dropped and the byte
+/// before them is increased instead. Returns None if there is no such byte
+/// string, when the prefix is empty or only has 0xFF bytes.
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let end = prefix.iter().rposition(|byte| *byte != u8::MAX)?;
+    let mut successor = prefix[..=end].to_vec();
+    successor[end] += 1;
+    Some(successor)
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug + Prefixed,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Iterate over the entries whose key starts with `prefix`, in key order
+    pub fn prefix<P: Prefixed + ?Sized>(&self, prefix: &P) -> Range<'_, T, V, S> {
+        self.range(self.prefix_bounds(prefix.key_bytes()))
+    }
+
+    /// Number of entries whose key starts with `prefix`
+    ///
+    /// Counted from the entry counts in the nodes, without visiting the entries.
+    pub fn count_prefix<P: Prefixed + ?Sized>(&self, prefix: &P) -> usize {
+        let (start, end) = self.prefix_positions(prefix.key_bytes());
+        end - start
+    }
+
+    /// Remove all entries whose key starts with `prefix`, returns how many were removed
+    pub fn remove_prefix<P: Prefixed + ?Sized>(&mut self, prefix: &P) -> usize {
+        if self.count_prefix(prefix) == 0 {
+            return 0;
+        }
+        self.remove_range(self.prefix_bounds(prefix.key_bytes()))
+    }
+
+    /// Positions of the first entry starting with `prefix`, and of the first
+    /// entry after them
+    fn prefix_positions(&self, prefix: &[u8]) -> (usize, usize) {
+        let start = self.partition_point(|key| key.key_bytes() < prefix);
+        let end = match prefix_successor(prefix) {
+            Some(successor) => self.partition_point(|key| key.key_bytes() < &successor[..]),
+            None => self.len(),
+        };
+        (start, end)
+    }
+
+    /// Bounds that contain exactly the keys starting with `prefix`
+    ///
+    /// The bounds are the keys just before and just after the matching keys,
+    /// so they can be used with the other range methods of the tree.
+    fn prefix_bounds(&self, prefix: &[u8]) -> (Bound<T>, Bound<T>) {
+        let (start, end) = self.prefix_positions(prefix);
+        let start_bound = match start.checked_sub(1).and_then(|before| self.select(before)) {
+            Some((key, _)) => Bound::Excluded(key.clone()),
+            None => Bound::Unbounded,
+        };
+        let end_bound = match self.select(end) {
+            Some((key, _)) => Bound::Excluded(key.clone()),
+            None => Bound::Unbounded,
+        };
+        (start_bound, end_bound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use super::prefix_successor;
+    use crate::BTree;
+
+    #[test]
+    fn successor_drops_trailing_0xff_bytes() {
+        assert_eq!(prefix_successor(b"ab"), Some(Vec::from(*b"ac")));
+        assert_eq!(prefix_successor(b"a\xff\xff"), Some(Vec::from(*b"b")));
+        assert_eq!(prefix_successor(b"\xfe\xff"), Some(Vec::from(*b"\xff")));
+        assert_eq!(prefix_successor(b"\xff\xff"), None);
+        assert_eq!(prefix_successor(b""), None);
+    }
+
+    #[test]
+    fn byte_keys_with_0xff_at_the_end() {
+        let mut tree = BTree::empty(3);
+        let keys: [&[u8]; 8] = [
+            b"\x00",
+            b"a",
+            b"a\xff",
+            b"a\xff\x00",
+            b"a\xff\xff",
+            b"b",
+            b"\xff",
+            b"\xff\xff",
+        ];
+        for key in keys {
+            tree.insert(Vec::from(key), ()).unwrap();
+        }
+        let prefixed =
+            |prefix: &[u8]| -> Vec<Vec<u8>> { tree.prefix(prefix).map(|(key, _)| key).collect() };
+        assert_eq!(prefixed(b"a\xff"), keys[2..5]);
+        assert_eq!(prefixed(b"a\xff\xff"), keys[4..5]);
+        assert_eq!(prefixed(b"\xff"), keys[6..]);
+        assert_eq!(prefixed(b"\xff\xff\xff"), Vec::<Vec<u8>>::new());
+        assert_eq!(tree.count_prefix(&b"a"[..]), 4);
+        assert_eq!(tree.count_prefix(&b"\xff"[..]), 2);
+    }
+
+    #[test]
+    fn the_empty_prefix_matches_every_key() {
+        let mut tree = BTree::empty(3);
+        for key in ["", "a", "b", "ba"] {
+            tree.insert(String::from(key), ()).unwrap();
+        }
+        assert_eq!(tree.prefix("").count(), 4);
+        assert_eq!(tree.count_prefix(""), 4);
+        assert_eq!(tree.remove_prefix(""), 4);
+        assert!(tree.is_empty());
+        assert_eq!(tree.count_prefix(""), 0);
+        assert_eq!(tree.remove_prefix(""), 0);
+    }
+
+    #[test]
+    fn string_and_str_keys() {
+        let words = [
+            "car",
+            "card",
+            "care",
+            "cart",
+            "cat",
+            "do",
+            "dog",
+            "z",
+            "z\u{10FFFF}",
+            "\u{10FFFF}",
+        ];
+        let mut strings = BTree::empty(3);
+        let mut strs = BTree::empty(3);
+        for word in words {
+            strings.insert(String::from(word), word.len()).unwrap();
+            strs.insert(word, word.len()).unwrap();
+        }
+        for prefix in [
+            "",
+            "c",
+            "car",
+            "card",
+            "ca",
+            "cb",
+            "d",
+            "x",
+            "z",
+            "\u{10FFFF}",
+        ] {
+            let expected: Vec<_> = words
+                .into_iter()
+                .filter(|word| word.starts_with(prefix))
+                .collect();
+            let from_strings: Vec<_> = strings.prefix(prefix).map(|(key, _)| key).collect();
+            let from_strs: Vec<_> = strs.prefix(prefix).map(|(key, _)| key).collect();
+            assert_eq!(from_strings, expected);
+            assert_eq!(from_strs, expected);
+            assert_eq!(strings.count_prefix(prefix), strings.prefix(prefix).count());
+            assert_eq!(strs.count_prefix(prefix), expected.len());
+        }
+    }
+
+    #[test]
+    fn remove_prefix_only_removes_matching_keys() {
+        let mut tree = BTree::empty(2);
+        for i in 0..300u32 {
+            let key = format!("{}/{i:03}", ["a", "ab", "b"][i as usize % 3]);
+            tree.insert(key, i).unwrap();
+        }
+        // "a/" does not match "ab/..."
+        assert_eq!(tree.remove_prefix("a/"), 100);
+        assert_eq!(tree.count_prefix("a"), 100);
+        assert_eq!(tree.remove_prefix("c"), 0);
+        assert_eq!(tree.remove_prefix("ab/00"), 3);
+        assert_eq!(tree.len(), 197);
+        tree.validate().unwrap();
+        assert!(tree.prefix("ab/").all(|(key, _)| !key.starts_with("ab/00")));
+    }
+}
@@ -0,0 +1,303 @@
+use core::fmt::Debug;
+use core::iter::Peekable;
+use core::ops::RangeBounds;
+
+use super::{BTree, Range};
+
+/// BTreeSet
+///
+/// An ordered set of keys, backed by a `BTree` with `()` as the value. Unlike
+/// `BTree::new`, it does not need a first key to be created.
+#[derive(Debug)]
+pub struct BTreeSet<T>
+where
+    T: PartialOrd + Clone + Debug,
+{
+    tree: BTree<T, ()>,
+    len: usize,
+}
+
+/// Iterator over the keys of a `BTreeSet` in order
+///
+/// Created by `BTreeSet::iter` and `BTreeSet::range`.
+pub struct SetRange<'a, T>
+where
+    T: PartialOrd + Clone + Debug,
+{
+    entries: Range<'a, T, ()>,
+}
+
+/// Which keys of the two sets a `SetOperation` yields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Union,
+    Intersection,
+    Difference,
+    SymmetricDifference,
+}
+
+/// Iterator over the result of combining two `BTreeSet`s, in order
+///
+/// Created by `BTreeSet::union`, `BTreeSet::intersection`,
+/// `BTreeSet::difference` and `BTreeSet::symmetric_difference`. Walks both sets
+/// side by side like the merge step of a merge sort, so every key of both sets
+/// is visited once and no lookups are needed.
+pub struct SetOperation<'a, T>
+where
+    T: PartialOrd + Clone + Debug,
+{
+    left: Peekable<SetRange<'a, T>>,
+    right: Peekable<SetRange<'a, T>>,
+    operation: Operation,
+}
+
+impl<T> BTreeSet<T>
+where
+    T: PartialOrd + Clone + Debug,
+{
+    pub fn new(max_keys_per_node: usize) -> Self {
+        BTreeSet {
+            tree: BTree::empty(max_keys_per_node),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add a key to the set, returns false if it was already present
+    pub fn insert(&mut self, key: T) -> bool {
+        let inserted = self.tree.insert(key, ()).is_ok();
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    pub fn contains(&self, key: &T) -> bool {
+        self.tree.exists(key.clone())
+    }
+
+    /// Remove a key from the set, returns false if it was not present
+    pub fn remove(&mut self, key: &T) -> bool {
+        let removed = self.tree.remove(key.clone()).is_ok();
+        if removed {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Iterate over all keys in order
+    pub fn iter(&self) -> SetRange<'_, T> {
+        self.range(..)
+    }
+
+    /// Iterate over the keys inside `range`, in order
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> SetRange<'_, T> {
+        SetRange {
+            entries: self.tree.range(range),
+        }
+    }
+
+    /// Keys that are in `self`, in `other`, or in both
+    pub fn union<'a>(&'a self, other: &'a BTreeSet<T>) -> SetOperation<'a, T> {
+        self.combine(other, Operation::Union)
+    }
+
+    /// Keys that are both in `self` and in `other`
+    pub fn intersection<'a>(&'a self, other: &'a BTreeSet<T>) -> SetOperation<'a, T> {
+        self.combine(other, Operation::Intersection)
+    }
+
+    /// Keys that are in `self` but not in `other`
+    pub fn difference<'a>(&'a self, other: &'a BTreeSet<T>) -> SetOperation<'a, T> {
+        self.combine(other, Operation::Difference)
+    }
+
+    /// Keys that are in either `self` or `other`, but not in both
+    pub fn symmetric_difference<'a>(&'a self, other: &'a BTreeSet<T>) -> SetOperation<'a, T> {
+        self.combine(other, Operation::SymmetricDifference)
+    }
+
+    /// Whether every key of `self` is also in `other`
+    pub fn is_subset(&self, other: &BTreeSet<T>) -> bool {
+        self.len <= other.len && self.difference(other).next().is_none()
+    }
+
+    fn combine<'a>(&'a self, other: &'a BTreeSet<T>, operation: Operation) -> SetOperation<'a, T> {
+        SetOperation {
+            left: self.iter().peekable(),
+            right: other.iter().peekable(),
+            operation,
+        }
+    }
+}
+
+impl<T> Iterator for SetRange<'_, T>
+where
+    T: PartialOrd + Clone + Debug,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|(key, _)| key)
+    }
+}
+
+impl<T> Iterator for SetOperation<'_, T>
+where
+    T: PartialOrd + Clone + Debug,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use Operation::*;
+        loop {
+            let (left, right) = match (self.left.peek(), self.right.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => (true, false),
+                (None, Some(_)) => (false, true),
+                (Some(left_key), Some(right_key)) if left_key < right_key => (true, false),
+                (Some(left_key), Some(right_key)) if left_key > right_key => (false, true),
+                (Some(_), Some(_)) => (true, true),
+            };
+            let keep = match (left, right) {
+                (true, true) => matches!(self.operation, Union | Intersection),
+                (true, false) => matches!(self.operation, Union | Difference | SymmetricDifference),
+                _ => matches!(self.operation, Union | SymmetricDifference),
+            };
+            // Once one side has run out, the rest of the other side is only
+            // kept by some of the operations
+            if !keep && (self.left.peek().is_none() || self.right.peek().is_none()) {
+                return None;
+            }
+            let key = if left { self.left.next() } else { None };
+            let key = if right { self.right.next() } else { key };
+            if keep {
+                return key;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeSet as StdSet;
+    use alloc::vec::Vec;
+
+    use super::BTreeSet;
+    use crate::test_util::Rng;
+
+    fn random_sets(rng: &mut Rng, max_keys_per_node: usize) -> (BTreeSet<u32>, StdSet<u32>) {
+        let mut set = BTreeSet::new(max_keys_per_node);
+        let mut expected = StdSet::new();
+        for _ in 0..200 {
+            let key = rng.below(300);
+            assert_eq!(set.insert(key), expected.insert(key));
+        }
+        for _ in 0..50 {
+            let key = rng.below(300);
+            assert_eq!(set.remove(&key), expected.remove(&key));
+        }
+        (set, expected)
+    }
+
+    #[test]
+    fn set_operations_match_std() {
+        let mut rng = Rng::new(12);
+        for max_keys_per_node in 2..=8 {
+            let (a, std_a) = random_sets(&mut rng, max_keys_per_node);
+            let (b, std_b) = random_sets(&mut rng, max_keys_per_node);
+            assert_eq!(a.len(), std_a.len());
+            assert_eq!(
+                a.iter().collect::<Vec<_>>(),
+                std_a.iter().copied().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                a.range(50..150).collect::<Vec<_>>(),
+                std_a.range(50..150).copied().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                a.union(&b).collect::<Vec<_>>(),
+                std_a.union(&std_b).copied().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                a.intersection(&b).collect::<Vec<_>>(),
+                std_a.intersection(&std_b).copied().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                a.difference(&b).collect::<Vec<_>>(),
+                std_a.difference(&std_b).copied().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                a.symmetric_difference(&b).collect::<Vec<_>>(),
+                std_a
+                    .symmetric_difference(&std_b)
+                    .copied()
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(a.is_subset(&b), std_a.is_subset(&std_b));
+            assert!(a.is_subset(&a));
+        }
+    }
+
+    #[test]
+    fn insert_and_remove_report_whether_the_set_changed() {
+        let mut set = BTreeSet::new(3);
+        assert!(set.insert(5));
+        assert!(!set.insert(5));
+        assert!(set.contains(&5));
+        assert_eq!(set.len(), 1);
+        assert!(set.remove(&5));
+        assert!(!set.remove(&5));
+        assert!(!set.contains(&5));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn operations_with_an_empty_set() {
+        let empty = BTreeSet::new(3);
+        let mut set = BTreeSet::new(3);
+        for key in [1, 2, 3] {
+            set.insert(key);
+        }
+        assert_eq!(set.union(&empty).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(empty.union(&set).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(set.intersection(&empty).next(), None);
+        assert_eq!(set.difference(&empty).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(empty.difference(&set).next(), None);
+        assert_eq!(
+            empty.symmetric_difference(&set).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert!(empty.is_subset(&set));
+        assert!(!set.is_subset(&empty));
+        assert!(empty.is_subset(&empty));
+    }
+
+    #[test]
+    fn disjoint_and_interleaved_sets() {
+        let mut low = BTreeSet::new(2);
+        let mut high = BTreeSet::new(2);
+        let mut odd = BTreeSet::new(2);
+        for key in 0..10 {
+            low.insert(key);
+            high.insert(key + 10);
+            if key % 2 == 1 {
+                odd.insert(key);
+            }
+        }
+        assert_eq!(low.union(&high).count(), 20);
+        assert_eq!(low.intersection(&high).next(), None);
+        assert_eq!(low.intersection(&odd).collect::<Vec<_>>(), [1, 3, 5, 7, 9]);
+        assert_eq!(low.difference(&odd).collect::<Vec<_>>(), [0, 2, 4, 6, 8]);
+        assert!(odd.is_subset(&low));
+        assert!(!low.is_subset(&odd));
+        assert!(!odd.is_subset(&high));
+    }
+}
@@ -0,0 +1,246 @@
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use super::{BTree, Node, Prefixed, Summary};
+
+/// The shape of a `BTree`, as reported by `BTree::stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeStats {
+    /// Number of levels of nodes, 1 for a tree that only has a root
+    pub height: usize,
+    pub entries: usize,
+    pub internal_nodes: usize,
+    pub leaf_nodes: usize,
+    /// One entry per level, starting at the root
+    pub levels: Vec<LevelStats>,
+    /// Average number of keys per node, as a fraction of `max_keys_per_node`
+    pub fill_factor: f64,
+    /// Estimate of the memory used by the nodes, including unused capacity
+    ///
+    /// Only counts the nodes themselves, not memory the keys or values point to.
+    pub estimated_heap_bytes: usize,
+    /// Memory used by byte-string keys, only set by `BTree::stats_with_key_memory`
+    pub key_memory: Option<KeyMemory>,
+}
+
+/// The nodes at one level of a `BTree`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelStats {
+    pub nodes: usize,
+    pub entries: usize,
+    /// Number of nodes with each number of keys, from 0 up to `max_keys_per_node`
+    pub occupancy: Vec<usize>,
+}
+
+/// Memory used by the keys, with and without prefix compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyMemory {
+    /// See `BTree::key_memory`
+    pub plain: usize,
+    /// See `BTree::compressed_key_memory`
+    pub prefix_compressed: usize,
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Walk all nodes of the tree and report its shape
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            height: 0,
+            entries: 0,
+            internal_nodes: 0,
+            leaf_nodes: 0,
+            levels: Vec::new(),
+            fill_factor: 0.0,
+            estimated_heap_bytes: 0,
+            key_memory: None,
+        };
+        let mut level = vec![Rc::clone(&self.root)];
+        while !level.is_empty() {
+            let mut level_stats = LevelStats {
+                nodes: level.len(),
+                entries: 0,
+                occupancy: vec![0; self.max_keys_per_node + 1],
+            };
+            let mut next_level = Vec::new();
+            for node in &level {
+                let borrowed_node = node.borrow();
+                let keys = borrowed_node.keys.len();
+                level_stats.entries += keys;
+                // A node can hold one key too many while it is being fixed
+                level_stats.occupancy[keys.min(self.max_keys_per_node)] += 1;
+                if borrowed_node.children.is_empty() {
+                    stats.leaf_nodes += 1;
+                } else {
+                    stats.internal_nodes += 1;
+                }
+                stats.estimated_heap_bytes += BTree::node_heap_bytes(&borrowed_node);
+                next_level.extend(borrowed_node.children.iter().cloned());
+            }
+            stats.entries += level_stats.entries;
+            stats.levels.push(level_stats);
+            level = next_level;
+        }
+        stats.height = stats.levels.len();
+        let nodes = stats.internal_nodes + stats.leaf_nodes;
+        stats.fill_factor = stats.entries as f64 / (nodes * self.max_keys_per_node) as f64;
+        stats
+    }
+
+    /// The keys of every node, one list of nodes per level starting at the root
+    ///
+    /// The nodes of a level are in key order, from left to right.
+    pub fn levels(&self) -> Vec<Vec<Vec<T>>> {
+        let mut levels = Vec::new();
+        let mut level = vec![Rc::clone(&self.root)];
+        while !level.is_empty() {
+            let mut next_level = Vec::new();
+            let mut level_keys = Vec::new();
+            for node in &level {
+                let borrowed_node = node.borrow();
+                level_keys.push(borrowed_node.keys.clone());
+                next_level.extend(borrowed_node.children.iter().cloned());
+            }
+            levels.push(level_keys);
+            level = next_level;
+        }
+        levels
+    }
+
+    /// Memory used by a node and the vectors it owns
+    fn node_heap_bytes(node: &Node<T, V, S>) -> usize {
+        Self::node_bytes(
+            node.keys.capacity(),
+            node.values.capacity(),
+            node.children.capacity(),
+        )
+    }
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug + Prefixed,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Like `stats`, and also compare the memory used by the keys with the
+    /// prefix-compressed node layout
+    pub fn stats_with_key_memory(&self) -> TreeStats {
+        let mut stats = self.stats();
+        stats.key_memory = Some(KeyMemory {
+            plain: self.key_memory(),
+            prefix_compressed: self.compressed_key_memory(),
+        });
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use alloc::vec::Vec;
+
+    use super::LevelStats;
+    use crate::test_util::{configs, random_map, tree_from, Rng};
+    use crate::{BTree, BTreeConfig, OverflowPolicy};
+
+    #[test]
+    fn stats_of_an_empty_tree() {
+        let tree: BTree<u32, u32> = BTree::empty(4);
+        let stats = tree.stats();
+        assert_eq!(stats.height, 1);
+        assert_eq!(stats.entries, 0);
+        assert_eq!(stats.internal_nodes, 0);
+        assert_eq!(stats.leaf_nodes, 1);
+        assert_eq!(
+            stats.levels,
+            [LevelStats {
+                nodes: 1,
+                entries: 0,
+                occupancy: Vec::from([1, 0, 0, 0, 0]),
+            }]
+        );
+        assert_eq!(stats.fill_factor, 0.0);
+        assert_eq!(stats.key_memory, None);
+    }
+
+    #[test]
+    fn occupancy_and_fill_factor_of_a_known_shape() {
+        let mut tree: BTree<u32, u32> = BTreeConfig::new()
+            .order(5)
+            .overflow_policy(OverflowPolicy::Redistribute)
+            .build()
+            .unwrap();
+        for key in 1..=13 {
+            tree.insert(key, key).unwrap();
+        }
+        assert_eq!(
+            tree.levels(),
+            [
+                Vec::from([Vec::from([5, 10])]),
+                Vec::from([
+                    Vec::from([1, 2, 3, 4]),
+                    Vec::from([6, 7, 8, 9]),
+                    Vec::from([11, 12, 13])
+                ]),
+            ]
+        );
+        let stats = tree.stats();
+        assert_eq!(stats.height, 2);
+        assert_eq!(stats.entries, 13);
+        assert_eq!(stats.internal_nodes, 1);
+        assert_eq!(stats.leaf_nodes, 3);
+        assert_eq!(stats.levels[0].occupancy, [0, 0, 1, 0, 0]);
+        assert_eq!(stats.levels[1].occupancy, [0, 0, 0, 1, 2]);
+        assert_eq!(stats.levels[1].entries, 11);
+        // 13 keys in 4 nodes of 4 keys each
+        assert_eq!(stats.fill_factor, 13.0 / 16.0);
+    }
+
+    #[test]
+    fn stats_add_up_for_every_configuration() {
+        let mut rng = Rng::new(41);
+        for config in configs() {
+            let map = random_map(&mut rng, 500, 5000);
+            let tree = tree_from(&config, &map);
+            let stats = tree.stats();
+            assert_eq!(stats.entries, tree.len());
+            assert_eq!(stats.height, tree.levels().len());
+            let nodes: usize = stats.levels.iter().map(|level| level.nodes).sum();
+            assert_eq!(nodes, stats.internal_nodes + stats.leaf_nodes);
+            assert_eq!(stats.levels.last().unwrap().nodes, stats.leaf_nodes);
+            for level in &stats.levels {
+                assert_eq!(level.occupancy.iter().sum::<usize>(), level.nodes);
+                let keys: usize = level
+                    .occupancy
+                    .iter()
+                    .enumerate()
+                    .map(|(keys, nodes)| keys * nodes)
+                    .sum();
+                assert_eq!(keys, level.entries);
+            }
+            assert!(stats.fill_factor > 0.0 && stats.fill_factor <= 1.0);
+            assert!(stats.estimated_heap_bytes > 0);
+        }
+    }
+
+    #[test]
+    fn stats_with_key_memory_reports_both_layouts() {
+        let mut tree: BTree<Vec<u8>, u32> = BTree::empty(4);
+        for i in 0..100 {
+            tree.insert(format!("shared/prefix/{i:03}").into_bytes(), i)
+                .unwrap();
+        }
+        let stats = tree.stats_with_key_memory();
+        let key_memory = stats.key_memory.unwrap();
+        assert_eq!(key_memory.plain, tree.key_memory());
+        assert_eq!(key_memory.prefix_compressed, tree.compressed_key_memory());
+        assert_eq!(stats.entries, 100);
+    }
+}
@@ -0,0 +1,221 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt::Debug;
+use core::ops::{Bound, RangeBounds};
+
+use super::{BTree, Node};
+
+/// A value that summarises a sequence of entries, cached in every node
+///
+/// Summaries form a monoid: `combine` must be associative and `identity` must
+/// leave any summary unchanged when combined with it. Summaries are always
+/// combined in key order, so `combine` does not need to be commutative.
+///
+/// The summary of a node covers all entries in the node and below it, which
+/// lets `BTree::aggregate` combine whole subtrees at once. `()` is the summary
+/// of a tree that does not need one.
+pub trait Summary<T, V>: Clone {
+    /// Summary of no entries at all
+    fn identity() -> Self;
+
+    /// Summary of a single entry
+    fn from_entry(key: &T, value: &V) -> Self;
+
+    /// Summary of the entries of `self` followed by the entries of `other`
+    fn combine(&self, other: &Self) -> Self;
+}
+
+impl<T, V> Summary<T, V> for () {
+    fn identity() -> Self {}
+
+    fn from_entry(_key: &T, _value: &V) -> Self {}
+
+    fn combine(&self, _other: &Self) -> Self {}
+}
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Summary of all entries with keys inside `range`
+    ///
+    /// Subtrees that lie entirely inside the range contribute their cached
+    /// summary, so only the nodes along the paths to both ends of the range
+    /// are visited.
+    pub fn aggregate<R: RangeBounds<T>>(&self, range: R) -> S {
+        BTree::aggregate_node(&self.root, &range, false, false)
+    }
+
+    /// Summary of the entries below `node` that are inside `range`
+    ///
+    /// `covered_start` and `covered_end` tell whether all keys below `node`
+    /// are already known to be on the inside of the start and end of the range.
+    fn aggregate_node<R: RangeBounds<T>>(
+        node: &Rc<RefCell<Node<T, V, S>>>,
+        range: &R,
+        covered_start: bool,
+        covered_end: bool,
+    ) -> S {
+        let borrowed_node = node.borrow();
+        if covered_start && covered_end {
+            return borrowed_node.summary.clone();
+        }
+        let after_start = |key: &T| match range.start_bound() {
+            Bound::Included(start) => key >= start,
+            Bound::Excluded(start) => key > start,
+            Bound::Unbounded => true,
+        };
+        let before_end = |key: &T| match range.end_bound() {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+        let mut summary = S::identity();
+        for i in 0..=borrowed_node.keys.len() {
+            // The keys in child `i` lie between the keys before and after it
+            let key_before = i.checked_sub(1).map(|j| &borrowed_node.keys[j]);
+            let key_after = borrowed_node.keys.get(i);
+            if let Some(child) = borrowed_node.children.get(i) {
+                let below_start = key_after.is_some_and(|key| !after_start(key));
+                let above_end = key_before.is_some_and(|key| !before_end(key));
+                if !below_start && !above_end {
+                    let child_summary = BTree::aggregate_node(
+                        child,
+                        range,
+                        key_before.map_or(covered_start, &after_start),
+                        key_after.map_or(covered_end, &before_end),
+                    );
+                    summary = summary.combine(&child_summary);
+                }
+            }
+            if let Some(key) = key_after {
+                if after_start(key) && before_end(key) {
+                    summary = summary.combine(&S::from_entry(key, &borrowed_node.values[i]));
+                }
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
+    use core::ops::Bound;
+
+    use super::Summary;
+    use crate::test_util::{configs, Rng};
+    use crate::{BTree, BTreeConfig};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Sum(u64);
+
+    impl Summary<u32, u32> for Sum {
+        fn identity() -> Self {
+            Sum(0)
+        }
+
+        fn from_entry(_key: &u32, value: &u32) -> Self {
+            Sum(u64::from(*value))
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            Sum(self.0 + other.0)
+        }
+    }
+
+    fn expected_sum(map: &BTreeMap<u32, u32>, start: u32, end: u32) -> Sum {
+        Sum(map
+            .range(start..end)
+            .map(|(_, value)| u64::from(*value))
+            .sum())
+    }
+
+    #[test]
+    fn aggregate_stays_correct_while_the_tree_changes() {
+        let mut rng = Rng::new(11);
+        for config in configs() {
+            let mut tree: BTree<u32, u32, Sum> = config.build_with_summary().unwrap();
+            let mut map = BTreeMap::new();
+            for step in 0..1500 {
+                let key = rng.below(400);
+                match rng.below(10) {
+                    0..=5 => {
+                        if tree.insert(key, step).is_ok() {
+                            map.insert(key, step);
+                        }
+                    }
+                    6..=8 => assert_eq!(tree.remove(key).ok(), map.remove(&key)),
+                    _ => {
+                        let mut right = tree.split_off(&key);
+                        let mut right_map = map.split_off(&key);
+                        let _ = right.extract_if(.., |key, _| key % 4 == 0).count();
+                        right_map.retain(|key, _| key % 4 != 0);
+                        tree.append(&mut right);
+                        map.append(&mut right_map);
+                    }
+                }
+                if step % 25 == 0 {
+                    tree.validate().unwrap();
+                    let start = rng.below(400);
+                    let end = start + rng.below(200);
+                    assert_eq!(tree.aggregate(start..end), expected_sum(&map, start, end));
+                    assert_eq!(tree.aggregate(..), expected_sum(&map, 0, u32::MAX));
+                }
+            }
+        }
+    }
+
+    /// The keys of the summarised entries, in the order they were combined
+    #[derive(Debug, Clone, PartialEq)]
+    struct Keys(Vec<u32>);
+
+    impl Summary<u32, ()> for Keys {
+        fn identity() -> Self {
+            Keys(Vec::new())
+        }
+
+        fn from_entry(key: &u32, _value: &()) -> Self {
+            Keys(Vec::from([*key]))
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            Keys([self.0.as_slice(), other.0.as_slice()].concat())
+        }
+    }
+
+    #[test]
+    fn summaries_are_combined_in_key_order() {
+        for config in configs() {
+            let mut tree: BTree<u32, (), Keys> = config.build_with_summary().unwrap();
+            // Descending inserts, so the insertion order is not the key order
+            for key in (0..100).rev() {
+                tree.insert(key, ()).unwrap();
+            }
+            assert_eq!(tree.aggregate(..), Keys((0..100).collect()));
+            assert_eq!(tree.aggregate(17..=63), Keys((17..=63).collect()));
+            let bounds = (Bound::Excluded(40), Bound::Excluded(90));
+            assert_eq!(tree.aggregate(bounds), Keys((41..90).collect()));
+        }
+    }
+
+    #[test]
+    fn empty_ranges_give_the_identity() {
+        let mut tree: BTree<u32, u32, Sum> =
+            BTreeConfig::new().order(3).build_with_summary().unwrap();
+        assert_eq!(tree.aggregate(..), Sum(0));
+        for key in 0..50 {
+            tree.insert(key * 2, 1).unwrap();
+        }
+        assert_eq!(tree.aggregate(..), Sum(50));
+        assert_eq!(tree.aggregate(10..10), Sum(0));
+        // Only odd keys lie between two neighbouring even keys
+        assert_eq!(tree.aggregate(11..12), Sum(0));
+        assert_eq!(tree.aggregate(200..), Sum(0));
+        assert_eq!(tree.aggregate(..0), Sum(0));
+        assert_eq!(tree.aggregate(10..=10), Sum(1));
+    }
+}
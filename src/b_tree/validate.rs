@@ -0,0 +1,96 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt::Debug;
+
+use super::{BTree, Node, Summary};
+
+impl<T, V, S> BTree<T, V, S>
+where
+    T: PartialOrd + Clone + Debug,
+    V: Clone + Debug,
+    S: Summary<T, V>,
+{
+    /// Check the invariants of the tree, returns an Err describing the first
+    /// one that does not hold
+    ///
+    /// Checks that the keys are sorted across all nodes, that every node but
+    /// the root has an allowed number of keys, that internal nodes have one
+    /// child more than they have keys, that all leaves are at the same depth,
+    /// and that the parent links and entry counts are correct. Walks the
+    /// whole tree, so this is meant for testing and debugging.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.root.borrow().parent.is_some() {
+            return Err("Root has a parent");
+        }
+        let mut leaf_depth = None;
+        self.validate_node(&self.root, 0, &mut leaf_depth, None, None)?;
+        Ok(())
+    }
+
+    /// Check the subtree below `node`, whose keys must lie between `lower` and `upper`
+    ///
+    /// Returns the number of entries in the subtree.
+    fn validate_node(
+        &self,
+        node: &Rc<RefCell<Node<T, V, S>>>,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+        lower: Option<&T>,
+        upper: Option<&T>,
+    ) -> Result<usize, &'static str> {
+        let borrowed_node = node.borrow();
+        if borrowed_node.keys.len() != borrowed_node.values.len() {
+            return Err("Node has a different number of keys and values");
+        }
+        if borrowed_node.keys.len() > self.max_keys_per_node {
+            return Err("Node has too many keys");
+        }
+        if depth > 0 && borrowed_node.keys.len() < self.min_keys_allowed() {
+            return Err("Node has too few keys");
+        }
+        if borrowed_node.keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("Keys are out of order");
+        }
+        let out_of_bounds = borrowed_node.keys.iter().any(|key| {
+            lower.is_some_and(|lower| key <= lower) || upper.is_some_and(|upper| key >= upper)
+        });
+        if out_of_bounds {
+            return Err("Key is on the wrong side of a key in a parent node");
+        }
+
+        let mut size = borrowed_node.keys.len();
+        if borrowed_node.children.is_empty() {
+            match leaf_depth {
+                Some(leaf_depth) if *leaf_depth != depth => {
+                    return Err("Leaves are at different depths");
+                }
+                _ => *leaf_depth = Some(depth),
+            }
+        } else {
+            if borrowed_node.children.len() != borrowed_node.keys.len() + 1 {
+                return Err("Node has the wrong number of children");
+            }
+            for (i, child) in borrowed_node.children.iter().enumerate() {
+                let parent = child
+                    .borrow()
+                    .parent
+                    .as_ref()
+                    .and_then(|parent| parent.upgrade());
+                if !parent.is_some_and(|parent| Rc::ptr_eq(&parent, node)) {
+                    return Err("Child does not link back to its parent");
+                }
+                let lower = if i == 0 {
+                    lower
+                } else {
+                    borrowed_node.keys.get(i - 1)
+                };
+                let upper = borrowed_node.keys.get(i).or(upper);
+                size += self.validate_node(child, depth + 1, leaf_depth, lower, upper)?;
+            }
+        }
+        if borrowed_node.size != size {
+            return Err("Node has the wrong entry count");
+        }
+        Ok(size)
+    }
+}
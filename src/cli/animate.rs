@@ -0,0 +1,403 @@
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::prelude::rust_2021::*;
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use super::{Command, Key, Shell};
+use crate::TreeObserver;
+
+/// How `b-tree-lib animate` waits after showing a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pause {
+    #[default]
+    None,
+    /// Sleep for a while
+    Delay(Duration),
+    /// Wait until Enter is pressed
+    Step,
+}
+
+/// Settings of `b-tree-lib animate`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnimateOptions {
+    /// File to read the commands from, stdin when None or `-`
+    pub script: Option<String>,
+    pub pause: Pause,
+}
+
+impl AnimateOptions {
+    /// Parse the arguments after `animate`
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = AnimateOptions::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--delay" => {
+                    let milliseconds = args
+                        .next()
+                        .ok_or("--delay is missing the number of milliseconds")?;
+                    let milliseconds = milliseconds
+                        .parse()
+                        .map_err(|_| format!("invalid number of milliseconds '{milliseconds}'"))?;
+                    options.pause = Pause::Delay(Duration::from_millis(milliseconds));
+                }
+                "--step" => options.pause = Pause::Step,
+                _ if arg.starts_with("--") => return Err(format!("unknown option '{arg}'")),
+                _ if options.script.is_some() => {
+                    return Err(format!("only one script can be run, got '{arg}' as well"))
+                }
+                _ => options.script = Some(arg),
+            }
+        }
+        let reads_stdin = matches!(options.script.as_deref(), None | Some("-"));
+        if options.pause == Pause::Step && reads_stdin {
+            return Err(
+                "--step reads Enter from stdin, so the commands must come from a script"
+                    .to_string(),
+            );
+        }
+        Ok(options)
+    }
+}
+
+/// Run shell commands, and show how every insertion and removal changes the nodes
+///
+/// For `insert` and `remove` the nodes that changed are shown before and
+/// after, level by level. The tree reports its splits, merges and borrows
+/// while the command runs. A key that moved up a level, like the separator
+/// that a split promotes into the parent, is marked with `↑`. A key that
+/// moved down, like the separator a merge pulls down into the merged node,
+/// is marked with `↓`. Other commands show their output as in the shell.
+pub fn run(options: &AnimateOptions) -> ExitCode {
+    let input: Box<dyn BufRead> = match options.script.as_deref() {
+        Some(path) if path != "-" => match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("error: cannot read {path}: {e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => Box::new(io::stdin().lock()),
+    };
+    let prompt = options.script.is_none() && io::stdin().is_terminal();
+    let mut shell = Shell::new();
+    let mut failed = false;
+    let mut lines = input.lines();
+    loop {
+        if prompt {
+            print!("> ");
+            io::stdout().flush().ok();
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                eprintln!("error: cannot read input: {e}");
+                return ExitCode::FAILURE;
+            }
+            None => break,
+        };
+        let command = match Command::parse(&line) {
+            Ok(Some(Command::Exit(Some(code)))) => return ExitCode::from(code),
+            Ok(Some(Command::Exit(None))) => break,
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(e) => {
+                failed = true;
+                eprintln!("error: {e}");
+                continue;
+            }
+        };
+        let animated = matches!(command, Command::Insert(..) | Command::Remove(..));
+        if animated {
+            match execute_animated(&mut shell, &command) {
+                Ok((output, frame)) => {
+                    println!("{}", line.trim());
+                    if !output.is_empty() {
+                        println!("  value: {output}");
+                    }
+                    print!("{frame}");
+                    pause(options.pause);
+                }
+                Err(e) => {
+                    failed = true;
+                    eprintln!("error: {e}");
+                }
+            }
+            continue;
+        }
+        match shell.execute(&command) {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => println!("{}", output.trim_end()),
+            Err(e) => {
+                failed = true;
+                eprintln!("error: {e}");
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn pause(pause: Pause) {
+    match pause {
+        Pause::None => {}
+        Pause::Delay(delay) => thread::sleep(delay),
+        Pause::Step => {
+            print!("  (press Enter to continue)");
+            io::stdout().flush().ok();
+            // Whatever was typed does not matter, only that Enter was pressed
+            io::stdin().lock().read_line(&mut String::new()).ok();
+        }
+    }
+}
+
+/// Run an insertion or removal, and show how it changed the nodes
+///
+/// Returns the output of the command and the frame to show.
+fn execute_animated(shell: &mut Shell, command: &Command) -> Result<(String, String), String> {
+    let before = shell.tree().levels();
+    let recorder = StepRecorder::default();
+    shell.tree_mut().set_observer(recorder.clone());
+    let result = shell.execute(command);
+    shell.tree_mut().take_observer();
+    let output = result?;
+    let step = recorder.0.take();
+    Ok((output, render_step(&before, &shell.tree().levels(), &step)))
+}
+
+/// What the tree reported while running one command
+#[derive(Debug, Default)]
+struct Step {
+    /// Keys that moved between levels, with how many levels they moved up,
+    /// or down when negative, in the order they first moved
+    shifts: Vec<(Key, isize)>,
+    /// Number of levels after the last time the tree got taller or shorter
+    height: Option<usize>,
+}
+
+impl Step {
+    fn shift(&mut self, key: &Key, levels: isize) {
+        match self.shifts.iter_mut().find(|(shifted, _)| shifted == key) {
+            Some((_, shift)) => *shift += levels,
+            None => self.shifts.push((key.clone(), levels)),
+        }
+    }
+}
+
+/// Collects a `Step` from the callbacks of the tree, shared with the caller
+#[derive(Clone, Default)]
+struct StepRecorder(Rc<RefCell<Step>>);
+
+impl TreeObserver<Key> for StepRecorder {
+    fn on_split(&mut self, _left: &[Key], _right: &[Key], separator: &Key) {
+        self.0.borrow_mut().shift(separator, 1);
+    }
+
+    fn on_merge(&mut self, _merged: &[Key], separator: &Key) {
+        self.0.borrow_mut().shift(separator, -1);
+    }
+
+    fn on_borrow(&mut self, _lender: &[Key], borrower: &[Key], separator: &Key) {
+        // The old separator is now the key of the borrower next to the lender
+        let old_separator = if borrower.first().is_some_and(|key| key > separator) {
+            borrower.first()
+        } else {
+            borrower.last()
+        };
+        let mut step = self.0.borrow_mut();
+        step.shift(separator, 1);
+        if let Some(key) = old_separator {
+            step.shift(key, -1);
+        }
+    }
+
+    fn on_height_change(&mut self, _old_height: usize, new_height: usize) {
+        self.0.borrow_mut().height = Some(new_height);
+    }
+}
+
+/// A key that moved to another level during a step
+struct Move {
+    key: Key,
+    promoted: bool,
+    level: usize,
+}
+
+/// Show the nodes that differ between two snapshots of `BTree::levels`, with
+/// the keys that `step` moved between levels marked
+///
+/// Levels are counted up from the leaves, so that a level keeps its number
+/// when the root is split or merged away. A key that went up and came back
+/// down again, like when keys are passed on through a sibling, is not marked.
+fn render_step(before: &[Vec<Vec<Key>>], after: &[Vec<Vec<Key>>], step: &Step) -> String {
+    let level_of = |key: &Key| {
+        after
+            .iter()
+            .rev()
+            .position(|nodes| nodes.iter().flatten().any(|other| other == key))
+    };
+    let moves: Vec<Move> = step
+        .shifts
+        .iter()
+        .filter(|(_, shift)| *shift != 0)
+        .filter_map(|(key, shift)| {
+            Some(Move {
+                key: key.clone(),
+                promoted: *shift > 0,
+                level: level_of(key)?,
+            })
+        })
+        .collect();
+
+    let mut output = String::new();
+    writeln!(output, "  before:").unwrap();
+    render_changed_nodes(&mut output, before, after, &[]);
+    writeln!(output, "  after:").unwrap();
+    render_changed_nodes(&mut output, after, before, &moves);
+    for key_move in &moves {
+        let how = if key_move.promoted {
+            "promoted"
+        } else {
+            "pulled down"
+        };
+        writeln!(
+            output,
+            "  {:?} was {how} to level {}",
+            key_move.key, key_move.level
+        )
+        .unwrap();
+    }
+    match step.height {
+        Some(1) => writeln!(output, "  the tree has 1 level now").unwrap(),
+        Some(height) => writeln!(output, "  the tree has {height} levels now").unwrap(),
+        None => {}
+    }
+    output
+}
+
+/// Show the nodes of `levels` that are not in `other`, with the keys in `moves` marked
+fn render_changed_nodes(
+    output: &mut String,
+    levels: &[Vec<Vec<Key>>],
+    other: &[Vec<Vec<Key>>],
+    moves: &[Move],
+) {
+    let marker = |key: &Key| match moves.iter().find(|key_move| key_move.key == *key) {
+        Some(Move { promoted: true, .. }) => "↑",
+        Some(_) => "↓",
+        None => "",
+    };
+    for (level, nodes) in levels.iter().rev().enumerate().rev() {
+        let other_nodes = match other.len().checked_sub(level + 1) {
+            Some(i) => &other[i][..],
+            None => &[],
+        };
+        let changed: Vec<String> = nodes
+            .iter()
+            .filter(|node| !other_nodes.contains(node))
+            .map(|node| {
+                let keys: Vec<String> = node
+                    .iter()
+                    .map(|key| format!("{key:?}{}", marker(key)))
+                    .collect();
+                format!("[{}]", keys.join(", "))
+            })
+            .collect();
+        if !changed.is_empty() {
+            writeln!(output, "    level {level}: {}", changed.join(" ")).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::rust_2021::*;
+
+    use super::{execute_animated, Command, Shell};
+
+    /// Run all of `lines` in a new shell, returns the frame of the last one
+    fn last_frame(lines: &[&str]) -> String {
+        let mut shell = Shell::new();
+        let (last, setup) = lines.split_last().unwrap();
+        for line in setup {
+            shell
+                .execute(&Command::parse(line).unwrap().unwrap())
+                .unwrap();
+        }
+        let command = Command::parse(last).unwrap().unwrap();
+        execute_animated(&mut shell, &command).unwrap().1
+    }
+
+    #[test]
+    fn a_split_promotes_the_new_key() {
+        let frame = last_frame(&[
+            "insert 1 a",
+            "insert 3 a",
+            "insert 5 a",
+            "insert 8 a",
+            "insert 4 a",
+        ]);
+        assert_eq!(
+            frame,
+            "  before:\n    level 0: [1, 3, 5, 8]\n  after:\n    level 1: [4↑]\n    level 0: [1, 3] [5, 8]\n  4 was promoted to level 1\n  the tree has 2 levels now\n"
+        );
+    }
+
+    #[test]
+    fn a_merge_pulls_down_the_predecessor() {
+        let frame = last_frame(&[
+            "insert 1 a",
+            "insert 2 a",
+            "insert 3 a",
+            "insert 4 a",
+            "insert 5 a",
+            "remove 3",
+        ]);
+        assert_eq!(
+            frame,
+            "  before:\n    level 1: [3]\n    level 0: [1, 2] [4, 5]\n  after:\n    level 0: [1, 2↓, 4, 5]\n  2 was pulled down to level 0\n  the tree has 1 level now\n"
+        );
+    }
+
+    #[test]
+    fn replacing_a_key_by_its_predecessor_moves_nothing() {
+        let frame = last_frame(&[
+            "insert 1 a",
+            "insert 2 a",
+            "insert 3 a",
+            "insert 4 a",
+            "insert 5 a",
+            "insert 0 a",
+            "remove 3",
+        ]);
+        assert_eq!(
+            frame,
+            "  before:\n    level 1: [3]\n    level 0: [0, 1, 2]\n  after:\n    level 1: [2]\n    level 0: [0, 1]\n"
+        );
+    }
+
+    #[test]
+    fn a_borrow_moves_one_key_up_and_one_down() {
+        let frame = last_frame(&[
+            "insert 1 a",
+            "insert 2 a",
+            "insert 3 a",
+            "insert 4 a",
+            "insert 5 a",
+            "insert 6 a",
+            "remove 1",
+        ]);
+        assert_eq!(
+            frame,
+            "  before:\n    level 1: [3]\n    level 0: [1, 2] [4, 5, 6]\n  after:\n    level 1: [4↑]\n    level 0: [2, 3↓] [5, 6]\n  4 was promoted to level 1\n  3 was pulled down to level 0\n"
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::fs;
+use std::io;
+use std::prelude::rust_2021::*;
+use std::process::ExitCode;
+
+use super::{Command, Shell};
+
+/// Settings of `b-tree-lib run`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchOptions {
+    /// File to read the commands from, stdin when None or `-`
+    pub script: Option<String>,
+    /// File with the output the script must produce
+    pub expect: Option<String>,
+    /// Check the invariants of the tree after every command
+    pub validate_each: bool,
+}
+
+impl BatchOptions {
+    /// Parse the arguments after `run`
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = BatchOptions::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--expect" => match args.next() {
+                    Some(path) => options.expect = Some(path),
+                    None => return Err("--expect is missing the file name".to_string()),
+                },
+                "--validate-each" => options.validate_each = true,
+                _ if arg.starts_with("--") => return Err(format!("unknown option '{arg}'")),
+                _ if options.script.is_some() => {
+                    return Err(format!("only one script can be run, got '{arg}' as well"))
+                }
+                _ => options.script = Some(arg),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Name of the script in messages
+    fn script_name(&self) -> &str {
+        match self.script.as_deref() {
+            Some(path) if path != "-" => path,
+            _ => "<stdin>",
+        }
+    }
+}
+
+/// Run a script of shell commands without a prompt
+///
+/// Every command runs even when an earlier one failed. Errors are shown as
+/// `error: line N: ...`, so the output of `run SCRIPT > FILE 2>&1` can be used
+/// as the golden output for `--expect`. With `--expect` the output is not shown
+/// but compared with the file, and the exit code tells whether they matched.
+/// Otherwise the exit code is the one passed to `exit`, or failure if any
+/// command failed.
+pub fn run(options: &BatchOptions) -> ExitCode {
+    let source = match options.script.as_deref() {
+        Some(path) if path != "-" => fs::read_to_string(path),
+        _ => io::read_to_string(io::stdin()),
+    };
+    let source = match source {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: cannot read {}: {e}", options.script_name());
+            return ExitCode::FAILURE;
+        }
+    };
+    let expected = match options.expect.as_deref().map(fs::read_to_string) {
+        Some(Ok(expected)) => Some(expected),
+        Some(Err(e)) => {
+            eprintln!(
+                "error: cannot read {}: {e}",
+                options.expect.as_deref().unwrap()
+            );
+            return ExitCode::FAILURE;
+        }
+        None => None,
+    };
+
+    // Only collected when there is output to compare with
+    let mut transcript = String::new();
+    let ending = execute(&source, options, |text| match text {
+        Ok(text) | Err(text) if expected.is_some() => {
+            transcript.push_str(text);
+            transcript.push('\n');
+        }
+        Ok(output) => println!("{output}"),
+        Err(message) => eprintln!("{message}"),
+    });
+
+    if let Ending::Invalid(message) = ending {
+        eprintln!("{message}");
+        return ExitCode::FAILURE;
+    }
+    match expected {
+        Some(expected) => match compare(&expected, &transcript) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!(
+                    "error: output of {} does not match {}: {e}",
+                    options.script_name(),
+                    options.expect.as_deref().unwrap()
+                );
+                ExitCode::FAILURE
+            }
+        },
+        None => match ending {
+            Ending::Exit(code) => ExitCode::from(code),
+            Ending::Done { failed: true } => ExitCode::FAILURE,
+            _ => ExitCode::SUCCESS,
+        },
+    }
+}
+
+/// How a script ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Ending {
+    /// At the end of the script or at `exit` without a code, tells whether any command failed
+    Done { failed: bool },
+    /// At `exit` with a code
+    Exit(u8),
+    /// With `--validate-each`, at the first command after which the tree was invalid
+    Invalid(String),
+}
+
+/// Run the commands of `source` on a new shell, and pass what they show to `show`
+///
+/// `show` gets the output of every command that has any, or the error message
+/// of every command that failed, in the order the commands ran.
+fn execute<F>(source: &str, options: &BatchOptions, mut show: F) -> Ending
+where
+    F: FnMut(Result<&str, &str>),
+{
+    let mut shell = Shell::new();
+    let mut failed = false;
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let result = match Command::parse(line) {
+            Ok(Some(Command::Exit(Some(code)))) => return Ending::Exit(code),
+            Ok(Some(Command::Exit(None))) => break,
+            Ok(Some(command)) => shell.execute(&command),
+            Ok(None) => continue,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => show(Ok(output.trim_end())),
+            Err(e) => {
+                failed = true;
+                show(Err(&format!("error: line {line_number}: {e}")));
+            }
+        }
+        if options.validate_each {
+            if let Err(e) = shell.tree().validate() {
+                return Ending::Invalid(format!(
+                    "error: {}: line {line_number}: tree is invalid after '{}': {e}",
+                    options.script_name(),
+                    line.trim()
+                ));
+            }
+        }
+    }
+    Ending::Done { failed }
+}
+
+/// Compare two outputs line by line, describes the first line that differs
+///
+/// Trailing whitespace and empty lines at the end are ignored, since editors
+/// tend to add or strip them in golden files.
+fn compare(expected: &str, actual: &str) -> Result<(), String> {
+    let lines = |text: &str| {
+        let mut lines: Vec<String> = text
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect();
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        lines
+    };
+    let expected = lines(expected);
+    let actual = lines(actual);
+    let show = |line: Option<&String>| match line {
+        Some(line) => format!("'{line}'"),
+        None => "the end of the output".to_string(),
+    };
+    for i in 0..expected.len().max(actual.len()) {
+        if expected.get(i) != actual.get(i) {
+            return Err(format!(
+                "line {} should be {} but is {}",
+                i + 1,
+                show(expected.get(i)),
+                show(actual.get(i))
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::prelude::rust_2021::*;
+    use std::process::ExitCode;
+
+    use super::{compare, execute, run, BatchOptions, Ending};
+
+    fn options(args: &[&str]) -> Result<BatchOptions, String> {
+        BatchOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    /// Run `source`, returns how it ended and everything it showed
+    fn execute_script(source: &str, validate_each: bool) -> (Ending, Vec<String>) {
+        let options = BatchOptions {
+            validate_each,
+            ..BatchOptions::default()
+        };
+        let mut shown = Vec::new();
+        let ending = execute(source, &options, |text| match text {
+            Ok(output) => shown.push(output.to_string()),
+            Err(message) => shown.push(format!("stderr: {message}")),
+        });
+        (ending, shown)
+    }
+
+    /// Write `contents` to a file only this test uses, returns its path
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!("b-tree-lib-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn options_of_run() {
+        assert_eq!(options(&[]), Ok(BatchOptions::default()));
+        assert_eq!(
+            options(&["--validate-each", "script.txt", "--expect", "golden.txt"]),
+            Ok(BatchOptions {
+                script: Some("script.txt".to_string()),
+                expect: Some("golden.txt".to_string()),
+                validate_each: true,
+            })
+        );
+        assert_eq!(
+            options(&["--expect"]),
+            Err("--expect is missing the file name".to_string())
+        );
+        assert_eq!(
+            options(&["--verbose"]),
+            Err("unknown option '--verbose'".to_string())
+        );
+        assert_eq!(
+            options(&["a.txt", "b.txt"]),
+            Err("only one script can be run, got 'b.txt' as well".to_string())
+        );
+        assert_eq!(options(&["-"]).unwrap().script_name(), "<stdin>");
+        assert_eq!(options(&["a.txt"]).unwrap().script_name(), "a.txt");
+    }
+
+    #[test]
+    fn every_command_runs_and_errors_name_their_line() {
+        let (ending, shown) = execute_script("insert 1 one\nget 2\n\n# comment\nget 1\nlen", false);
+        assert_eq!(ending, Ending::Done { failed: true });
+        assert_eq!(
+            shown,
+            [
+                "stderr: error: line 2: cannot get 2: Key not found",
+                "one",
+                "1"
+            ]
+        );
+        let (ending, _) = execute_script("insert 1 one\nget 1", false);
+        assert_eq!(ending, Ending::Done { failed: false });
+    }
+
+    #[test]
+    fn exit_stops_the_script() {
+        let (ending, shown) = execute_script("len\nexit 4\nlen", false);
+        assert_eq!(ending, Ending::Exit(4));
+        assert_eq!(shown, ["0"]);
+        let (ending, shown) = execute_script("get 1\nexit\nlen", false);
+        assert_eq!(ending, Ending::Done { failed: true });
+        assert_eq!(shown.len(), 1);
+    }
+
+    #[test]
+    fn validate_each_checks_the_tree_through_splits_and_merges() {
+        let mut script = String::from("order 3\n");
+        for key in 0..200 {
+            script.push_str(&format!("insert {} v\n", key * 7 % 200));
+        }
+        script.push_str("order 6\n");
+        for key in 0..150 {
+            script.push_str(&format!("remove {key}\n"));
+        }
+        script.push_str("len\n");
+        let (ending, shown) = execute_script(&script, true);
+        assert_eq!(ending, Ending::Done { failed: false });
+        assert_eq!(shown.last().unwrap(), "50");
+    }
+
+    #[test]
+    fn compare_describes_the_first_difference() {
+        assert_eq!(compare("a\nb\n", "a\nb"), Ok(()));
+        assert_eq!(compare("a  \nb\n\n\n", "a\nb  \n"), Ok(()));
+        assert_eq!(
+            compare("a\nb\n", "a\nc\n"),
+            Err("line 2 should be 'b' but is 'c'".to_string())
+        );
+        assert_eq!(
+            compare("a\nb\n", "a\n"),
+            Err("line 2 should be 'b' but is the end of the output".to_string())
+        );
+        assert_eq!(
+            compare("", "a"),
+            Err("line 1 should be the end of the output but is 'a'".to_string())
+        );
+    }
+
+    #[test]
+    fn expect_compares_the_output_with_a_golden_file() {
+        let script = temp_file("expect.script", "insert 2 two\nget 2\nget 3\nexit 5\n");
+        let golden = temp_file(
+            "expect.golden",
+            "two\nerror: line 3: cannot get 3: Key not found\n",
+        );
+        let wrong = temp_file("expect.wrong", "two\n");
+        let run_with = |expect: &str| {
+            run(&BatchOptions {
+                script: Some(script.clone()),
+                expect: Some(expect.to_string()),
+                validate_each: true,
+            })
+        };
+        // The exit code tells whether the output matched, not what the script returned
+        assert_eq!(run_with(&golden), ExitCode::SUCCESS);
+        assert_eq!(run_with(&wrong), ExitCode::FAILURE);
+        assert_eq!(run_with("/nonexistent/golden"), ExitCode::FAILURE);
+        let without_expect = BatchOptions {
+            script: Some(script.clone()),
+            ..BatchOptions::default()
+        };
+        assert_eq!(run(&without_expect), ExitCode::from(5));
+        for path in [script, golden, wrong] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::prelude::rust_2021::*;
+use std::str::FromStr;
+
+use super::key::KeyType;
+use super::transfer::Transfer;
+
+/// One line of the shell's command language
+///
+/// Keys are kept as text, they are parsed with the key type of the shell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Insert(String, String),
+    Get(String),
+    Remove(String),
+    /// Both ends are included
+    Range(String, String),
+    Len,
+    Dump,
+    Stats,
+    Validate,
+    /// Rebuild with this order, the maximum number of children per node
+    Order(usize),
+    /// Show the key type, or change it
+    Keys(Option<KeyType>),
+    Import(Transfer),
+    Export(Transfer),
+    Help,
+    /// Leave the shell, with the given exit code
+    Exit(Option<u8>),
+}
+
+pub const HELP: &str = "\
+commands:
+  insert K V        insert value V under key K, V is the rest of the line
+  get K             show the value stored under K
+  remove K          remove K and show its value
+  range A B         show the entries with A <= key <= B
+  len               show the number of entries
+  dump              show the keys of every node, indented by depth
+  stats             show the height, node counts and fill factor
+  validate          check the invariants of the tree
+  order N           rebuild the tree with at most N children and N - 1 keys
+                    per node
+  keys [TYPE]       show the key type, or change it to int, float or string
+                    while the tree is empty
+  import FILE [OPTIONS]
+                    add the entries of a CSV or JSON lines file, replacing
+                    the values of keys that are already in the tree
+  export FILE [OPTIONS]
+                    write all entries to a CSV or JSON lines file
+  help              show this list
+  exit [CODE]       leave the shell
+options of import and export:
+  --format F        csv or jsonl, by default jsonl for .jsonl and .ndjson files
+  --key COLUMN      name or number of the key column, the first by default
+  --value COLUMN    name or number of the value column, the second by default
+  --key-type TYPE   only for import, the type to read the keys as";
+
+impl Command {
+    /// Parse a line, returns None for a line without a command
+    ///
+    /// Lines starting with `#` are comments.
+    pub fn parse(line: &str) -> Result<Option<Command>, String> {
+        let line = line.trim();
+        if line.starts_with('#') {
+            return Ok(None);
+        }
+        let (name, rest) = match line.split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, rest.trim_start()),
+            None => (line, ""),
+        };
+        let mut args = rest.split_whitespace();
+        let command = match name {
+            "" => return Ok(None),
+            "insert" => {
+                let key = parse_arg(args.next(), "insert", "key")?;
+                // The value is everything after the key, so it can hold spaces
+                let value = rest
+                    .split_once(char::is_whitespace)
+                    .map(|(_, value)| value.trim());
+                match value {
+                    Some(value) if !value.is_empty() => Command::Insert(key, value.to_string()),
+                    _ => return Err("insert needs a key and a value".to_string()),
+                }
+            }
+            "get" => Command::Get(parse_arg(args.next(), name, "key")?),
+            "remove" => Command::Remove(parse_arg(args.next(), name, "key")?),
+            "range" => Command::Range(
+                parse_arg(args.next(), name, "start key")?,
+                parse_arg(args.next(), name, "end key")?,
+            ),
+            "len" => Command::Len,
+            "dump" => Command::Dump,
+            "stats" => Command::Stats,
+            "validate" => Command::Validate,
+            "order" => Command::Order(parse_arg(
+                args.next(),
+                name,
+                "maximum number of children per node",
+            )?),
+            "keys" => match args.next() {
+                Some(key_type) => Command::Keys(Some(key_type.parse()?)),
+                None => Command::Keys(None),
+            },
+            "import" => Command::Import(parse_transfer(&mut args, name)?),
+            "export" => Command::Export(parse_transfer(&mut args, name)?),
+            "help" => Command::Help,
+            "exit" | "quit" => match args.next() {
+                Some(code) => Command::Exit(Some(parse_arg(Some(code), "exit", "exit code")?)),
+                None => Command::Exit(None),
+            },
+            _ => {
+                return Err(format!(
+                    "unknown command '{name}', type 'help' for a list of commands"
+                ))
+            }
+        };
+        // `insert` already used the rest of the line as its value
+        if let Some(extra) = args
+            .next()
+            .filter(|_| !matches!(command, Command::Insert(..)))
+        {
+            return Err(format!("{name} does not take '{extra}'"));
+        }
+        Ok(Some(command))
+    }
+}
+
+/// Parse the file name and options of `import` or `export`
+///
+/// Used for the commands of the shell, and for the subcommands of the binary.
+pub fn parse_transfer<'a, I>(args: &mut I, command: &str) -> Result<Transfer, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut transfer = Transfer {
+        path: parse_arg(args.next(), command, "file name")?,
+        format: None,
+        key: None,
+        value: None,
+        key_type: None,
+    };
+    while let Some(option) = args.next() {
+        match option {
+            "--format" => transfer.format = Some(parse_option(args.next(), command, option)?),
+            "--key" => transfer.key = Some(parse_option(args.next(), command, option)?),
+            "--value" => transfer.value = Some(parse_option(args.next(), command, option)?),
+            "--key-type" if command == "import" => {
+                transfer.key_type = Some(parse_option(args.next(), command, option)?)
+            }
+            _ => return Err(format!("{command} does not take '{option}'")),
+        }
+    }
+    Ok(transfer)
+}
+
+/// Parse the value of an option, with the error of its type
+fn parse_option<A>(arg: Option<&str>, command: &str, option: &str) -> Result<A, String>
+where
+    A: FromStr<Err = String>,
+{
+    arg.ok_or_else(|| format!("{option} of {command} is missing its value"))?
+        .parse()
+        .map_err(|e| format!("invalid {option} for {command}: {e}"))
+}
+
+fn parse_arg<A: FromStr>(arg: Option<&str>, command: &str, what: &str) -> Result<A, String> {
+    let arg = arg.ok_or_else(|| format!("{command} is missing the {what}"))?;
+    arg.parse()
+        .map_err(|_| format!("invalid {what} '{arg}' for {command}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::rust_2021::*;
+
+    use super::Command;
+    use crate::cli::KeyType;
+
+    fn parse(line: &str) -> Option<Command> {
+        Command::parse(line).unwrap()
+    }
+
+    fn error(line: &str) -> String {
+        Command::parse(line).unwrap_err()
+    }
+
+    #[test]
+    fn commands_and_their_arguments() {
+        assert_eq!(parse("get 5"), Some(Command::Get("5".to_string())));
+        assert_eq!(
+            parse("  remove   x  "),
+            Some(Command::Remove("x".to_string()))
+        );
+        assert_eq!(
+            parse("range 1 9"),
+            Some(Command::Range("1".to_string(), "9".to_string()))
+        );
+        assert_eq!(parse("len"), Some(Command::Len));
+        assert_eq!(parse("dump"), Some(Command::Dump));
+        assert_eq!(parse("stats"), Some(Command::Stats));
+        assert_eq!(parse("validate"), Some(Command::Validate));
+        assert_eq!(parse("order 7"), Some(Command::Order(7)));
+        assert_eq!(parse("keys"), Some(Command::Keys(None)));
+        assert_eq!(
+            parse("keys float"),
+            Some(Command::Keys(Some(KeyType::Float)))
+        );
+        assert_eq!(parse("help"), Some(Command::Help));
+        assert_eq!(parse("exit"), Some(Command::Exit(None)));
+        assert_eq!(parse("quit 3"), Some(Command::Exit(Some(3))));
+    }
+
+    #[test]
+    fn insert_takes_the_rest_of_the_line_as_its_value() {
+        assert_eq!(
+            parse("insert 5   hello  big world "),
+            Some(Command::Insert(
+                "5".to_string(),
+                "hello  big world".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("insert\tk\tv"),
+            Some(Command::Insert("k".to_string(), "v".to_string()))
+        );
+    }
+
+    #[test]
+    fn empty_lines_and_comments_are_not_commands() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("   \t"), None);
+        assert_eq!(parse("# insert 1 2"), None);
+        assert_eq!(parse("  # indented comment"), None);
+    }
+
+    #[test]
+    fn errors_name_the_command_and_the_argument() {
+        assert_eq!(
+            error("fetch 1"),
+            "unknown command 'fetch', type 'help' for a list of commands"
+        );
+        assert_eq!(error("insert"), "insert is missing the key");
+        assert_eq!(error("insert 5"), "insert needs a key and a value");
+        assert_eq!(error("get"), "get is missing the key");
+        assert_eq!(error("range 1"), "range is missing the end key");
+        assert_eq!(error("get 1 2"), "get does not take '2'");
+        assert_eq!(error("len now"), "len does not take 'now'");
+        assert_eq!(
+            error("order many"),
+            "invalid maximum number of children per node 'many' for order"
+        );
+        assert_eq!(
+            error("keys bytes"),
+            "unknown key type 'bytes', must be int, float or string"
+        );
+        assert_eq!(error("exit 256"), "invalid exit code '256' for exit");
+        // Commands are case sensitive
+        assert!(error("GET 1").starts_with("unknown command 'GET'"));
+    }
+}
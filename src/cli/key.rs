@@ -0,0 +1,113 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::prelude::rust_2021::*;
+use std::str::FromStr;
+
+/// A float that is ordered by `f64::total_cmp`, so it can be used as a key
+///
+/// Unlike with `f64`, every value is equal to itself and comparable with
+/// every other value: -0.0 comes before 0.0, and NaN after infinity.
+#[derive(Debug, Clone, Copy)]
+pub struct TotalF64(pub f64);
+
+impl PartialEq for TotalF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TotalF64 {}
+
+impl PartialOrd for TotalF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalF64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A key of the shell's tree
+///
+/// All keys in a tree have the same variant, chosen by its `KeyType`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    Int(i64),
+    Float(TotalF64),
+    String(String),
+}
+
+/// Without the variant, so `dump` shows the keys as they were typed
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::String(key) => write!(f, "{key:?}"),
+            key => write!(f, "{key}"),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Int(key) => write!(f, "{key}"),
+            Key::Float(key) => write!(f, "{}", key.0),
+            Key::String(key) => write!(f, "{key}"),
+        }
+    }
+}
+
+/// What kind of keys the shell's tree holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyType {
+    #[default]
+    Int,
+    Float,
+    String,
+}
+
+impl KeyType {
+    /// Parse `text` as a key of this type
+    pub fn parse(self, text: &str) -> Result<Key, String> {
+        match self {
+            KeyType::Int => text
+                .parse()
+                .map(Key::Int)
+                .map_err(|_| format!("invalid key '{text}': not a whole number")),
+            KeyType::Float => text
+                .parse()
+                .map(|key| Key::Float(TotalF64(key)))
+                .map_err(|_| format!("invalid key '{text}': not a number")),
+            KeyType::String => Ok(Key::String(text.to_string())),
+        }
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "int" => Ok(KeyType::Int),
+            "float" => Ok(KeyType::Float),
+            "string" => Ok(KeyType::String),
+            _ => Err(format!(
+                "unknown key type '{name}', must be int, float or string"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            KeyType::Int => "int",
+            KeyType::Float => "float",
+            KeyType::String => "string",
+        };
+        write!(f, "{name}")
+    }
+}
@@ -0,0 +1,489 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::prelude::rust_2021::*;
+use std::process::ExitCode;
+
+use crate::{BTree, BTreeConfig};
+
+pub use animate::{AnimateOptions, Pause};
+pub use batch::BatchOptions;
+pub use command::{Command, HELP};
+pub use key::{Key, KeyType, TotalF64};
+pub use transfer::{Column, Format, Record, Transfer, TransferOptions};
+
+mod animate;
+mod batch;
+mod command;
+mod key;
+mod transfer;
+
+/// Maximum number of children per node the shell starts with, as in `BTreeConfig::order`
+const DEFAULT_ORDER: usize = 5;
+
+/// Shell
+///
+/// Runs the commands of the shell's command language against a tree with
+/// string values, and whole number keys unless the key type is changed.
+/// Every command returns the text it shows, or an error message, so the shell
+/// can be driven without a terminal.
+pub struct Shell {
+    tree: BTree<Key, String>,
+    key_type: KeyType,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Shell::new()
+    }
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Shell {
+            tree: BTree::empty(DEFAULT_ORDER - 1),
+            key_type: KeyType::default(),
+        }
+    }
+
+    pub fn tree(&self) -> &BTree<Key, String> {
+        &self.tree
+    }
+
+    pub fn tree_mut(&mut self) -> &mut BTree<Key, String> {
+        &mut self.tree
+    }
+
+    pub fn key_type(&self) -> KeyType {
+        self.key_type
+    }
+
+    /// Run `command`, returns the text to show
+    ///
+    /// `Command::Exit` is left to the caller and shows nothing.
+    pub fn execute(&mut self, command: &Command) -> Result<String, String> {
+        let output = match command {
+            Command::Insert(key, value) => {
+                self.tree
+                    .insert(self.key_type.parse(key)?, value.clone())
+                    .map_err(|e| format!("cannot insert {key}: {e}"))?;
+                String::new()
+            }
+            Command::Get(key) => self
+                .tree
+                .get(self.key_type.parse(key)?)
+                .ok_or_else(|| format!("cannot get {key}: Key not found"))?,
+            Command::Remove(key) => self
+                .tree
+                .remove(self.key_type.parse(key)?)
+                .map_err(|e| format!("cannot remove {key}: {e}"))?,
+            Command::Range(start, end) => {
+                let range = self.key_type.parse(start)?..=self.key_type.parse(end)?;
+                let mut output = String::new();
+                for (key, value) in self.tree.range(range) {
+                    writeln!(output, "{key}: {value}").unwrap();
+                }
+                output
+            }
+            Command::Len => self.tree.len().to_string(),
+            Command::Dump => self.tree.to_string(),
+            Command::Stats => self.stats(),
+            Command::Validate => {
+                self.tree
+                    .validate()
+                    .map_err(|e| format!("tree is invalid: {e}"))?;
+                "ok".to_string()
+            }
+            Command::Order(order) => {
+                let mut tree = BTreeConfig::new()
+                    .order(*order)
+                    .build()
+                    .map_err(|e| format!("cannot use order {order}: {e}"))?;
+                tree.append(&mut self.tree);
+                self.tree = tree;
+                String::new()
+            }
+            Command::Keys(None) => self.key_type.to_string(),
+            Command::Keys(Some(key_type)) => {
+                self.set_key_type(*key_type)?;
+                String::new()
+            }
+            Command::Import(transfer) => self
+                .import(transfer)
+                .map_err(|e| format!("cannot import {}: {e}", transfer.path))?,
+            Command::Export(transfer) => {
+                let count = transfer
+                    .write(self.tree.iter())
+                    .map_err(|e| format!("cannot export {}: {e}", transfer.path))?;
+                format!("exported {count} entries to {}", transfer.path)
+            }
+            Command::Help => HELP.to_string(),
+            Command::Exit(_) => String::new(),
+        };
+        Ok(output)
+    }
+
+    fn set_key_type(&mut self, key_type: KeyType) -> Result<(), String> {
+        if key_type != self.key_type && !self.tree.is_empty() {
+            return Err(format!(
+                "cannot change the key type from {} to {key_type} while the tree has entries",
+                self.key_type
+            ));
+        }
+        self.key_type = key_type;
+        Ok(())
+    }
+
+    /// Add the records of a file, in one bulk build if they are sorted by key
+    fn import(&mut self, transfer: &Transfer) -> Result<String, String> {
+        let records = transfer.read()?;
+        let key_type = transfer.key_type.unwrap_or(self.key_type);
+        let entries = records
+            .into_iter()
+            .map(|record| {
+                let key = key_type
+                    .parse(&record.key)
+                    .map_err(|e| format!("line {}: {e}", record.line))?;
+                Ok((key, record.value))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        self.set_key_type(key_type)?;
+        let count = entries.len();
+        if entries.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+            self.tree.append_sorted(entries)?;
+        } else {
+            // Later records replace earlier ones, like `append_sorted` does
+            // with the entries already in the tree
+            for (key, value) in entries {
+                if let Some(mut old_value) = self.tree.get_mut(key.clone()) {
+                    *old_value = value;
+                    continue;
+                }
+                self.tree.insert(key, value)?;
+            }
+        }
+        Ok(format!("imported {count} entries from {}", transfer.path))
+    }
+
+    fn stats(&self) -> String {
+        let stats = self.tree.stats();
+        let mut output = String::new();
+        writeln!(output, "height: {}", stats.height).unwrap();
+        writeln!(output, "entries: {}", stats.entries).unwrap();
+        writeln!(
+            output,
+            "nodes: {} internal, {} leaf",
+            stats.internal_nodes, stats.leaf_nodes
+        )
+        .unwrap();
+        writeln!(output, "fill factor: {:.2}", stats.fill_factor).unwrap();
+        writeln!(
+            output,
+            "estimated heap bytes: {}",
+            stats.estimated_heap_bytes
+        )
+        .unwrap();
+        for (depth, level) in stats.levels.iter().enumerate() {
+            writeln!(
+                output,
+                "level {depth}: {} nodes, {} entries",
+                level.nodes, level.entries
+            )
+            .unwrap();
+        }
+        output
+    }
+}
+
+pub const USAGE: &str = "\
+usage:
+  b-tree-lib                      read commands from stdin, with a prompt on a terminal
+  b-tree-lib import FILE [OPTIONS] [export FILE [OPTIONS]]
+  b-tree-lib export FILE [OPTIONS]
+                                  like b-tree-lib, and add the entries of a file to the
+                                  tree first, or write the tree to a file at the end,
+                                  with the options of the import and export commands
+  b-tree-lib run [OPTIONS] [SCRIPT]
+                                  run the commands in SCRIPT, or stdin when it is - or missing
+options of run:
+  --expect FILE                   compare the output with FILE instead of showing it
+  --validate-each                 check the tree after every command, stop when it is invalid
+  b-tree-lib animate [OPTIONS] [SCRIPT]
+                                  like run, and show how each insert and remove changes the nodes
+options of animate:
+  --delay MS                      wait MS milliseconds after every change
+  --step                          wait for Enter after every change, needs a SCRIPT";
+
+/// Run the binary with the command line arguments `args`, without the program name
+pub fn run<I: IntoIterator<Item = String>>(args: I) -> ExitCode {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        None => interactive(),
+        Some("run") => match BatchOptions::parse(args) {
+            Ok(options) => batch::run(&options),
+            Err(e) => usage_error(&e),
+        },
+        Some("animate") => match AnimateOptions::parse(args) {
+            Ok(options) => animate::run(&options),
+            Err(e) => usage_error(&e),
+        },
+        Some(name @ ("import" | "export")) => {
+            let args = std::iter::once(name.to_string()).chain(args);
+            match TransferOptions::parse(args) {
+                Ok(options) => transfer(&options),
+                Err(e) => usage_error(&e),
+            }
+        }
+        Some("help" | "--help" | "-h") => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        Some(arg) => usage_error(&format!("unknown argument '{arg}'")),
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("error: {message}\n{USAGE}");
+    ExitCode::from(2)
+}
+
+/// Run the shell on stdin until `exit` or the end of the input
+///
+/// Shows a prompt when stdin is a terminal.
+fn interactive() -> ExitCode {
+    let stdin = io::stdin();
+    let on_terminal = stdin.is_terminal();
+    let mut shell = Shell::new();
+    repl(
+        &mut shell,
+        stdin.lock(),
+        &mut io::stdout(),
+        &mut io::stderr(),
+        on_terminal,
+    )
+}
+
+/// Like `interactive`, with an import before the commands and an export after them
+///
+/// Returns failure if the import or export failed, a failed import stops
+/// before any commands are read. Otherwise returns the exit code of the
+/// commands.
+fn transfer(options: &TransferOptions) -> ExitCode {
+    let mut shell = Shell::new();
+    if let Some(import) = &options.import {
+        match shell.execute(&Command::Import(import.clone())) {
+            Ok(output) => println!("{output}"),
+            Err(e) => {
+                eprintln!("error: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let stdin = io::stdin();
+    let on_terminal = stdin.is_terminal();
+    let code = repl(
+        &mut shell,
+        stdin.lock(),
+        &mut io::stdout(),
+        &mut io::stderr(),
+        on_terminal,
+    );
+    if let Some(export) = &options.export {
+        match shell.execute(&Command::Export(export.clone())) {
+            Ok(output) => println!("{output}"),
+            Err(e) => {
+                eprintln!("error: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    code
+}
+
+/// Run `shell` on the lines of `input` until `exit` or the end of the input
+///
+/// Results go to `output` and errors to `errors`, with `prompt` a `> ` is
+/// written before every line is read. Returns the exit code passed to `exit`,
+/// otherwise failure if the last command failed or the output could not be
+/// written.
+fn repl<R: BufRead, W: Write, E: Write>(
+    shell: &mut Shell,
+    input: R,
+    output: &mut W,
+    errors: &mut E,
+    prompt: bool,
+) -> ExitCode {
+    let mut last_failed = false;
+    let mut lines = input.lines();
+    loop {
+        if prompt {
+            // A prompt that is not shown only makes the shell harder to use
+            write!(output, "> ").ok();
+            output.flush().ok();
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                writeln!(errors, "error: cannot read input: {e}").ok();
+                return ExitCode::FAILURE;
+            }
+            None => break,
+        };
+        let result = match Command::parse(&line) {
+            Ok(Some(Command::Exit(Some(code)))) => return ExitCode::from(code),
+            Ok(Some(Command::Exit(None))) => break,
+            Ok(Some(command)) => shell.execute(&command),
+            Ok(None) => continue,
+            Err(e) => Err(e),
+        };
+        let written = match result {
+            Ok(text) => {
+                last_failed = false;
+                if text.is_empty() {
+                    Ok(())
+                } else {
+                    writeln!(output, "{}", text.trim_end())
+                }
+            }
+            Err(e) => {
+                last_failed = true;
+                writeln!(errors, "error: {e}")
+            }
+        };
+        if written.is_err() {
+            return ExitCode::FAILURE;
+        }
+    }
+    if last_failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::rust_2021::*;
+    use std::process::ExitCode;
+
+    use super::{repl, Command, Shell};
+
+    fn execute(shell: &mut Shell, line: &str) -> Result<String, String> {
+        shell.execute(&Command::parse(line).unwrap().unwrap())
+    }
+
+    /// Run `input` through the shell, returns the exit code, output and errors
+    fn run_repl(input: &str) -> (ExitCode, String, String) {
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let code = repl(
+            &mut Shell::new(),
+            input.as_bytes(),
+            &mut output,
+            &mut errors,
+            false,
+        );
+        (
+            code,
+            String::from_utf8(output).unwrap(),
+            String::from_utf8(errors).unwrap(),
+        )
+    }
+
+    #[test]
+    fn shell_runs_commands_against_its_tree() {
+        let mut shell = Shell::new();
+        for key in [5, 1, 9, 3] {
+            assert_eq!(
+                execute(&mut shell, &format!("insert {key} v{key}")),
+                Ok(String::new())
+            );
+        }
+        assert_eq!(execute(&mut shell, "get 9"), Ok("v9".to_string()));
+        assert_eq!(execute(&mut shell, "len"), Ok("4".to_string()));
+        assert_eq!(
+            execute(&mut shell, "range 2 5"),
+            Ok("3: v3\n5: v5\n".to_string())
+        );
+        assert_eq!(execute(&mut shell, "remove 1"), Ok("v1".to_string()));
+        assert_eq!(execute(&mut shell, "validate"), Ok("ok".to_string()));
+        assert_eq!(execute(&mut shell, "order 3"), Ok(String::new()));
+        assert_eq!(shell.tree().max_keys_per_node(), 2);
+        assert_eq!(execute(&mut shell, "len"), Ok("3".to_string()));
+        assert!(execute(&mut shell, "stats")
+            .unwrap()
+            .starts_with("height: "));
+        assert_eq!(execute(&mut shell, "keys"), Ok("int".to_string()));
+    }
+
+    #[test]
+    fn errors_come_from_the_library_and_the_key_type() {
+        let mut shell = Shell::new();
+        execute(&mut shell, "insert 1 one").unwrap();
+        assert_eq!(
+            execute(&mut shell, "insert 1 uno"),
+            Err("cannot insert 1: Key already exists".to_string())
+        );
+        assert_eq!(
+            execute(&mut shell, "get 2"),
+            Err("cannot get 2: Key not found".to_string())
+        );
+        assert_eq!(
+            execute(&mut shell, "remove 2"),
+            Err("cannot remove 2: Key not found".to_string())
+        );
+        assert_eq!(
+            execute(&mut shell, "get two"),
+            Err("invalid key 'two': not a whole number".to_string())
+        );
+        assert!(execute(&mut shell, "order 2")
+            .unwrap_err()
+            .starts_with("cannot use order 2: "));
+        assert_eq!(
+            execute(&mut shell, "keys string"),
+            Err(
+                "cannot change the key type from int to string while the tree has entries"
+                    .to_string()
+            )
+        );
+        execute(&mut shell, "remove 1").unwrap();
+        execute(&mut shell, "keys string").unwrap();
+        assert_eq!(execute(&mut shell, "insert two 2"), Ok(String::new()));
+    }
+
+    #[test]
+    fn repl_shows_output_and_errors_separately() {
+        let (code, output, errors) = run_repl("insert 1 one\n\n# comment\nget 1\nget 2\nlen\n");
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(output, "one\n1\n");
+        assert_eq!(errors, "error: cannot get 2: Key not found\n");
+    }
+
+    #[test]
+    fn repl_exit_codes() {
+        // The last command decides, an earlier failure is forgiven
+        assert_eq!(run_repl("get 1\nlen\n").0, ExitCode::SUCCESS);
+        assert_eq!(run_repl("len\nget 1\n").0, ExitCode::FAILURE);
+        assert_eq!(run_repl("bogus").0, ExitCode::FAILURE);
+        assert_eq!(run_repl("").0, ExitCode::SUCCESS);
+        // `exit` stops reading, with its own code
+        let (code, output, _) = run_repl("insert 1 one\nexit 7\nget 1\n");
+        assert_eq!(code, ExitCode::from(7));
+        assert_eq!(output, "");
+        assert_eq!(run_repl("get 1\nexit\n").0, ExitCode::FAILURE);
+        assert_eq!(run_repl("quit 0\n").0, ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn repl_prompts_before_every_line() {
+        let mut output = Vec::new();
+        let code = repl(
+            &mut Shell::new(),
+            "len\n".as_bytes(),
+            &mut output,
+            &mut Vec::new(),
+            true,
+        );
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(output).unwrap(), "> 0\n> ");
+    }
+}
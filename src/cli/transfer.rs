@@ -0,0 +1,875 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::prelude::rust_2021::*;
+use std::str::FromStr;
+
+use super::command::parse_transfer;
+use super::key::{Key, KeyType};
+
+/// File format of `import` and `export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Comma separated values, with a header row naming the columns
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+impl Format {
+    /// The format a file name suggests, CSV unless it ends in `.jsonl` or `.ndjson`
+    fn from_path(path: &str) -> Self {
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("jsonl" | "ndjson") => Format::JsonLines,
+            _ => Format::Csv,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "csv" => Ok(Format::Csv),
+            "jsonl" | "ndjson" => Ok(Format::JsonLines),
+            _ => Err(format!("unknown format '{name}', must be csv or jsonl")),
+        }
+    }
+}
+
+/// A column of a CSV file, or a field of a JSON object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Name(String),
+    /// Counted from 1, in the order of the header or of the object's fields
+    Position(usize),
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text.parse::<usize>() {
+            Ok(0) => Err("column numbers start at 1".to_string()),
+            Ok(position) => Ok(Column::Position(position)),
+            Err(_) => Ok(Column::Name(text.to_string())),
+        }
+    }
+}
+
+/// The file and columns of an `import` or `export`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    pub path: String,
+    /// Guessed from the file name when not given
+    pub format: Option<Format>,
+    /// The first column when not given, or `key` when exporting
+    pub key: Option<Column>,
+    /// The second column when not given, or `value` when exporting
+    pub value: Option<Column>,
+    /// Only for `import`, the key type of the shell when not given
+    pub key_type: Option<KeyType>,
+}
+
+/// Settings of `b-tree-lib import` and `b-tree-lib export`
+///
+/// Both subcommands can be given at once, to convert a file or to load and
+/// save the tree around the commands read from stdin.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransferOptions {
+    /// File to add to the tree before the commands run
+    pub import: Option<Transfer>,
+    /// File to write the tree to after the commands ran
+    pub export: Option<Transfer>,
+}
+
+impl TransferOptions {
+    /// Parse the arguments from the first `import` or `export` on
+    ///
+    /// Each subcommand takes the arguments up to the next one, with the same
+    /// options as the shell command of the same name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let args: Vec<String> = args.into_iter().collect();
+        let mut options = TransferOptions::default();
+        let mut rest = &args[..];
+        while let Some((name, tail)) = rest.split_first() {
+            let end = tail
+                .iter()
+                .position(|arg| arg == "import" || arg == "export")
+                .unwrap_or(tail.len());
+            let transfer = parse_transfer(&mut tail[..end].iter().map(String::as_str), name)?;
+            let slot = match name.as_str() {
+                "import" => &mut options.import,
+                "export" => &mut options.export,
+                _ => return Err(format!("unknown argument '{name}'")),
+            };
+            if slot.is_some() {
+                return Err(format!("{name} can only be given once"));
+            }
+            *slot = Some(transfer);
+            rest = &tail[end..];
+        }
+        Ok(options)
+    }
+}
+
+/// A key and value read from a file, still as text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Line of the file the record starts on
+    pub line: usize,
+    pub key: String,
+    pub value: String,
+}
+
+impl Transfer {
+    fn format(&self) -> Format {
+        self.format.unwrap_or_else(|| Format::from_path(&self.path))
+    }
+
+    /// Read the key and value columns of every record in the file
+    pub fn read(&self) -> Result<Vec<Record>, String> {
+        let text = fs::read_to_string(&self.path).map_err(|e| e.to_string())?;
+        let key = self.key.clone().unwrap_or(Column::Position(1));
+        let value = self.value.clone().unwrap_or(Column::Position(2));
+        match self.format() {
+            Format::Csv => read_csv(&text, &key, &value),
+            Format::JsonLines => read_json_lines(&text, &key, &value),
+        }
+    }
+
+    /// Write the entries to the file, returns how many were written
+    pub fn write<I: Iterator<Item = (Key, String)>>(&self, entries: I) -> Result<usize, String> {
+        let name = |column: &Option<Column>, default: &str| match column {
+            Some(Column::Name(name)) => Ok(name.clone()),
+            Some(Column::Position(_)) => Err("export needs column names, not numbers".to_string()),
+            None => Ok(default.to_string()),
+        };
+        let key_name = name(&self.key, "key")?;
+        let value_name = name(&self.value, "value")?;
+        let mut text = String::new();
+        let mut count = 0;
+        match self.format() {
+            Format::Csv => {
+                writeln!(text, "{},{}", csv_field(&key_name), csv_field(&value_name)).unwrap();
+                for (key, value) in entries {
+                    writeln!(
+                        text,
+                        "{},{}",
+                        csv_field(&key.to_string()),
+                        csv_field(&value)
+                    )
+                    .unwrap();
+                    count += 1;
+                }
+            }
+            Format::JsonLines => {
+                for (key, value) in entries {
+                    writeln!(
+                        text,
+                        "{{{}:{},{}:{}}}",
+                        json_string(&key_name),
+                        json_key(&key),
+                        json_string(&value_name),
+                        json_string(&value)
+                    )
+                    .unwrap();
+                    count += 1;
+                }
+            }
+        }
+        fs::write(&self.path, text).map_err(|e| e.to_string())?;
+        Ok(count)
+    }
+}
+
+/// Split CSV text into records, each with the line it starts on
+///
+/// Fields in double quotes can hold commas, line breaks and doubled quotes.
+/// Empty lines are skipped.
+fn parse_csv(text: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = text.chars().peekable();
+    // Whether the current field was quoted, so an empty line is not a record
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() && !quoted => {
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => {
+                            return Err(format!("line {record_line}: quoted field is not closed"))
+                        }
+                    }
+                }
+                if !matches!(chars.peek(), None | Some(',' | '\n' | '\r')) {
+                    return Err(format!(
+                        "line {line}: unexpected text after a closing quote"
+                    ));
+                }
+            }
+            ',' => {
+                record.push(std::mem::take(&mut field));
+                quoted = false;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if !record.is_empty() || !field.is_empty() || quoted {
+                    record.push(std::mem::take(&mut field));
+                    records.push((record_line, std::mem::take(&mut record)));
+                }
+                quoted = false;
+                line += 1;
+                record_line = line;
+            }
+            c => field.push(c),
+        }
+    }
+    if !record.is_empty() || !field.is_empty() || quoted {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    Ok(records)
+}
+
+fn read_csv(text: &str, key: &Column, value: &Column) -> Result<Vec<Record>, String> {
+    let mut records = parse_csv(text)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Ok(Vec::new());
+    };
+    let index = |column: &Column| match column {
+        Column::Name(name) => header
+            .iter()
+            .position(|header_name| header_name == name)
+            .ok_or_else(|| format!("no column named '{name}' in the header")),
+        Column::Position(position) => Ok(position - 1),
+    };
+    let key_index = index(key)?;
+    let value_index = index(value)?;
+    records
+        .map(|(line, fields)| {
+            let field = |i: usize| {
+                fields
+                    .get(i)
+                    .cloned()
+                    .ok_or_else(|| format!("line {line}: missing column {}", i + 1))
+            };
+            Ok(Record {
+                line,
+                key: field(key_index)?,
+                value: field(value_index)?,
+            })
+        })
+        .collect()
+}
+
+fn read_json_lines(text: &str, key: &Column, value: &Column) -> Result<Vec<Record>, String> {
+    let mut records = Vec::new();
+    for (i, line_text) in text.lines().enumerate() {
+        let line = i + 1;
+        if line_text.trim().is_empty() {
+            continue;
+        }
+        let fields = JsonParser::new(line_text)
+            .parse_line()
+            .map_err(|e| format!("line {line}: {e}"))?;
+        let field = |column: &Column| {
+            let found = match column {
+                Column::Name(name) => fields.iter().find(|(field_name, _)| field_name == name),
+                Column::Position(position) => fields.get(position - 1),
+            };
+            match (found, column) {
+                (Some((_, text)), _) => Ok(text.clone()),
+                (None, Column::Name(name)) => Err(format!("line {line}: missing field '{name}'")),
+                (None, Column::Position(position)) => {
+                    Err(format!("line {line}: missing field {position}"))
+                }
+            }
+        };
+        records.push(Record {
+            line,
+            key: field(key)?,
+            value: field(value)?,
+        });
+    }
+    Ok(records)
+}
+
+/// A CSV field, quoted when it would otherwise be read back differently
+fn csv_field(text: &str) -> String {
+    let needs_quotes =
+        text.is_empty() || text.contains([',', '"', '\n', '\r']) || text.trim() != text;
+    if needs_quotes {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// A JSON number for numeric keys, a JSON string otherwise
+fn json_key(key: &Key) -> String {
+    match key {
+        Key::Int(key) => key.to_string(),
+        // JSON has no numbers for NaN and the infinities
+        Key::Float(key) if key.0.is_finite() => key.0.to_string(),
+        key => json_string(&key.to_string()),
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Reads the fields of a JSON object on a single line
+///
+/// String fields are unescaped, any other field is kept as its JSON text.
+struct JsonParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn new(text: &'a str) -> Self {
+        JsonParser { text, position: 0 }
+    }
+
+    fn parse_line(&mut self) -> Result<Vec<(String, String)>, String> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Err("expected a JSON object".to_string());
+        }
+        let mut fields = Vec::new();
+        self.position += 1;
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                let name = self.parse_string()?;
+                self.skip_whitespace();
+                self.expect(':')?;
+                self.skip_whitespace();
+                let value = if self.peek() == Some('"') {
+                    self.parse_string()?
+                } else {
+                    let start = self.position;
+                    self.skip_value()?;
+                    self.text[start..self.position].to_string()
+                };
+                fields.push((name, value));
+                self.skip_whitespace();
+                match self.next() {
+                    Some(',') => continue,
+                    Some('}') => break,
+                    _ => return Err("expected ',' or '}' in object".to_string()),
+                }
+            }
+        }
+        self.skip_whitespace();
+        if self.position < self.text.len() {
+            return Err("unexpected text after the object".to_string());
+        }
+        Ok(fields)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(format!("expected '{expected}'")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| matches!(c, ' ' | '\t' | '\r' | '\n'))
+        {
+            self.position += 1;
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('/') => string.push('/'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => string.push(self.parse_unicode_escape()?),
+                    _ => return Err("invalid escape in string".to_string()),
+                },
+                Some(c) => string.push(c),
+                None => return Err("string is not closed".to_string()),
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, which takes two escapes outside the
+    /// basic multilingual plane
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.text[self.position..].starts_with("\\u") {
+                return Err("unpaired surrogate in string".to_string());
+            }
+            self.position += 2;
+            let low = self.parse_hex()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err("unpaired surrogate in string".to_string());
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| "invalid character in string".to_string())
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .ok_or_else(|| "invalid escape in string".to_string())?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| "invalid escape in string")?;
+        self.position += 4;
+        Ok(code)
+    }
+
+    /// Move past a number, literal, array or object
+    fn skip_value(&mut self) -> Result<(), String> {
+        match self.peek() {
+            Some('"') => self.parse_string().map(|_| ()),
+            Some(open @ ('{' | '[')) => {
+                let close = if open == '{' { '}' } else { ']' };
+                self.position += 1;
+                self.skip_whitespace();
+                if self.peek() == Some(close) {
+                    self.position += 1;
+                    return Ok(());
+                }
+                loop {
+                    self.skip_whitespace();
+                    if open == '{' {
+                        self.parse_string()?;
+                        self.skip_whitespace();
+                        self.expect(':')?;
+                        self.skip_whitespace();
+                    }
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    match self.next() {
+                        Some(',') => continue,
+                        Some(c) if c == close => return Ok(()),
+                        _ => return Err(format!("expected ',' or '{close}'")),
+                    }
+                }
+            }
+            _ => {
+                let start = self.position;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))
+                {
+                    self.position += 1;
+                }
+                let literal = &self.text[start..self.position];
+                let is_number = literal.parse::<f64>().is_ok()
+                    && literal.starts_with(|c: char| c == '-' || c.is_ascii_digit());
+                if is_number || matches!(literal, "true" | "false" | "null") {
+                    Ok(())
+                } else {
+                    Err("expected a JSON value".to_string())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::prelude::rust_2021::*;
+
+    use super::{
+        csv_field, json_string, parse_csv, read_csv, read_json_lines, Column, Format, JsonParser,
+        Record, Transfer, TransferOptions,
+    };
+    use crate::cli::{Command, Key, KeyType, Shell, TotalF64};
+    use crate::BTree;
+
+    fn fields(records: &[&str]) -> Vec<String> {
+        records.iter().map(|field| field.to_string()).collect()
+    }
+
+    fn record(line: usize, key: &str, value: &str) -> Record {
+        Record {
+            line,
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn json_fields(line: &str) -> Result<Vec<(String, String)>, String> {
+        JsonParser::new(line).parse_line()
+    }
+
+    /// Write `contents` to a file only this test uses, returns its path
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!("b-tree-lib-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn csv_quoted_fields() {
+        let text = "a,\"b,c\",\"say \"\"hi\"\"\"\n\"\",x,\n";
+        assert_eq!(
+            parse_csv(text),
+            Ok(Vec::from([
+                (1, fields(&["a", "b,c", "say \"hi\""])),
+                (2, fields(&["", "x", ""])),
+            ]))
+        );
+    }
+
+    #[test]
+    fn csv_fields_across_lines_keep_the_line_they_start_on() {
+        let text = "k,v\r\n1,\"first\nsecond\"\r\n\n2,two";
+        assert_eq!(
+            parse_csv(text),
+            Ok(Vec::from([
+                (1, fields(&["k", "v"])),
+                (2, fields(&["1", "first\nsecond"])),
+                (5, fields(&["2", "two"])),
+            ]))
+        );
+        // A quoted empty field is a record, an empty line is not
+        assert_eq!(parse_csv("\"\"\n\n"), Ok(Vec::from([(1, fields(&[""]))])));
+    }
+
+    #[test]
+    fn csv_errors() {
+        assert_eq!(
+            parse_csv("k,v\n1,\"open\nstill open"),
+            Err("line 2: quoted field is not closed".to_string())
+        );
+        assert_eq!(
+            parse_csv("\"quoted\"text,1"),
+            Err("line 1: unexpected text after a closing quote".to_string())
+        );
+    }
+
+    #[test]
+    fn csv_columns_by_name_or_position() {
+        let text = "id,name,score\n1,one,10\n2,two,20\n";
+        let name = Column::Name("score".to_string());
+        assert_eq!(
+            read_csv(text, &Column::Position(2), &name),
+            Ok(Vec::from([record(2, "one", "10"), record(3, "two", "20")]))
+        );
+        assert_eq!(
+            read_csv(text, &Column::Name("missing".to_string()), &name),
+            Err("no column named 'missing' in the header".to_string())
+        );
+        assert_eq!(
+            read_csv("a,b\n1\n", &Column::Position(1), &Column::Position(2)),
+            Err("line 2: missing column 2".to_string())
+        );
+        assert_eq!(
+            read_csv("", &Column::Position(1), &Column::Position(2)),
+            Ok(Vec::new())
+        );
+    }
+
+    #[test]
+    fn json_escapes_and_surrogate_pairs() {
+        assert_eq!(
+            json_fields(r#"{"k": "a\"b\\c\/d\n\t\r\b\f", "e": "é😀"}"#),
+            Ok(Vec::from([
+                ("k".to_string(), "a\"b\\c/d\n\t\r\u{8}\u{c}".to_string()),
+                ("e".to_string(), "\u{e9}\u{1F600}".to_string()),
+            ]))
+        );
+        for (line, error) in [
+            (r#"{"k": "\ud83d"}"#, "unpaired surrogate in string"),
+            (r#"{"k": "\ud83dA"}"#, "unpaired surrogate in string"),
+            (r#"{"k": "\udc00"}"#, "invalid character in string"),
+            (r#"{"k": "\u12"}"#, "invalid escape in string"),
+            (r#"{"k": "\x"}"#, "invalid escape in string"),
+            (r#"{"k": "open}"#, "string is not closed"),
+        ] {
+            assert_eq!(json_fields(line), Err(error.to_string()), "{line}");
+        }
+    }
+
+    #[test]
+    fn json_values_that_are_not_strings_are_kept_as_text() {
+        assert_eq!(
+            json_fields(
+                r#" { "n": -1.5e3, "t": true, "z": null, "a": [1, {"x": "]"}], "o": {} } "#
+            ),
+            Ok(Vec::from([
+                ("n".to_string(), "-1.5e3".to_string()),
+                ("t".to_string(), "true".to_string()),
+                ("z".to_string(), "null".to_string()),
+                ("a".to_string(), r#"[1, {"x": "]"}]"#.to_string()),
+                ("o".to_string(), "{}".to_string()),
+            ]))
+        );
+        assert_eq!(json_fields("{}"), Ok(Vec::new()));
+        for (line, error) in [
+            ("[1]", "expected a JSON object"),
+            (r#"{"k": nope}"#, "expected a JSON value"),
+            (r#"{"k": 1} extra"#, "unexpected text after the object"),
+            (r#"{"k" 1}"#, "expected ':'"),
+            (r#"{"k": 1,}"#, "expected '\"'"),
+            (r#"{"k": 1"#, "expected ',' or '}' in object"),
+        ] {
+            assert_eq!(json_fields(line), Err(error.to_string()), "{line}");
+        }
+    }
+
+    #[test]
+    fn json_lines_fields_by_name_or_position() {
+        let text = "{\"id\": 7, \"name\": \"seven\"}\n\n{\"name\": \"eight\", \"id\": 8}\n";
+        let id = Column::Name("id".to_string());
+        let name = Column::Name("name".to_string());
+        assert_eq!(
+            read_json_lines(text, &id, &name),
+            Ok(Vec::from([
+                record(1, "7", "seven"),
+                record(3, "8", "eight")
+            ]))
+        );
+        // Positions follow the order of the fields on each line
+        assert_eq!(
+            read_json_lines(text, &Column::Position(1), &Column::Position(2)),
+            Ok(Vec::from([
+                record(1, "7", "seven"),
+                record(3, "eight", "8")
+            ]))
+        );
+        assert_eq!(
+            read_json_lines("{\"id\": 1}", &id, &name),
+            Err("line 1: missing field 'name'".to_string())
+        );
+        assert_eq!(
+            read_json_lines("{\"id\": 1}", &id, &Column::Position(3)),
+            Err("line 1: missing field 3".to_string())
+        );
+    }
+
+    #[test]
+    fn written_fields_read_back_the_same() {
+        for text in ["plain", "", " padded ", "a,b", "say \"hi\"", "two\nlines"] {
+            let line = format!("{},x\n", csv_field(text));
+            assert_eq!(parse_csv(&line).unwrap()[0].1[0], text);
+            let json = format!("{{\"k\": {}}}", json_string(text));
+            assert_eq!(json_fields(&json).unwrap()[0].1, text);
+        }
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(json_string("\u{1}\t"), "\"\\u0001\\t\"");
+    }
+
+    #[test]
+    fn formats_and_columns() {
+        assert_eq!(Format::from_path("data.jsonl"), Format::JsonLines);
+        assert_eq!(
+            Format::from_path("dir.jsonl/data.ndjson"),
+            Format::JsonLines
+        );
+        assert_eq!(Format::from_path("data.json"), Format::Csv);
+        assert_eq!(Format::from_path("data"), Format::Csv);
+        assert_eq!("ndjson".parse(), Ok(Format::JsonLines));
+        assert_eq!(
+            "xml".parse::<Format>(),
+            Err("unknown format 'xml', must be csv or jsonl".to_string())
+        );
+        assert_eq!("2".parse(), Ok(Column::Position(2)));
+        assert_eq!("name".parse(), Ok(Column::Name("name".to_string())));
+        assert_eq!(
+            "0".parse::<Column>(),
+            Err("column numbers start at 1".to_string())
+        );
+    }
+
+    #[test]
+    fn options_of_the_import_and_export_subcommands() {
+        let parse = |args: &[&str]| TransferOptions::parse(args.iter().map(|arg| arg.to_string()));
+        let options = parse(&["import", "in.csv", "--key", "id", "export", "out.jsonl"]).unwrap();
+        assert_eq!(
+            options.import,
+            Some(Transfer {
+                path: "in.csv".to_string(),
+                format: None,
+                key: Some(Column::Name("id".to_string())),
+                value: None,
+                key_type: None,
+            })
+        );
+        assert_eq!(options.export.unwrap().path, "out.jsonl");
+        let options = parse(&["export", "out.csv", "--format", "jsonl"]).unwrap();
+        assert_eq!(options.import, None);
+        assert_eq!(options.export.unwrap().format, Some(Format::JsonLines));
+        assert_eq!(
+            parse(&["import"]),
+            Err("import is missing the file name".to_string())
+        );
+        assert_eq!(
+            parse(&["export", "a.csv", "--key-type", "int"]),
+            Err("export does not take '--key-type'".to_string())
+        );
+        assert_eq!(
+            parse(&["import", "a.csv", "import", "b.csv"]),
+            Err("import can only be given once".to_string())
+        );
+    }
+
+    #[test]
+    fn float_keys_are_imported_in_total_order() {
+        let path = temp_file(
+            "floats.csv",
+            "key,value\nNaN,nan\n1e3,big\n-0.0,minus zero\n0,zero\n-inf,low\n",
+        );
+        let mut shell = Shell::new();
+        let import = Command::parse(&format!("import {path} --key-type float"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            shell.execute(&import),
+            Ok(format!("imported 5 entries from {path}"))
+        );
+        assert_eq!(shell.key_type(), KeyType::Float);
+        let keys: Vec<_> = shell
+            .tree()
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .collect();
+        assert_eq!(keys, ["-inf", "-0", "0", "1000", "NaN"]);
+        let zero = shell.tree().get(Key::Float(TotalF64(0.0)));
+        assert_eq!(zero, Some("zero".to_string()));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sorted_files_are_built_in_bulk() {
+        let text: String = (1..=40)
+            .map(|key| format!("{{\"k\": {key}, \"v\": \"{key}\"}}\n"))
+            .collect();
+        let path = temp_file("sorted.jsonl", &text);
+        let mut shell = Shell::new();
+        shell
+            .execute(
+                &Command::parse(&format!("import {path} --key k --value v"))
+                    .unwrap()
+                    .unwrap(),
+            )
+            .unwrap();
+        // `append_sorted` packs the nodes bottom-up, inserts leave them half full
+        let entries: Vec<_> = (1..=40)
+            .map(|key| (Key::Int(key), key.to_string()))
+            .collect();
+        let mut bulk: BTree<Key, String> = BTree::empty(shell.tree().max_keys_per_node());
+        bulk.append_sorted(entries.clone()).unwrap();
+        let mut inserted: BTree<Key, String> = BTree::empty(shell.tree().max_keys_per_node());
+        for (key, value) in entries {
+            inserted.insert(key, value).unwrap();
+        }
+        assert_eq!(shell.tree().levels(), bulk.levels());
+        assert_ne!(shell.tree().levels(), inserted.levels());
+
+        // A file that is not sorted is inserted entry by entry, later entries win
+        let unsorted = temp_file("unsorted.csv", "k,v\n5,a\n2,b\n5,c\n");
+        let import = Command::parse(&format!("import {unsorted}"))
+            .unwrap()
+            .unwrap();
+        shell.execute(&import).unwrap();
+        assert_eq!(shell.tree().get(Key::Int(5)), Some("c".to_string()));
+        assert_eq!(shell.tree().len(), 40);
+        shell.tree().validate().unwrap();
+        for path in [path, unsorted] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn export_and_import_round_trip() {
+        let mut shell = Shell::new();
+        for line in [
+            "keys string",
+            "insert b two, with comma",
+            "insert a \"one\"",
+        ] {
+            shell
+                .execute(&Command::parse(line).unwrap().unwrap())
+                .unwrap();
+        }
+        for name in ["round.csv", "round.jsonl"] {
+            let path = temp_file(name, "");
+            let export = Command::parse(&format!("export {path}")).unwrap().unwrap();
+            assert_eq!(
+                shell.execute(&export),
+                Ok(format!("exported 2 entries to {path}"))
+            );
+            let mut copy = Shell::new();
+            let import = Command::parse(&format!("import {path} --key-type string"))
+                .unwrap()
+                .unwrap();
+            copy.execute(&import).unwrap();
+            assert_eq!(copy.tree(), shell.tree());
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
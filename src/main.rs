@@ -1,4 +1,7 @@
-use b_tree_lib::run;
-fn main() {
-    let res = run();
+use std::process::ExitCode;
+
+use b_tree_lib::cli;
+
+fn main() -> ExitCode {
+    cli::run(std::env::args().skip(1))
 }
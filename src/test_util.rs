@@ -0,0 +1,89 @@
+//! Helpers shared by the unit tests
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::{BTree, BTreeConfig, InsertionMode, OverflowPolicy};
+
+/// Xorshift generator, so every run of a test sees the same operations
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift never leaves 0
+        Rng(seed | 1)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number from 0 up to, but not including, `bound`
+    pub fn below(&mut self, bound: u32) -> u32 {
+        (self.next() % u64::from(bound)) as u32
+    }
+}
+
+/// Every order from 3 to 9, with every overflow policy and insertion mode
+///
+/// Each order is used with the default minimum number of keys per node, and
+/// with a minimum of 1 where that is lower. Top-down insertion is skipped for
+/// order 3, which `BTreeConfig::build` rejects.
+pub fn configs() -> Vec<BTreeConfig> {
+    let mut configs = Vec::new();
+    for order in 3..=9 {
+        let mut mins = Vec::from([(order - 1) / 2]);
+        if mins[0] > 1 {
+            mins.push(1);
+        }
+        for min_keys_per_node in mins {
+            for overflow_policy in [OverflowPolicy::Split, OverflowPolicy::Redistribute] {
+                for insertion_mode in [InsertionMode::BottomUp, InsertionMode::TopDown] {
+                    if insertion_mode == InsertionMode::TopDown && order < 4 {
+                        continue;
+                    }
+                    configs.push(
+                        BTreeConfig::new()
+                            .order(order)
+                            .min_keys_per_node(min_keys_per_node)
+                            .overflow_policy(overflow_policy)
+                            .insertion_mode(insertion_mode),
+                    );
+                }
+            }
+        }
+    }
+    configs
+}
+
+/// A tree built from `config`, holding every entry of `map`
+pub fn tree_from(config: &BTreeConfig, map: &BTreeMap<u32, u32>) -> BTree<u32, u32> {
+    let mut tree = config.build().unwrap();
+    for (key, value) in map {
+        tree.insert(*key, *value).unwrap();
+    }
+    tree
+}
+
+/// A map with `len` random keys below `bound`, each mapped to its own double
+pub fn random_map(rng: &mut Rng, len: usize, bound: u32) -> BTreeMap<u32, u32> {
+    let mut map = BTreeMap::new();
+    while map.len() < len {
+        let key = rng.below(bound);
+        map.insert(key, key * 2);
+    }
+    map
+}
+
+/// Panic unless `tree` is valid and holds exactly the entries of `map`
+pub fn assert_matches(tree: &BTree<u32, u32>, map: &BTreeMap<u32, u32>) {
+    tree.validate()
+        .unwrap_or_else(|e| panic!("{e} in {tree:?}"));
+    assert_eq!(tree.len(), map.len());
+    let entries: Vec<_> = tree.iter().collect();
+    let expected: Vec<_> = map.iter().map(|(key, value)| (*key, *value)).collect();
+    assert_eq!(entries, expected);
+}